@@ -0,0 +1,574 @@
+//! Label- and Property-Level Access Control
+//!
+//! A graph's `[graphs.<name>.access]` policy hides labels (node labels and relationship types) and
+//! properties from every caller except the API keys it exempts. Hidden elements are removed from
+//! the schema before it reaches the model; generated queries that reference them are rejected, or
+//! have their property reads replaced with `null` when the policy rewrites; and nodes and
+//! relationships in query results are stripped of hidden labels and properties before they are
+//! formatted.
+//!
+//! Queries are checked with patterns rather than a Cypher parser. Anything the patterns cannot
+//! attribute to a label, such as a property of a variable whose label is not written in the query,
+//! is treated as if it could belong to every label. With hidden labels, that includes the hidden
+//! ones, so such properties cannot be read at all.
+
+use crate::dialect::STRING_LITERAL;
+use crate::graph::Records;
+use crate::schema::discovery::Schema;
+use falkordb::{Edge, FalkorValue, Node};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Relationship type shown in results in place of a hidden one
+pub const REDACTED_TYPE: &str = "REDACTED";
+
+const IDENTIFIER: &str = r"`[^`]+`|[A-Za-z_]\w*";
+
+static MAP_KEY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"([{{,]\s*)({IDENTIFIER})\s*:")).expect("Pattern should be valid"));
+static LABEL_EXPRESSION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?:({IDENTIFIER})\s*)?:\s*((?:{IDENTIFIER})(?:\s*[|&:]\s*:?\s*(?:{IDENTIFIER}))*)"
+    ))
+    .expect("Pattern should be valid")
+});
+static LABEL_SEPARATOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*[|&:]\s*:?\s*").expect("Pattern should be valid"));
+static PROPERTY_ACCESS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"({IDENTIFIER})\s*\.\s*({IDENTIFIER})(\s*\()?")).expect("Pattern should be valid")
+});
+static DYNAMIC_ACCESS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r#"({IDENTIFIER})\[\s*(['"])"#)).expect("Pattern should be valid"));
+static MAP_PROJECTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"({IDENTIFIER})\s*\{{([^{{}}]*)\}}")).expect("Pattern should be valid"));
+static PROJECTED_PROPERTY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"(?:^|,)\s*\.\s*({IDENTIFIER}|\*)")).expect("Pattern should be valid"));
+static INTROSPECTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(labels|type|keys|properties|db\.labels|db\.relationshiptypes|db\.propertykeys|db\.schema\.\w+)\s*\(",
+    )
+    .expect("Pattern should be valid")
+});
+
+/// What to do with a generated query that references hidden labels or properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ViolationPolicy {
+    /// Refuse to run the query
+    #[default]
+    Reject,
+    /// Replace reads of hidden properties with `null`; references that cannot be rewritten, such
+    /// as hidden labels, are still rejected
+    Rewrite,
+}
+
+/// Labels and properties of a graph that callers may not see
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessPolicy {
+    /// Node labels and relationship types to hide
+    #[serde(default)]
+    pub hidden_labels: Vec<String>,
+    /// Properties to hide, either `property` on every label or `Label.property`
+    #[serde(default)]
+    pub hidden_properties: Vec<String>,
+    #[serde(default)]
+    pub on_violation: ViolationPolicy,
+    /// Names of the API keys the policy does not apply to
+    #[serde(default)]
+    pub exempt_keys: Vec<String>,
+}
+
+/// A generated query after the policy was applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enforced {
+    /// The query to run
+    pub query: String,
+    /// The property reads replaced with `null`, e.g. `p.ssn`
+    pub rewritten: Vec<String>,
+}
+
+impl AccessPolicy {
+    /// Whether the policy hides nothing
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.hidden_labels.is_empty() && self.hidden_properties.is_empty()
+    }
+
+    /// Whether the policy applies to the caller with the given key name
+    #[must_use]
+    pub fn applies_to(
+        &self,
+        key_name: Option<&str>,
+    ) -> bool {
+        !self.is_empty() && key_name.is_none_or(|name| !self.exempt_keys.iter().any(|exempt| exempt == name))
+    }
+
+    #[must_use]
+    pub fn hides_label(
+        &self,
+        label: &str,
+    ) -> bool {
+        self.hidden_labels.iter().any(|hidden| hidden == label)
+    }
+
+    /// Whether the property is hidden on an element with the given labels; `None` means the
+    /// labels are unknown, so a property hidden on any label is hidden
+    #[must_use]
+    pub fn hides_property(
+        &self,
+        labels: Option<&[String]>,
+        property: &str,
+    ) -> bool {
+        self.hidden_properties.iter().any(|rule| match rule.split_once('.') {
+            Some((label, hidden)) => {
+                hidden == property && labels.is_none_or(|labels| labels.iter().any(|l| l == label))
+            }
+            None => rule == property,
+        })
+    }
+
+    /// Remove hidden labels, relations touching them and hidden attributes from the schema
+    pub fn filter_schema(
+        &self,
+        schema: &mut Schema,
+    ) {
+        schema.entities.retain(|entity| !self.hides_label(&entity.label));
+        for entity in &mut schema.entities {
+            let labels = std::slice::from_ref(&entity.label);
+            entity
+                .attributes
+                .retain(|attribute| !self.hides_property(Some(labels), &attribute.name));
+        }
+
+        schema.relations.retain(|relation| {
+            !self.hides_label(&relation.label)
+                && !self.hides_label(&relation.source)
+                && !self.hides_label(&relation.target)
+        });
+        for relation in &mut schema.relations {
+            let labels = std::slice::from_ref(&relation.label);
+            relation
+                .attributes
+                .retain(|attribute| !self.hides_property(Some(labels), &attribute.name));
+        }
+    }
+
+    /// [`AccessPolicy::filter_schema`] for a schema serialized as JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is not a schema.
+    pub fn filter_schema_json(
+        &self,
+        schema: &str,
+    ) -> Result<String, serde_json::Error> {
+        let mut schema: Schema = serde_json::from_str(schema)?;
+        self.filter_schema(&mut schema);
+        serde_json::to_string(&schema)
+    }
+
+    /// Check a generated query against the policy, rewriting it if the policy allows
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason the query may not run.
+    pub fn enforce(
+        &self,
+        query: &str,
+    ) -> Result<Enforced, String> {
+        // Blank out string literals without moving anything, so offsets still match the query
+        let blanked = STRING_LITERAL.replace_all(query, |captures: &regex::Captures| {
+            let literal = &captures[0];
+            format!("{0}{1}{0}", &literal[..1], "_".repeat(literal.len() - 2))
+        });
+        let without_map_keys = MAP_KEY.replace_all(&blanked, "$1");
+
+        let bindings = self.label_bindings(&without_map_keys)?;
+        self.check_introspection(&blanked)?;
+
+        for captures in MAP_KEY.captures_iter(&blanked) {
+            let key = unquote(&captures[2]);
+            if self.hides_property(None, &key) {
+                return Err(format!(
+                    "the query matches or builds a map on the hidden property {key}"
+                ));
+            }
+        }
+
+        // Without a label in the query, an element could have a hidden label
+        let unlabelled = |variable: &str| !self.hidden_labels.is_empty() && !bindings.contains_key(variable);
+
+        for captures in MAP_PROJECTION.captures_iter(&blanked) {
+            let variable = unquote(&captures[1]);
+            if unlabelled(&variable) && PROJECTED_PROPERTY.is_match(&captures[2]) {
+                return Err(format!(
+                    "the query projects properties of {variable}, which has no label and could have a hidden one"
+                ));
+            }
+            let labels = bindings.get(&variable).map(Vec::as_slice);
+            for projected in PROJECTED_PROPERTY.captures_iter(&captures[2]) {
+                let property = unquote(&projected[1]);
+                if property == "*" && !self.hidden_properties.is_empty() {
+                    return Err("the query projects every property of an element".to_string());
+                }
+                if self.hides_property(labels, &property) {
+                    return Err(format!("the query projects the hidden property {property}"));
+                }
+            }
+        }
+
+        for captures in DYNAMIC_ACCESS.captures_iter(&blanked) {
+            let start = captures.get(2).map_or(0, |quote| quote.end());
+            let quote = &captures[2];
+            let property = query[start..].split(quote).next().unwrap_or_default();
+            let variable = unquote(&captures[1]);
+            if unlabelled(&variable) {
+                return Err(format!(
+                    "the query reads {variable}['{property}'] of an element without a label, which could have a hidden one"
+                ));
+            }
+            let labels = bindings.get(&variable).map(Vec::as_slice);
+            if self.hides_property(labels, property) {
+                return Err(format!("the query reads the hidden property {property}"));
+            }
+        }
+
+        let mut rewritten = Vec::new();
+        let mut output = String::with_capacity(query.len());
+        let mut last = 0;
+        for captures in PROPERTY_ACCESS.captures_iter(&blanked) {
+            if captures.get(3).is_some() {
+                // A namespaced function call, e.g. string.join()
+                continue;
+            }
+            let variable = unquote(&captures[1]);
+            let property = unquote(&captures[2]);
+            if unlabelled(&variable) {
+                return Err(format!(
+                    "the query reads {variable}.{property} of an element without a label, which could have a hidden one"
+                ));
+            }
+            let labels = bindings.get(&variable).map(Vec::as_slice);
+            if !self.hides_property(labels, &property) {
+                continue;
+            }
+            if self.on_violation == ViolationPolicy::Reject {
+                return Err(format!("the query reads the hidden property {variable}.{property}"));
+            }
+            let (Some(start), Some(end)) = (captures.get(1), captures.get(2)) else {
+                continue;
+            };
+            output.push_str(&query[last..start.start()]);
+            output.push_str("null");
+            last = end.end();
+            rewritten.push(format!("{variable}.{property}"));
+        }
+        output.push_str(&query[last..]);
+
+        Ok(Enforced {
+            query: output,
+            rewritten,
+        })
+    }
+
+    /// The labels written for each variable of the query, refusing hidden labels
+    fn label_bindings(
+        &self,
+        query: &str,
+    ) -> Result<HashMap<String, Vec<String>>, String> {
+        let mut bindings: HashMap<String, Vec<String>> = HashMap::new();
+        for captures in LABEL_EXPRESSION.captures_iter(query) {
+            let labels: Vec<String> = LABEL_SEPARATOR
+                .split(captures[2].trim_start_matches(':'))
+                .map(unquote)
+                .collect();
+            if let Some(label) = labels.iter().find(|label| self.hides_label(label)) {
+                return Err(format!("the query references the hidden label {label}"));
+            }
+            if let Some(variable) = captures.get(1) {
+                bindings.entry(unquote(variable.as_str())).or_default().extend(labels);
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Refuse calls that list labels or properties the policy hides
+    fn check_introspection(
+        &self,
+        query: &str,
+    ) -> Result<(), String> {
+        for captures in INTROSPECTION.captures_iter(query) {
+            let function = captures[1].to_lowercase();
+            let reveals_labels = !matches!(function.as_str(), "keys" | "properties" | "db.propertykeys");
+            let reveals_properties = !matches!(
+                function.as_str(),
+                "labels" | "type" | "db.labels" | "db.relationshiptypes"
+            );
+            // The properties of an element can reveal that it has a hidden label
+            if reveals_labels && !self.hidden_labels.is_empty() || reveals_properties && !self.is_empty() {
+                return Err(format!(
+                    "the query calls {}(), which could reveal hidden labels or properties",
+                    &captures[1]
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Strip hidden labels and properties from every node, relationship and map in the records
+    pub fn redact(
+        &self,
+        records: &mut Records,
+    ) {
+        for value in records.iter_mut().flatten() {
+            self.redact_value(value);
+        }
+    }
+
+    fn redact_value(
+        &self,
+        value: &mut FalkorValue,
+    ) {
+        match value {
+            FalkorValue::Node(node) => self.redact_node(node),
+            FalkorValue::Edge(edge) => self.redact_edge(edge),
+            FalkorValue::Path(path) => {
+                for node in &mut path.nodes {
+                    self.redact_node(node);
+                }
+                for edge in &mut path.relationships {
+                    self.redact_edge(edge);
+                }
+            }
+            FalkorValue::Array(values) => {
+                for value in values {
+                    self.redact_value(value);
+                }
+            }
+            FalkorValue::Map(map) => {
+                map.retain(|key, _| !self.hides_property(Some(&[]), key));
+                for value in map.values_mut() {
+                    self.redact_value(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn redact_node(
+        &self,
+        node: &mut Node,
+    ) {
+        let labelled = node.labels.len();
+        node.labels.retain(|label| !self.hides_label(label));
+        if node.labels.len() < labelled {
+            node.properties.clear();
+            return;
+        }
+        let labels = node.labels.clone();
+        node.properties
+            .retain(|property, _| !self.hides_property(Some(&labels), property));
+        for value in node.properties.values_mut() {
+            self.redact_value(value);
+        }
+    }
+
+    fn redact_edge(
+        &self,
+        edge: &mut Edge,
+    ) {
+        if self.hides_label(&edge.relationship_type) {
+            edge.relationship_type = REDACTED_TYPE.to_string();
+            edge.properties.clear();
+            return;
+        }
+        let labels = std::slice::from_ref(&edge.relationship_type);
+        edge.properties
+            .retain(|property, _| !self.hides_property(Some(labels), property));
+    }
+}
+
+fn unquote(identifier: &str) -> String {
+    identifier.trim().trim_matches('`').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{attribute::Attribute, attribute::AttributeType, entity::Entity, relation::Relation};
+
+    fn policy(on_violation: ViolationPolicy) -> AccessPolicy {
+        AccessPolicy {
+            hidden_labels: vec!["Salary".to_string()],
+            hidden_properties: vec!["ssn".to_string(), "Person.born".to_string()],
+            on_violation,
+            exempt_keys: vec!["hr".to_string()],
+        }
+    }
+
+    fn attribute(name: &str) -> Attribute {
+        Attribute::new(name.to_string(), AttributeType::String, 1, false, false)
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let policy = policy(ViolationPolicy::Reject);
+        assert!(policy.applies_to(None));
+        assert!(policy.applies_to(Some("analytics")));
+        assert!(!policy.applies_to(Some("hr")));
+        assert!(!AccessPolicy::default().applies_to(None));
+    }
+
+    #[test]
+    fn test_filter_schema() {
+        let mut schema = Schema {
+            entities: vec![
+                Entity::new(
+                    "Person".to_string(),
+                    vec![attribute("name"), attribute("born"), attribute("ssn")],
+                    None,
+                ),
+                Entity::new("Movie".to_string(), vec![attribute("title"), attribute("born")], None),
+                Entity::new("Salary".to_string(), vec![attribute("amount")], None),
+            ],
+            relations: vec![
+                Relation::new(
+                    "ACTED_IN".to_string(),
+                    "Person".to_string(),
+                    "Movie".to_string(),
+                    vec![],
+                ),
+                Relation::new("EARNS".to_string(), "Person".to_string(), "Salary".to_string(), vec![]),
+            ],
+        };
+        policy(ViolationPolicy::Reject).filter_schema(&mut schema);
+
+        let entities: Vec<(&str, Vec<&str>)> = schema
+            .entities
+            .iter()
+            .map(|e| (e.label.as_str(), e.attributes.iter().map(|a| a.name.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            entities,
+            vec![("Person", vec!["name"]), ("Movie", vec!["title", "born"])]
+        );
+        assert_eq!(schema.relations.len(), 1);
+        assert_eq!(schema.relations[0].label, "ACTED_IN");
+
+        // Cached schemas are serialized without counts and must still filter
+        let json = serde_json::to_string(&schema).unwrap();
+        assert_eq!(policy(ViolationPolicy::Reject).filter_schema_json(&json).unwrap(), json);
+    }
+
+    #[test]
+    fn test_enforce_rejects() {
+        let policy = policy(ViolationPolicy::Reject);
+        for query in [
+            "MATCH (p:Person)-[:EARNS]->(s:Salary) RETURN s.amount",
+            "MATCH (p) WHERE p:`Salary` RETURN p",
+            "MATCH (p:Person) RETURN p.ssn",
+            "MATCH (p:Person) RETURN p.born",
+            "MATCH (p {ssn: '123'}) RETURN p.name",
+            "MATCH (p:Person) RETURN p {.name, .ssn}",
+            "MATCH (p:Person) RETURN p {.*}",
+            "MATCH (p:Person) RETURN p['ssn']",
+            "MATCH (p:Person) RETURN keys(p)",
+            "CALL db.labels()",
+            "MATCH (p:Person)-[r]->(s) RETURN s.amount",
+            "MATCH (n) RETURN n.amount",
+            "MATCH (p:Person)-[r]->(s) RETURN r.since",
+            "MATCH (n) RETURN n['amount']",
+            "MATCH (n) RETURN n {.amount}",
+            "MATCH (n) RETURN properties(n)",
+        ] {
+            assert!(policy.enforce(query).is_err(), "{query}");
+        }
+
+        // Unlabelled elements are only refused when a label is hidden
+        let properties_only = AccessPolicy {
+            hidden_labels: vec![],
+            ..policy
+        };
+        assert!(properties_only.enforce("MATCH (n) RETURN n.amount").is_ok());
+        assert!(properties_only.enforce("MATCH (n) RETURN n.ssn").is_err());
+    }
+
+    #[test]
+    fn test_enforce_allows() {
+        let policy = policy(ViolationPolicy::Reject);
+        for query in [
+            "MATCH (p:Person)-[:ACTED_IN]->(m:Movie) WHERE m.title = 'Salary: ssn' RETURN p.name, m.born",
+            "MATCH (p:Person {name: 'Keanu'}) RETURN p",
+            "MATCH (m:Movie) RETURN m {.title, .born}",
+            "MATCH (p:Person) RETURN string.join([p.name, 'x'], ',')",
+        ] {
+            assert_eq!(
+                policy.enforce(query),
+                Ok(Enforced {
+                    query: query.to_string(),
+                    rewritten: vec![],
+                }),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_enforce_rewrites() {
+        let policy = policy(ViolationPolicy::Rewrite);
+        let enforced = policy
+            .enforce("MATCH (p:Person) WHERE p.ssn STARTS WITH '1' RETURN p.name, p.`born` AS born")
+            .unwrap();
+        assert_eq!(
+            enforced.query,
+            "MATCH (p:Person) WHERE null STARTS WITH '1' RETURN p.name, null AS born"
+        );
+        assert_eq!(enforced.rewritten, vec!["p.ssn", "p.born"]);
+        assert!(policy.enforce("MATCH (s:Salary) RETURN s.amount").is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let node = |labels: &[&str], properties: &[(&str, FalkorValue)]| {
+            FalkorValue::Node(Node {
+                entity_id: 1,
+                labels: labels.iter().map(ToString::to_string).collect(),
+                properties: properties
+                    .iter()
+                    .map(|(key, value)| ((*key).to_string(), value.clone()))
+                    .collect(),
+            })
+        };
+        let name = ("name", FalkorValue::String("Keanu".to_string()));
+        let born = ("born", FalkorValue::I64(1964));
+        let ssn = ("ssn", FalkorValue::String("123".to_string()));
+
+        let mut records = vec![vec![
+            node(&["Person"], &[name.clone(), born.clone(), ssn.clone()]),
+            node(&["Movie"], &[born.clone(), ssn]),
+            node(&["Salary"], &[("amount", FalkorValue::I64(1))]),
+            FalkorValue::Edge(Edge {
+                entity_id: 2,
+                relationship_type: "EARNS".to_string(),
+                src_node_id: 1,
+                dst_node_id: 3,
+                properties: HashMap::new(),
+            }),
+        ]];
+        let mut policy = policy(ViolationPolicy::Reject);
+        policy.hidden_labels.push("EARNS".to_string());
+        policy.redact(&mut records);
+
+        assert_eq!(records[0][0], node(&["Person"], &[name]));
+        assert_eq!(records[0][1], node(&["Movie"], &[born]));
+        assert_eq!(records[0][2], node(&[], &[]));
+        let FalkorValue::Edge(edge) = &records[0][3] else {
+            panic!("Expected an edge");
+        };
+        assert_eq!(edge.relationship_type, REDACTED_TYPE);
+    }
+}
@@ -0,0 +1,378 @@
+//! Audit Log Module
+//!
+//! Writes one JSON line per text-to-cypher interaction to an append-only file. The file is
+//! rotated once it grows past a size limit (`audit.jsonl` -> `audit.jsonl.1` -> ...), keeping a
+//! bounded number of rotated files. Questions and result contents can be redacted, in which
+//! case only their SHA-256 hash is recorded. The result hash covers every row the database
+//! returned, before results are summarized or values are redacted for the model.
+
+use falkordb::FalkorValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::formatter::falkor_value_to_json;
+
+const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 10;
+
+/// Which parts of an interaction are left out of the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redaction {
+    pub questions: bool,
+    pub results: bool,
+}
+
+impl Redaction {
+    /// Parse a comma-separated list of `question`, `result` or `none`
+    #[must_use]
+    pub fn parse(value: &str) -> Self {
+        let mut redaction = Self {
+            questions: false,
+            results: false,
+        };
+        for item in value.split(',').map(str::trim) {
+            match item {
+                "question" | "questions" => redaction.questions = true,
+                "result" | "results" => redaction.results = true,
+                "none" | "" => {}
+                other => tracing::warn!("Ignoring unknown audit redaction '{}'", other),
+            }
+        }
+        redaction
+    }
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            questions: false,
+            results: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    pub max_files: usize,
+    pub redaction: Redaction,
+}
+
+impl AuditConfig {
+    /// Build the audit configuration from the environment; `None` when `AUDIT_LOG_PATH` is not set
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("AUDIT_LOG_PATH").ok()?;
+        let max_bytes = std::env::var("AUDIT_LOG_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        let max_files = std::env::var("AUDIT_LOG_MAX_FILES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_FILES);
+        let redaction =
+            std::env::var("AUDIT_REDACT").map_or_else(|_| Redaction::default(), |value| Redaction::parse(&value));
+
+        Some(Self {
+            path: PathBuf::from(path),
+            max_bytes,
+            max_files,
+            redaction,
+        })
+    }
+}
+
+/// Everything known about an interaction once the pipeline has finished
+#[derive(Debug, Clone, Default)]
+pub struct AuditRecord {
+    pub request_id: String,
+    pub caller: String,
+    pub graph: String,
+    pub model: String,
+    pub question: String,
+    pub cypher: Option<String>,
+    pub row_count: Option<usize>,
+    pub result: Option<String>,
+    /// Hash of the records the query returned, see [`records_hash`]
+    pub result_hash: Option<String>,
+    /// Prompt-injection signals detected in the client's messages
+    pub injection_signals: Vec<String>,
+}
+
+/// A single line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub request_id: String,
+    pub caller: String,
+    pub graph: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    pub question_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cypher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injection_signals: Vec<String>,
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    #[must_use]
+    pub fn new(
+        record: AuditRecord,
+        outcome: &str,
+        redaction: Redaction,
+    ) -> Self {
+        let question_hash = sha256_hex(&record.question);
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            request_id: record.request_id,
+            caller: record.caller,
+            graph: record.graph,
+            model: record.model,
+            question: (!redaction.questions).then_some(record.question),
+            question_hash,
+            cypher: record.cypher,
+            row_count: record.row_count,
+            result_hash: record.result_hash,
+            result: record.result.filter(|_| !redaction.results),
+            injection_signals: record.injection_signals,
+            outcome: outcome.to_string(),
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 digest of the given text
+#[must_use]
+pub fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            use std::fmt::Write;
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Hex-encoded SHA-256 digest of query results, over a canonical JSON serialization of every
+/// value; map keys are serialized in sorted order, so the same rows always hash the same
+#[must_use]
+pub fn records_hash(records: &[Vec<FalkorValue>]) -> String {
+    let rows = records
+        .iter()
+        .map(|record| Value::Array(record.iter().map(falkor_value_to_json).collect()))
+        .collect();
+    sha256_hex(&Value::Array(rows).to_string())
+}
+
+/// Append-only JSONL audit log with size-based rotation
+#[derive(Debug)]
+pub struct AuditLog {
+    config: AuditConfig,
+    file: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    #[must_use]
+    pub const fn new(config: AuditConfig) -> Self {
+        Self {
+            config,
+            file: Mutex::new(None),
+        }
+    }
+
+    #[must_use]
+    pub const fn redaction(&self) -> Redaction {
+        self.config.redaction
+    }
+
+    /// Append an entry, rotating the file first if it would exceed the size limit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry cannot be serialized or written.
+    pub fn append(
+        &self,
+        entry: &AuditEntry,
+    ) -> Result<(), std::io::Error> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().map_err(|_| std::io::Error::other("Audit log lock poisoned"))?;
+
+        let current_size = std::fs::metadata(&self.config.path).map_or(0, |metadata| metadata.len());
+        if current_size > 0 && current_size + line.len() as u64 > self.config.max_bytes {
+            *file = None;
+            rotate(&self.config.path, self.config.max_files)?;
+        }
+
+        let writer = match file.as_mut() {
+            Some(writer) => writer,
+            None => file.insert(self.open()?),
+        };
+        let result = writer.write_all(line.as_bytes()).and_then(|()| writer.flush());
+        drop(file);
+        result
+    }
+
+    fn open(&self) -> Result<File, std::io::Error> {
+        if let Some(parent) = self.config.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new().create(true).append(true).open(&self.config.path)
+    }
+}
+
+fn rotated_path(
+    path: &Path,
+    index: usize,
+) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// Shift `path.N-1` to `path.N` down to `path` to `path.1`, dropping the oldest file
+fn rotate(
+    path: &Path,
+    max_files: usize,
+) -> Result<(), std::io::Error> {
+    if max_files == 0 {
+        return std::fs::remove_file(path);
+    }
+
+    let oldest = rotated_path(path, max_files);
+    if oldest.exists() {
+        std::fs::remove_file(&oldest)?;
+    }
+    for index in (1..max_files).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated_path(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> AuditRecord {
+        AuditRecord {
+            request_id: "req-1".to_string(),
+            caller: "anonymous".to_string(),
+            graph: "movies".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            question: "Who acted in Heat?".to_string(),
+            cypher: Some("MATCH (a:Actor) RETURN a".to_string()),
+            row_count: Some(2),
+            result: Some("1. \"Al\"\n2. \"Bob\"".to_string()),
+            result_hash: Some(records_hash(&[
+                vec![FalkorValue::String("Al".to_string())],
+                vec![FalkorValue::String("Bob".to_string())],
+            ])),
+            injection_signals: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_redaction_parse() {
+        assert_eq!(
+            Redaction::parse("question, result"),
+            Redaction {
+                questions: true,
+                results: true,
+            }
+        );
+        assert_eq!(
+            Redaction::parse("none"),
+            Redaction {
+                questions: false,
+                results: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_entry_redacts_but_keeps_hashes() {
+        let redaction = Redaction {
+            questions: true,
+            results: true,
+        };
+        let entry = AuditEntry::new(record(), "success", redaction);
+        assert!(entry.question.is_none());
+        assert!(entry.result.is_none());
+        assert_eq!(entry.question_hash, sha256_hex("Who acted in Heat?"));
+        assert_eq!(entry.result_hash.as_deref().map(str::len), Some(64));
+    }
+
+    #[test]
+    fn test_records_hash() {
+        let map = |entries: &[(&str, i64)]| {
+            FalkorValue::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| ((*key).to_string(), FalkorValue::I64(*value)))
+                    .collect(),
+            )
+        };
+        let hash = records_hash(&[vec![map(&[("a", 1), ("b", 2)]), FalkorValue::F64(3.5)]]);
+        assert_eq!(
+            hash,
+            records_hash(&[vec![map(&[("b", 2), ("a", 1)]), FalkorValue::F64(3.5)]])
+        );
+        assert_ne!(
+            hash,
+            records_hash(&[vec![map(&[("a", 1), ("b", 3)]), FalkorValue::F64(3.5)]])
+        );
+        assert_ne!(
+            hash,
+            records_hash(&[vec![map(&[("a", 1), ("b", 2)])], vec![FalkorValue::F64(3.5)]])
+        );
+    }
+
+    #[test]
+    fn test_append_rotates_files() {
+        let dir = std::env::temp_dir().join(format!("audit-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("audit.jsonl");
+        let log = AuditLog::new(AuditConfig {
+            path: path.clone(),
+            max_bytes: 400,
+            max_files: 2,
+            redaction: Redaction::default(),
+        });
+
+        let entry = AuditEntry::new(record(), "success", Redaction::default());
+        for _ in 0..6 {
+            log.append(&entry).unwrap();
+        }
+
+        assert!(path.exists());
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let parsed: AuditEntry = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(parsed.graph, "movies");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+//! API Key Authentication
+//!
+//! When the `[auth]` section of the configuration file lists keys or names a keys file, every
+//! HTTP endpoint except the API documentation requires an `Authorization: Bearer <key>` header.
+//! The [`authenticate`] middleware resolves the key to a [`Principal`] and checks the query
+//! [`Scope`]; admin endpoints are wrapped in [`require_admin`] at their route, so the check
+//! follows the route the request was dispatched to however its path is encoded. Handlers then
+//! check the graph and connection the request targets against the key's allowlists. Keys are
+//! only compared by their SHA-256 digest, so the configuration can hold `key_sha256` instead of
+//! the key itself.
+
+use crate::audit::sha256_hex;
+use crate::config::{AuthSettings, Settings};
+use crate::error::ApiError;
+use crate::graph::MEMORY_PREFIX;
+use crate::limits::{RateLimit, Subjects};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{AUTHORIZATION, HeaderMap};
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::{Ready, ready};
+use std::sync::{Arc, OnceLock};
+
+/// Allowlist entry matching every graph or connection
+const WILDCARD: &str = "*";
+
+static KEY_STORE: OnceLock<Option<KeyStore>> = OnceLock::new();
+
+/// What a key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Scope {
+    /// Ask questions, read schemas and examples, list graphs and submit feedback
+    Query,
+    /// Everything `query` allows, plus clearing caches, reading usage and metrics and reviewing
+    /// feedback
+    Admin,
+}
+
+/// An API key and what it may access
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeySettings {
+    /// Identifies the key in logs and the audit log
+    pub name: String,
+    /// The key itself; prefer `key_sha256` so the configuration holds no secrets
+    pub key: Option<String>,
+    /// Hex-encoded SHA-256 digest of the key
+    pub key_sha256: Option<String>,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<Scope>,
+    /// Graphs the key may use; every graph when omitted
+    pub graphs: Option<Vec<String>>,
+    /// Names of the `[connections]` the key may pass as `falkordb_connection`; none when omitted.
+    /// Connection strings need the admin scope and `server.allow_raw_connections`.
+    #[serde(default)]
+    pub connections: Vec<String>,
+    /// Replaces `limits.per_key` for this key
+    pub rate_limit: Option<RateLimit>,
+    /// Replaces `limits.daily_tokens_per_key` for this key
+    pub daily_tokens: Option<u64>,
+}
+
+fn default_scopes() -> Vec<Scope> {
+    vec![Scope::Query]
+}
+
+impl ApiKeySettings {
+    /// The digest the key is looked up by
+    #[must_use]
+    pub fn digest(&self) -> Option<String> {
+        self.key_sha256
+            .as_deref()
+            .map(str::to_ascii_lowercase)
+            .or_else(|| self.key.as_deref().map(sha256_hex))
+    }
+}
+
+/// The caller of a request, as established by [`authenticate`]
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Name of the key; `None` when authentication is disabled
+    name: Option<String>,
+    scopes: Vec<Scope>,
+    graphs: Option<Vec<String>>,
+    connections: Vec<String>,
+    rate_limit: Option<RateLimit>,
+    daily_tokens: Option<u64>,
+}
+
+impl Principal {
+    /// The caller when authentication is disabled, which may do anything
+    #[must_use]
+    pub fn unrestricted() -> Self {
+        Self {
+            name: None,
+            scopes: vec![Scope::Query, Scope::Admin],
+            graphs: None,
+            connections: vec![WILDCARD.to_string()],
+            rate_limit: None,
+            daily_tokens: None,
+        }
+    }
+
+    fn from_settings(key: &ApiKeySettings) -> Self {
+        Self {
+            name: Some(key.name.clone()),
+            scopes: key.scopes.clone(),
+            graphs: key.graphs.clone(),
+            connections: key.connections.clone(),
+            rate_limit: key.rate_limit,
+            daily_tokens: key.daily_tokens,
+        }
+    }
+
+    /// Name of the key; `None` when authentication is disabled
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Who a request from this caller and client address is counted against
+    #[must_use]
+    pub fn limit_subjects(
+        &self,
+        client_addr: &str,
+    ) -> Subjects {
+        Subjects {
+            key: self.name.clone(),
+            ip: Some(client_addr.to_string()),
+            key_rate_limit: self.rate_limit,
+            key_daily_tokens: self.daily_tokens,
+        }
+    }
+
+    /// Whether the key has the scope; `admin` implies `query`
+    #[must_use]
+    pub fn has_scope(
+        &self,
+        scope: Scope,
+    ) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+
+    /// Whether the key may use the graph
+    #[must_use]
+    pub fn allows_graph(
+        &self,
+        graph_name: &str,
+    ) -> bool {
+        self.graphs
+            .as_ref()
+            .is_none_or(|graphs| graphs.iter().any(|graph| graph == WILDCARD || graph == graph_name))
+    }
+
+    /// Check that the key may use the graph
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ApiError::Forbidden`] if the graph is not in the key's allowlist.
+    pub fn authorize_graph(
+        &self,
+        graph_name: &str,
+    ) -> Result<(), ApiError> {
+        if self.allows_graph(graph_name) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "API key '{}' may not access graph '{graph_name}'",
+                self.name().unwrap_or_default()
+            )))
+        }
+    }
+
+    /// Whether the key may select the named connection
+    #[must_use]
+    pub fn allows_connection(
+        &self,
+        name: &str,
+    ) -> bool {
+        self.has_scope(Scope::Admin)
+            || self
+                .connections
+                .iter()
+                .any(|connection| connection == WILDCARD || connection == name)
+    }
+
+    /// Check that the key may pass `falkordb_connection` in place of the configured connection:
+    /// a named connection in its allowlist, or, with the admin scope and raw connections allowed,
+    /// a connection string other than a `memory:` fixture
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ApiError::Forbidden`] if the key may not use the connection.
+    pub fn authorize_connection(
+        &self,
+        falkordb_connection: &str,
+    ) -> Result<(), ApiError> {
+        self.authorize_connection_in(Settings::get(), falkordb_connection)
+    }
+
+    fn authorize_connection_in(
+        &self,
+        settings: &Settings,
+        falkordb_connection: &str,
+    ) -> Result<(), ApiError> {
+        if settings.is_named_connection(falkordb_connection) {
+            if self.allows_connection(falkordb_connection) {
+                return Ok(());
+            }
+            return Err(ApiError::Forbidden(format!(
+                "API key '{}' may not use connection '{falkordb_connection}'",
+                self.name().unwrap_or_default()
+            )));
+        }
+        // Fixtures are read from the server's disk, so only the configuration may name them
+        if falkordb_connection.starts_with(MEMORY_PREFIX) {
+            return Err(ApiError::Forbidden(
+                "In-memory fixtures cannot be requested; pass the name of a connection listed by /list_connections"
+                    .to_string(),
+            ));
+        }
+        if !settings.server.allow_raw_connections {
+            return Err(ApiError::Forbidden(
+                "Connection strings are disabled; pass the name of a connection listed by /list_connections"
+                    .to_string(),
+            ));
+        }
+        if !self.has_scope(Scope::Admin) {
+            return Err(ApiError::Forbidden(
+                "Connection strings need the admin scope; pass the name of a connection listed by /list_connections"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The configured keys, by digest
+#[derive(Debug)]
+pub struct KeyStore {
+    keys: HashMap<String, Arc<Principal>>,
+}
+
+impl KeyStore {
+    /// Build the store from the settings; `None` when authentication is disabled
+    #[must_use]
+    pub fn from_settings(settings: &AuthSettings) -> Option<Self> {
+        if !settings.enabled() {
+            return None;
+        }
+        let keys = settings
+            .keys
+            .iter()
+            .filter_map(|key| Some((key.digest()?, Arc::new(Principal::from_settings(key)))))
+            .collect();
+        Some(Self { keys })
+    }
+
+    /// The store built from [`Settings`], or `None` when authentication is disabled
+    pub fn get() -> Option<&'static Self> {
+        KEY_STORE.get_or_init(|| Self::from_settings(&Settings::get().auth)).as_ref()
+    }
+
+    /// The principal of the key, if it is configured
+    #[must_use]
+    pub fn lookup(
+        &self,
+        key: &str,
+    ) -> Option<Arc<Principal>> {
+        self.keys.get(&sha256_hex(key)).cloned()
+    }
+
+    /// Number of configured keys
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// The key in an `Authorization: Bearer <key>` header
+#[must_use]
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Whether the path is public: the API documentation and the health checks
+///
+/// The raw path is compared, so an encoded spelling of a public path needs a key like any other.
+#[must_use]
+pub fn is_public(path: &str) -> bool {
+    path.starts_with("/swagger-ui") || path.starts_with("/api-doc/") || path == "/healthz" || path == "/readyz"
+}
+
+/// Middleware authenticating the bearer key of every request to a non-public path
+///
+/// The key must have the query scope. The [`Principal`] is stored in the request extensions for
+/// the handlers and [`require_admin`].
+///
+/// # Errors
+///
+/// Returns [`ApiError::Unauthorized`] if the key is missing or unknown, and
+/// [`ApiError::Forbidden`] if the key lacks the query scope.
+#[allow(clippy::future_not_send)] // actix middleware runs on a single-threaded worker
+pub async fn authenticate(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    authenticate_with(KeyStore::get(), request, next).await
+}
+
+#[allow(clippy::future_not_send)] // actix middleware runs on a single-threaded worker
+async fn authenticate_with(
+    keys: Option<&KeyStore>,
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(keys) = keys else {
+        request.extensions_mut().insert(Arc::new(Principal::unrestricted()));
+        return next.call(request).await;
+    };
+    if is_public(request.path()) {
+        return next.call(request).await;
+    }
+
+    let principal = match bearer_token(request.headers()) {
+        Some(token) => keys
+            .lookup(token)
+            .ok_or_else(|| ApiError::Unauthorized("Unknown API key".to_string()))?,
+        None => return Err(ApiError::Unauthorized("Missing bearer API key".to_string()).into()),
+    };
+    check_scope(&principal, Scope::Query, &request)?;
+
+    request.extensions_mut().insert(principal);
+    next.call(request).await
+}
+
+/// Route middleware admitting only keys with the admin scope, e.g.
+/// `#[get("/usage", wrap = "from_fn(auth::require_admin)")]`
+///
+/// # Errors
+///
+/// Returns [`ApiError::Unauthorized`] if [`authenticate`] established no caller, and
+/// [`ApiError::Forbidden`] if the key lacks the admin scope.
+#[allow(clippy::future_not_send)] // actix middleware runs on a single-threaded worker
+pub async fn require_admin(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let principal = request.extensions().get::<Arc<Principal>>().cloned();
+    let Some(principal) = principal else {
+        return Err(ApiError::Unauthorized("Missing bearer API key".to_string()).into());
+    };
+    check_scope(&principal, Scope::Admin, &request)?;
+    next.call(request).await
+}
+
+fn check_scope(
+    principal: &Principal,
+    scope: Scope,
+    request: &ServiceRequest,
+) -> Result<(), ApiError> {
+    if principal.has_scope(scope) {
+        return Ok(());
+    }
+    tracing::warn!(
+        "API key '{}' denied {} {}: missing the {scope} scope",
+        principal.name().unwrap_or_default(),
+        request.method(),
+        request.path()
+    );
+    Err(ApiError::Forbidden(format!(
+        "API key '{}' lacks the {scope} scope",
+        principal.name().unwrap_or_default()
+    )))
+}
+
+impl FromRequest for Principal {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(
+        request: &HttpRequest,
+        _payload: &mut Payload,
+    ) -> Self::Future {
+        let principal = request.extensions().get::<Arc<Self>>().map(|principal| (**principal).clone());
+        ready(match principal {
+            Some(principal) => Ok(principal),
+            // Without the middleware only an unauthenticated deployment may proceed
+            None if KeyStore::get().is_none() => Ok(Self::unrestricted()),
+            None => Err(ApiError::Unauthorized("Missing bearer API key".to_string())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use actix_web::middleware::from_fn;
+
+    fn key(
+        graphs: Option<&[&str]>,
+        connections: &[&str],
+    ) -> ApiKeySettings {
+        ApiKeySettings {
+            name: "analytics".to_string(),
+            key: Some("secret".to_string()),
+            key_sha256: None,
+            scopes: default_scopes(),
+            graphs: graphs.map(|graphs| graphs.iter().map(ToString::to_string).collect()),
+            connections: connections.iter().map(ToString::to_string).collect(),
+            rate_limit: None,
+            daily_tokens: None,
+        }
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(is_public("/swagger-ui/index.html"));
+        assert!(is_public("/api-doc/openapi.json"));
+        assert!(is_public("/readyz"));
+        assert!(!is_public("/text_to_cypher"));
+        assert!(!is_public("/%68ealthz"));
+    }
+
+    #[actix_web::get("/usage", wrap = "from_fn(require_admin)")]
+    async fn admin_route() -> &'static str {
+        "admin"
+    }
+
+    #[actix_web::get("/list_graphs")]
+    async fn query_route() -> &'static str {
+        "query"
+    }
+
+    #[actix_web::test]
+    async fn test_admin_scope_follows_the_route() {
+        let mut admin = key(None, &[]);
+        admin.name = "ops".to_string();
+        admin.key = Some("admin-secret".to_string());
+        admin.scopes = vec![Scope::Admin];
+        let store: &'static KeyStore = Box::leak(Box::new(
+            KeyStore::from_settings(&AuthSettings {
+                keys_file: None,
+                keys: vec![key(None, &[]), admin],
+            })
+            .unwrap(),
+        ));
+        let app = actix_web::test::init_service(
+            actix_web::App::new()
+                .wrap(from_fn(move |request, next| {
+                    authenticate_with(Some(store), request, next)
+                }))
+                .service(admin_route)
+                .service(query_route),
+        )
+        .await;
+
+        let status = |path: &'static str, key: &'static str| {
+            let request = actix_web::test::TestRequest::get()
+                .uri(path)
+                .insert_header((AUTHORIZATION, format!("Bearer {key}")))
+                .to_request();
+            let app = &app;
+            async move {
+                match actix_web::test::try_call_service(app, request).await {
+                    Ok(response) => response.status().as_u16(),
+                    Err(e) => e.error_response().status().as_u16(),
+                }
+            }
+        };
+        assert_eq!(status("/list_graphs", "secret").await, 200);
+        assert_eq!(status("/usage", "secret").await, 403);
+        assert_eq!(status("/%75sage", "secret").await, 403);
+        assert_eq!(status("/%75sage", "admin-secret").await, 200);
+        assert_eq!(status("/usage", "guess").await, 401);
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer  secret "));
+        assert_eq!(bearer_token(&headers), Some("secret"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic c2VjcmV0"));
+        assert_eq!(bearer_token(&headers), None);
+    }
+
+    #[test]
+    fn test_lookup_by_digest() {
+        let mut hashed = key(None, &[]);
+        hashed.name = "hashed".to_string();
+        hashed.key = None;
+        hashed.key_sha256 = Some(sha256_hex("other").to_uppercase());
+        let store = KeyStore::from_settings(&AuthSettings {
+            keys_file: None,
+            keys: vec![key(None, &[]), hashed],
+        })
+        .unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.lookup("secret").unwrap().name(), Some("analytics"));
+        assert_eq!(store.lookup("other").unwrap().name(), Some("hashed"));
+        assert!(store.lookup("guess").is_none());
+        assert!(KeyStore::from_settings(&AuthSettings::default()).is_none());
+    }
+
+    #[test]
+    fn test_authorization() {
+        let principal = Principal::from_settings(&key(Some(&["movies"]), &["replica"]));
+        assert!(principal.has_scope(Scope::Query));
+        assert!(!principal.has_scope(Scope::Admin));
+        assert!(principal.authorize_graph("movies").is_ok());
+        assert!(principal.authorize_graph("payroll").is_err());
+        assert!(principal.allows_connection("replica"));
+        assert!(!principal.allows_connection("primary"));
+        assert!(principal.authorize_connection("falkor://replica:6379").is_err());
+
+        let open = Principal::from_settings(&key(None, &[]));
+        assert!(open.allows_graph("payroll"));
+        assert!(!open.allows_connection("replica"));
+
+        let unrestricted = Principal::unrestricted();
+        assert!(unrestricted.has_scope(Scope::Admin));
+        assert!(unrestricted.allows_connection("primary"));
+        assert!(unrestricted.authorize_connection("falkor://primary:6379").is_err());
+
+        let mut settings = Settings::default();
+        settings.server.allow_raw_connections = true;
+        assert!(unrestricted.authorize_connection_in(&settings, "falkor://primary:6379").is_ok());
+        assert!(
+            unrestricted
+                .authorize_connection_in(&settings, "memory:/etc/fixture.json")
+                .is_err()
+        );
+        assert!(principal.authorize_connection_in(&settings, "falkor://replica:6379").is_err());
+    }
+}
@@ -0,0 +1,32 @@
+//! Offline evaluation of text-to-Cypher accuracy
+//!
+//! Sends every question of a dataset to a running text-to-cypher service, executes the generated
+//! and reference queries against `FalkorDB` and reports execution accuracy, validity rate, latency
+//! and token usage as Markdown (printed) and optionally as JSON and Markdown files. Also available
+//! as `text-to-cypher eval`.
+
+use clap::Parser;
+use text_to_cypher::eval::{self, EvalArgs};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "text-to-cypher-eval",
+    about = "Evaluate text-to-Cypher accuracy on a dataset"
+)]
+struct Args {
+    #[command(flatten)]
+    eval: EvalArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    dotenvy::dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
+    eval::run(Args::parse().eval).await
+}
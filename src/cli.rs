@@ -0,0 +1,281 @@
+//! Command-Line Interface
+//!
+//! `serve` (the default) runs the HTTP and MCP servers. The other subcommands script the service
+//! without running it: `ask` answers one question through the same pipeline as `/text_to_cypher`,
+//! `schema` prints a graph's discovered schema, `cache clear` clears the schema cache of a running
+//! server and `eval` evaluates accuracy on a dataset.
+
+use crate::chat::{ChatMessage, ChatRequest, ChatRole};
+use crate::config::Settings;
+use crate::dialect::Dialect;
+use crate::limits::Subjects;
+use crate::template::TemplateEngine;
+use crate::usage::{self, RequestUsage};
+use crate::{AppConfig, Progress, RequestContext, TextToCypherRequest, discover_graph_schema, llm};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+pub type CliError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "text-to-cypher",
+    version,
+    about = "Translate natural language questions into Cypher"
+)]
+pub struct Cli {
+    /// Configuration file; defaults to `CONFIG_FILE`, then `text-to-cypher.toml` if it exists
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP and MCP servers (the default)
+    Serve,
+    /// Answer a question and print the progress events
+    Ask(AskArgs),
+    /// Print the discovered schema of a graph
+    Schema(SchemaArgs),
+    /// Maintain the schema cache of a running server
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Evaluate text-to-Cypher accuracy on a dataset
+    Eval(text_to_cypher::eval::EvalArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AskArgs {
+    /// Graph to query
+    #[arg(short, long)]
+    graph: String,
+
+    /// The question to answer
+    question: String,
+
+    /// Model to use; defaults to `DEFAULT_MODEL`
+    #[arg(long)]
+    model: Option<String>,
+
+    /// API key for the model; defaults to `DEFAULT_KEY`
+    #[arg(long, hide_env_values = true)]
+    key: Option<String>,
+
+    /// Connection string or name of a configured connection; defaults to `FALKORDB_CONNECTION`
+    #[arg(long)]
+    falkordb_connection: Option<String>,
+
+    /// Cypher dialect to generate; defaults to the dialect of the connection's backend
+    #[arg(long)]
+    dialect: Option<Dialect>,
+
+    /// Print a single JSON summary at the end instead of one JSON event per line
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum SchemaFormat {
+    #[default]
+    Json,
+    Text,
+    Mermaid,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SchemaArgs {
+    /// Graph to describe
+    #[arg(short, long)]
+    graph: String,
+
+    #[arg(long, value_enum, default_value_t)]
+    format: SchemaFormat,
+
+    /// Connection string or name of a configured connection; defaults to `FALKORDB_CONNECTION`
+    #[arg(long)]
+    falkordb_connection: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Clear the cached schema of one graph, or of every graph
+    Clear {
+        /// Graph whose schema to clear; all graphs when omitted
+        #[arg(short, long)]
+        graph: Option<String>,
+
+        /// Base URL of the running text-to-cypher service; defaults to the configured server port
+        /// on the loopback address
+        #[arg(long, env = "TEXT_TO_CYPHER_URL")]
+        server: Option<String>,
+
+        /// API key with the admin scope, when the service requires one
+        #[arg(long, env = "TEXT_TO_CYPHER_API_KEY", hide_env_values = true)]
+        api_key: Option<String>,
+    },
+}
+
+/// Outcome of `ask --json`
+#[derive(Debug, Default, Serialize)]
+struct AskSummary {
+    request_id: Option<String>,
+    query: Option<String>,
+    result: Option<String>,
+    answer: Option<String>,
+    usage: Option<RequestUsage>,
+    warnings: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl AskSummary {
+    fn record(
+        &mut self,
+        progress: Progress,
+    ) {
+        match progress {
+            Progress::RequestId(request_id) => self.request_id = Some(request_id),
+            Progress::CypherQuery(query) => self.query = Some(query),
+            Progress::CypherResult(result) => self.result = Some(result),
+            Progress::Result(answer) => self.answer = Some(answer.text),
+            Progress::Warning(warning) => self.warnings.push(warning),
+            Progress::Error(error) => self.errors.push(error),
+            Progress::Usage(usage) => self.usage = Some(usage),
+            Progress::Status(_) | Progress::Schema(_) | Progress::ModelOutputChunk(_) => {}
+        }
+    }
+}
+
+/// Run the text-to-Cypher pipeline for one question
+///
+/// # Errors
+///
+/// Returns an error if a template cannot be loaded, no model is configured, the model cannot be
+/// resolved or the pipeline reports an error.
+pub async fn ask(args: AskArgs) -> Result<(), CliError> {
+    TemplateEngine::init()?;
+    let config = AppConfig::get();
+    let model = args
+        .model
+        .or_else(|| config.default_model.clone())
+        .ok_or("Model must be provided either with --model or as DEFAULT_MODEL in .env file")?;
+    let key = args.key.or_else(|| config.default_key.clone());
+    let backend = llm::backend_for(&model, key.as_deref())
+        .await
+        .map_err(|e| format!("Failed to resolve service target: {e}"))?;
+
+    let account = usage::key_id(key.as_deref());
+    let context = RequestContext {
+        request_id: uuid::Uuid::new_v4().to_string(),
+        caller: format!("{account}@cli"),
+        account,
+        subjects: Subjects::default(),
+        access: Settings::get().access_policy(&args.graph, None).cloned(),
+    };
+    let request = TextToCypherRequest {
+        graph_name: args.graph,
+        chat_request: ChatRequest {
+            messages: vec![ChatMessage {
+                role: ChatRole::User,
+                content: args.question,
+            }],
+        },
+        model: Some(model),
+        key,
+        falkordb_connection: args.falkordb_connection,
+        dialect: args.dialect,
+    };
+
+    let (tx, mut rx) = mpsc::channel(100);
+    tokio::spawn(crate::process_text_to_cypher_request(
+        request,
+        context,
+        backend,
+        tx,
+        std::future::pending(),
+    ));
+
+    let mut summary = AskSummary::default();
+    while let Some(progress) = rx.recv().await {
+        if !args.json {
+            println!("{}", serde_json::to_string(&progress)?);
+        }
+        summary.record(progress);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+    summary.errors.pop().map_or(Ok(()), |error| Err(error.into()))
+}
+
+/// Discover and print the schema of a graph
+///
+/// # Errors
+///
+/// Returns an error if the schema cannot be discovered.
+pub async fn schema(args: SchemaArgs) -> Result<(), CliError> {
+    let connection = args.falkordb_connection.as_deref().map_or_else(
+        || AppConfig::get().connection_for(&args.graph),
+        |connection| Settings::get().resolve_connection(connection),
+    );
+    let schema = discover_graph_schema(&connection, &args.graph).await?;
+
+    match args.format {
+        SchemaFormat::Json => println!("{}", serde_json::to_string_pretty(&schema)?),
+        SchemaFormat::Text => print!("{}", schema.to_text()),
+        SchemaFormat::Mermaid => print!("{}", schema.to_mermaid()),
+    }
+    Ok(())
+}
+
+/// Clear the schema cache of a running server
+///
+/// # Errors
+///
+/// Returns an error if the server cannot be reached or rejects a request.
+pub async fn clear_cache(
+    graph: Option<String>,
+    server: Option<String>,
+    api_key: Option<String>,
+) -> Result<(), CliError> {
+    let server = server.unwrap_or_else(|| format!("http://127.0.0.1:{}", Settings::get().server.port));
+    let server = server.trim_end_matches('/');
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(api_key) = api_key {
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            reqwest::header::HeaderValue::from_str(&format!("Bearer {api_key}"))?,
+        );
+    }
+    let client = reqwest::Client::builder().default_headers(headers).build()?;
+
+    let graphs = match graph {
+        Some(graph) => vec![graph],
+        None => {
+            client
+                .get(format!("{server}/list_graphs"))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?
+        }
+    };
+
+    for graph in graphs {
+        let mut url = url::Url::parse(server)?;
+        url.path_segments_mut()
+            .map_err(|()| format!("Invalid server URL: {server}"))?
+            .extend(["clear_schema_cache", graph.as_str()]);
+        client.post(url).send().await?.error_for_status()?;
+        println!("Cleared schema cache for graph: {graph}");
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Cross-Origin Resource Sharing
+//!
+//! Browsers only let a page call the HTTP API or the MCP server from another origin if the
+//! response allows it. With `cors.allowed_origins` configured, both servers answer preflight
+//! requests and add the CORS headers for the listed origins, methods and request headers; without
+//! it they send no CORS headers and browsers block cross-origin calls.
+
+use actix_cors::Cors;
+use actix_web::middleware::Condition;
+
+use crate::config::CorsSettings;
+
+/// Middleware applying the CORS policy, or passing requests through when none is configured
+///
+/// The settings are checked by [`Settings::validate`](crate::config::Settings::validate); invalid
+/// origins, methods or headers would panic here.
+#[must_use]
+pub fn middleware(settings: &CorsSettings) -> Condition<Cors> {
+    let mut cors = Cors::default()
+        .allowed_methods(settings.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(settings.allowed_headers.iter().map(String::as_str))
+        .max_age(settings.max_age_secs);
+    for origin in &settings.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    Condition::new(settings.enabled(), cors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{Method, StatusCode, header};
+    use actix_web::{App, HttpResponse, test, web};
+
+    #[actix_web::test]
+    async fn test_middleware() {
+        let settings = CorsSettings {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            ..CorsSettings::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware(&settings))
+                .route("/list_graphs", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let preflight = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/list_graphs")
+            .insert_header((header::ORIGIN, "https://app.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+            .to_request();
+        let response = test::call_service(&app, preflight).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://app.example.com"
+        );
+
+        let other = test::TestRequest::get()
+            .uri("/list_graphs")
+            .insert_header((header::ORIGIN, "https://evil.example.com"))
+            .to_request();
+        let response = test::call_service(&app, other).await;
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
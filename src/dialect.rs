@@ -0,0 +1,250 @@
+//! Cypher Dialect Profiles
+//!
+//! Graph databases agree on core openCypher but differ in their procedures, namespaced functions
+//! and path syntax. A [`Dialect`] selects the dialect-specific section of the system prompt
+//! (`templates/dialects/<dialect>.txt`) and the rules generated queries are validated against,
+//! so the model is neither told about nor allowed to use another database's extensions.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use utoipa::ToSchema;
+
+/// A single- or double-quoted Cypher string literal
+pub static STRING_LITERAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"'(?:[^'\\]|\\.)*'|"(?:[^"\\]|\\.)*""#).expect("Pattern should be valid"));
+static PROCEDURE_CALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bCALL\s+([A-Za-z_][\w.]*)\s*\(").expect("Pattern should be valid"));
+static FUNCTION_CALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(\bCALL\s+)?\b([A-Za-z_][\w.]*)\s*\(").expect("Pattern should be valid"));
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    Serialize,
+    Deserialize,
+    ToSchema,
+    strum::EnumString,
+    strum::Display,
+    strum::EnumIter,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case", ascii_case_insensitive)]
+pub enum Dialect {
+    #[default]
+    Falkordb,
+    /// Neo4j 5
+    Neo4j,
+    Memgraph,
+    /// Plain openCypher, without vendor extensions
+    #[serde(alias = "opencypher")]
+    #[strum(to_string = "open_cypher", serialize = "opencypher")]
+    OpenCypher,
+}
+
+/// What a dialect provides beyond core openCypher
+struct Profile {
+    /// Prefixes of the procedures that can be called
+    procedures: &'static [&'static str],
+    /// Namespaces of the functions that can be called, e.g. `apoc` for `apoc.text.join()`
+    function_namespaces: &'static [&'static str],
+    /// Functions of other dialects this one lacks (lowercase)
+    unsupported_functions: &'static [&'static str],
+}
+
+const FALKORDB: Profile = Profile {
+    procedures: &["db.", "dbms.", "algo."],
+    function_namespaces: &["vec", "string", "list"],
+    unsupported_functions: &[],
+};
+
+const NEO4J: Profile = Profile {
+    procedures: &["db.", "dbms.", "apoc.", "gds.", "genai."],
+    function_namespaces: &[
+        "apoc",
+        "gds",
+        "genai",
+        "db",
+        "vector",
+        "point",
+        "date",
+        "datetime",
+        "localdatetime",
+        "localtime",
+        "time",
+        "duration",
+    ],
+    unsupported_functions: &["vecf32"],
+};
+
+const MEMGRAPH: Profile = Profile {
+    procedures: &[
+        "mg.",
+        "schema.",
+        "algo.",
+        "pagerank.",
+        "betweenness_centrality.",
+        "degree_centrality.",
+        "katz_centrality.",
+        "community_detection.",
+        "weakly_connected_components.",
+        "node_similarity.",
+        "nxalg.",
+        "path.",
+        "text_search.",
+        "vector_search.",
+    ],
+    function_namespaces: &[
+        "collections",
+        "convert",
+        "date",
+        "duration",
+        "json_util",
+        "label",
+        "map",
+        "math",
+        "node",
+        "nodes",
+        "path",
+        "text",
+        "util_module",
+    ],
+    unsupported_functions: &["shortestpath", "allshortestpaths", "vecf32"],
+};
+
+const OPEN_CYPHER: Profile = Profile {
+    procedures: &[],
+    function_namespaces: &[],
+    unsupported_functions: &["vecf32"],
+};
+
+impl Dialect {
+    const fn profile(self) -> &'static Profile {
+        match self {
+            Self::Falkordb => &FALKORDB,
+            Self::Neo4j => &NEO4J,
+            Self::Memgraph => &MEMGRAPH,
+            Self::OpenCypher => &OPEN_CYPHER,
+        }
+    }
+
+    /// Template of the system prompt section describing the dialect's features, relative to the
+    /// templates directory
+    #[must_use]
+    pub fn template_path(self) -> String {
+        format!("dialects/{self}.txt")
+    }
+
+    /// Check that the query only uses procedures and functions the dialect provides
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first violation found.
+    pub fn validate(
+        self,
+        query: &str,
+    ) -> Result<(), String> {
+        let profile = self.profile();
+        let query = STRING_LITERAL.replace_all(query, "''");
+
+        for captures in PROCEDURE_CALL.captures_iter(&query) {
+            let procedure = &captures[1];
+            let lowercase = procedure.to_lowercase();
+            if !profile
+                .procedures
+                .iter()
+                .any(|prefix| lowercase.starts_with(&prefix.to_lowercase()))
+            {
+                return Err(format!("procedure {procedure}() is not available in {self}"));
+            }
+        }
+
+        for captures in FUNCTION_CALL.captures_iter(&query) {
+            if captures.get(1).is_some() {
+                // A procedure call, checked above
+                continue;
+            }
+            let function = &captures[2];
+            let lowercase = function.to_lowercase();
+
+            match lowercase.split_once('.') {
+                Some((namespace, _)) if !profile.function_namespaces.contains(&namespace) => {
+                    return Err(format!("function {function}() is not available in {self}"));
+                }
+                None if profile.unsupported_functions.contains(&lowercase.as_str()) => {
+                    return Err(format!("function {function}() is not available in {self}"));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_cypher_is_valid_everywhere() {
+        let query = "MATCH (m:Manager)-[:OWNS]->(s:Stock) WHERE toLower(s.sector) CONTAINS 'technology' \
+                     RETURN m, count(s) AS stocks";
+        for dialect in [Dialect::Falkordb, Dialect::Neo4j, Dialect::Memgraph, Dialect::OpenCypher] {
+            assert_eq!(dialect.validate(query), Ok(()), "{dialect}");
+        }
+    }
+
+    #[test]
+    fn test_foreign_procedures_are_rejected() {
+        let apoc = "MATCH (n:Person) CALL apoc.path.expand(n, 'KNOWS', null, 1, 3) YIELD path RETURN path";
+        assert_eq!(
+            Dialect::Falkordb.validate(apoc),
+            Err("procedure apoc.path.expand() is not available in falkordb".to_string())
+        );
+        assert_eq!(Dialect::Neo4j.validate(apoc), Ok(()));
+
+        let algo = "MATCH (a {name: 'A'}), (b {name: 'B'}) CALL algo.SPpaths({sourceNode: a, targetNode: b, \
+                    relTypes: ['ROAD'], weightProp: 'dist'}) YIELD path RETURN path";
+        assert_eq!(Dialect::Falkordb.validate(algo), Ok(()));
+        assert!(Dialect::Neo4j.validate(algo).is_err());
+        assert!(Dialect::OpenCypher.validate(algo).is_err());
+    }
+
+    #[test]
+    fn test_foreign_functions_are_rejected() {
+        let query = "MATCH (n) RETURN apoc.text.join([n.first, n.last], ' ')";
+        assert!(Dialect::Falkordb.validate(query).is_err());
+        assert_eq!(Dialect::Neo4j.validate(query), Ok(()));
+
+        let query = "MATCH p = allShortestPaths((a:City)-[:ROAD*]->(b:City)) RETURN p";
+        assert_eq!(Dialect::Falkordb.validate(query), Ok(()));
+        assert_eq!(
+            Dialect::Memgraph.validate(query),
+            Err("function allShortestPaths() is not available in memgraph".to_string())
+        );
+    }
+
+    #[test]
+    fn test_string_literals_are_ignored() {
+        let query = "MATCH (n) WHERE n.note = 'see apoc.help() and CALL algo.BFS()' RETURN n";
+        assert_eq!(Dialect::OpenCypher.validate(query), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_dialect() {
+        assert_eq!("neo4j".parse::<Dialect>(), Ok(Dialect::Neo4j));
+        assert_eq!("FalkorDB".parse::<Dialect>(), Ok(Dialect::Falkordb));
+        assert_eq!("open_cypher".parse::<Dialect>(), Ok(Dialect::OpenCypher));
+        assert_eq!("openCypher".parse::<Dialect>(), Ok(Dialect::OpenCypher));
+        assert_eq!(
+            serde_json::from_str::<Dialect>(r#""memgraph""#).unwrap(),
+            Dialect::Memgraph
+        );
+        assert_eq!(Dialect::OpenCypher.template_path(), "dialects/open_cypher.txt");
+    }
+}
@@ -21,6 +21,12 @@ pub enum ApiError {
     NotFound(String),
     #[allow(dead_code)]
     ServiceUnavailable(String),
+    Unauthorized(String),
+    Forbidden(String),
+    TooManyRequests {
+        message: String,
+        retry_after: std::time::Duration,
+    },
 }
 
 impl fmt::Display for ApiError {
@@ -34,6 +40,9 @@ impl fmt::Display for ApiError {
             Self::BadRequest(msg) => write!(f, "Bad request: {msg}"),
             Self::NotFound(msg) => write!(f, "Not found: {msg}"),
             Self::ServiceUnavailable(msg) => write!(f, "Service unavailable: {msg}"),
+            Self::Unauthorized(msg) => write!(f, "Unauthorized: {msg}"),
+            Self::Forbidden(msg) => write!(f, "Forbidden: {msg}"),
+            Self::TooManyRequests { message, .. } => write!(f, "Too many requests: {message}"),
         }
     }
 }
@@ -58,6 +67,9 @@ impl ResponseError for ApiError {
             Self::BadRequest(msg) => (400, "BAD_REQUEST", msg.clone()),
             Self::NotFound(msg) => (404, "NOT_FOUND", msg.clone()),
             Self::ServiceUnavailable(msg) => (503, "SERVICE_UNAVAILABLE", msg.clone()),
+            Self::Unauthorized(msg) => (401, "UNAUTHORIZED", msg.clone()),
+            Self::Forbidden(msg) => (403, "FORBIDDEN", msg.clone()),
+            Self::TooManyRequests { message, .. } => (429, "RATE_LIMITED", message.clone()),
         };
 
         let error_response = ErrorResponse {
@@ -66,7 +78,19 @@ impl ResponseError for ApiError {
             status_code,
         };
 
-        HttpResponse::build(actix_web::http::StatusCode::from_u16(status_code).unwrap()).json(error_response)
+        let mut response = HttpResponse::build(actix_web::http::StatusCode::from_u16(status_code).unwrap());
+        match self {
+            Self::Unauthorized(_) => {
+                response.insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Bearer"));
+            }
+            Self::TooManyRequests { retry_after, .. } => {
+                // Whole seconds, rounded up so clients never retry too early
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response.insert_header((actix_web::http::header::RETRY_AFTER, seconds.max(1)));
+            }
+            _ => {}
+        }
+        response.json(error_response)
     }
 }
 
@@ -77,6 +101,26 @@ impl From<genai::Error> for ApiError {
     }
 }
 
+impl From<crate::limits::Limited> for ApiError {
+    fn from(limited: crate::limits::Limited) -> Self {
+        Self::TooManyRequests {
+            message: limited.message,
+            retry_after: limited.retry_after,
+        }
+    }
+}
+
+impl From<crate::feedback::FeedbackError> for ApiError {
+    fn from(err: crate::feedback::FeedbackError) -> Self {
+        use crate::feedback::FeedbackError;
+        match err {
+            FeedbackError::UnknownRequest(_) | FeedbackError::UnknownFeedback(_) => Self::NotFound(err.to_string()),
+            FeedbackError::NotPromotable(_) => Self::BadRequest(err.to_string()),
+            FeedbackError::Storage(_) => Self::InternalServerError(err.to_string()),
+        }
+    }
+}
+
 // Helper functions for creating specific error types
 impl ApiError {
     #[allow(dead_code)]
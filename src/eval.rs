@@ -0,0 +1,689 @@
+//! Evaluation Harness Module
+//!
+//! Runs a dataset of questions through a running text-to-cypher service, so generation follows
+//! exactly the same path as `/text_to_cypher`, then executes the generated and the reference
+//! queries through a [`GraphBackend`] (`FalkorDB`, Bolt or a `memory:` fixture) and compares their
+//! result sets regardless of row order. Used by the
+//! `text-to-cypher-eval` binary and the `eval` subcommand to produce JSON and Markdown reports.
+//!
+//! ## Dataset Format
+//!
+//! One JSON object per line, with a reference query, expected rows, or both (expected rows win):
+//! `{"graph_name": "movies", "question": "Who directed Heat?", "reference_query": "MATCH ...", "expected_rows": [["Michael Mann"]]}`
+
+use crate::chat::{ChatMessage, ChatRequest, ChatRole};
+use crate::formatter::falkor_value_to_json;
+use crate::graph::{self, GraphBackend};
+use crate::usage::{RequestUsage, TokenUsage};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    #[serde(alias = "graph")]
+    pub graph_name: String,
+    pub question: String,
+    #[serde(default, alias = "reference_cypher")]
+    pub reference_query: Option<String>,
+    #[serde(default)]
+    pub expected_rows: Option<Vec<Vec<Value>>>,
+}
+
+/// Load a JSONL dataset, skipping blank lines and `#` comments
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is not a valid case.
+pub fn load_dataset(path: &Path) -> Result<Vec<EvalCase>, BoxError> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+    let mut cases = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let case: EvalCase =
+            serde_json::from_str(line).map_err(|e| format!("Invalid case on line {}: {e}", index + 1))?;
+        if case.reference_query.is_none() && case.expected_rows.is_none() {
+            return Err(format!(
+                "Case on line {} has neither reference_query nor expected_rows",
+                index + 1
+            )
+            .into());
+        }
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+/// A stable textual form of a value: numbers compare by value, objects by sorted keys
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Number(number) => number.as_f64().map_or_else(|| number.to_string(), |f| format!("{f:.6}")),
+        Value::Array(values) => format!("[{}]", values.iter().map(canonical).collect::<Vec<_>>().join(",")),
+        Value::Object(map) => {
+            let mut entries: Vec<String> =
+                map.iter().map(|(key, value)| format!("{key:?}:{}", canonical(value))).collect();
+            entries.sort();
+            format!("{{{}}}", entries.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Whether two result sets contain the same rows, ignoring row order
+#[must_use]
+pub fn rows_match(
+    actual: &[Vec<Value>],
+    expected: &[Vec<Value>],
+) -> bool {
+    let sorted = |rows: &[Vec<Value>]| {
+        let mut rows: Vec<String> = rows.iter().map(|row| canonical(&Value::Array(row.clone()))).collect();
+        rows.sort();
+        rows
+    };
+    actual.len() == expected.len() && sorted(actual) == sorted(expected)
+}
+
+/// Outcome of a single dataset case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub graph_name: String,
+    pub question: String,
+    /// Model reported by the service
+    pub model: Option<String>,
+    pub generated_query: Option<String>,
+    pub reference_query: Option<String>,
+    /// The generated query executed successfully
+    pub valid: bool,
+    /// The generated query returned the expected rows; `None` when the reference could not be run
+    pub correct: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time until the generated query was streamed back
+    pub generation_latency_ms: f64,
+    pub tokens: TokenUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalSummary {
+    pub cases: usize,
+    pub valid: usize,
+    pub correct: usize,
+    /// Cases whose correctness could be judged
+    pub judged: usize,
+    pub validity_rate: f64,
+    pub execution_accuracy: f64,
+    pub latency_mean_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub tokens: TokenUsage,
+    pub tokens_per_case: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub model: String,
+    pub dataset: String,
+    pub summary: EvalSummary,
+    pub results: Vec<CaseResult>,
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(
+    part: usize,
+    whole: usize,
+) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 / whole as f64 }
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn percentile(
+    sorted: &[f64],
+    p: f64,
+) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+impl EvalSummary {
+    #[must_use]
+    pub fn new(results: &[CaseResult]) -> Self {
+        let mut latencies: Vec<f64> = results.iter().map(|result| result.generation_latency_ms).collect();
+        latencies.sort_by(f64::total_cmp);
+
+        let mut tokens = TokenUsage::default();
+        for result in results {
+            tokens.add(&result.tokens);
+        }
+
+        let valid = results.iter().filter(|result| result.valid).count();
+        let judged = results.iter().filter(|result| result.correct.is_some()).count();
+        let correct = results.iter().filter(|result| result.correct == Some(true)).count();
+        #[allow(clippy::cast_precision_loss)]
+        let per_case = |total: f64| {
+            if results.is_empty() {
+                0.0
+            } else {
+                total / results.len() as f64
+            }
+        };
+
+        Self {
+            cases: results.len(),
+            valid,
+            correct,
+            judged,
+            validity_rate: ratio(valid, results.len()),
+            execution_accuracy: ratio(correct, judged),
+            latency_mean_ms: per_case(latencies.iter().sum()),
+            latency_p50_ms: percentile(&latencies, 0.5),
+            latency_p95_ms: percentile(&latencies, 0.95),
+            tokens,
+            #[allow(clippy::cast_precision_loss)]
+            tokens_per_case: per_case(tokens.total_tokens() as f64),
+        }
+    }
+}
+
+impl EvalReport {
+    #[must_use]
+    pub fn new(
+        model: String,
+        dataset: String,
+        results: Vec<CaseResult>,
+    ) -> Self {
+        Self {
+            model,
+            dataset,
+            summary: EvalSummary::new(&results),
+            results,
+        }
+    }
+
+    #[must_use]
+    pub fn to_markdown(&self) -> String {
+        let summary = &self.summary;
+        let mut md = String::new();
+        let _ = writeln!(md, "# Text to Cypher Evaluation\n");
+        let _ = writeln!(md, "- Model: `{}`", self.model);
+        let _ = writeln!(md, "- Dataset: `{}` ({} cases)\n", self.dataset, summary.cases);
+        let _ = writeln!(md, "| Metric | Value |");
+        let _ = writeln!(md, "|---|---|");
+        let _ = writeln!(
+            md,
+            "| Execution accuracy | {:.1}% ({}/{}) |",
+            summary.execution_accuracy * 100.0,
+            summary.correct,
+            summary.judged
+        );
+        let _ = writeln!(
+            md,
+            "| Validity rate | {:.1}% ({}/{}) |",
+            summary.validity_rate * 100.0,
+            summary.valid,
+            summary.cases
+        );
+        let _ = writeln!(
+            md,
+            "| Generation latency (mean / p50 / p95) | {:.0} / {:.0} / {:.0} ms |",
+            summary.latency_mean_ms, summary.latency_p50_ms, summary.latency_p95_ms
+        );
+        let _ = writeln!(
+            md,
+            "| Query generation tokens (prompt / completion) | {} / {} |",
+            summary.tokens.prompt_tokens, summary.tokens.completion_tokens
+        );
+        let _ = writeln!(md, "| Tokens per case | {:.0} |", summary.tokens_per_case);
+
+        let _ = writeln!(md, "\n## Cases\n");
+        let _ = writeln!(md, "| Graph | Question | Valid | Correct | Latency (ms) | Error |");
+        let _ = writeln!(md, "|---|---|---|---|---|---|");
+        for result in &self.results {
+            let correct = match result.correct {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "n/a",
+            };
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {:.0} | {} |",
+                escape_cell(&result.graph_name),
+                escape_cell(&result.question),
+                if result.valid { "yes" } else { "no" },
+                correct,
+                result.generation_latency_ms,
+                escape_cell(result.error.as_deref().unwrap_or(""))
+            );
+        }
+        md
+    }
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// What the service streamed back for a question
+#[derive(Debug, Default)]
+struct Generation {
+    query: Option<String>,
+    error: Option<String>,
+    usage: Option<RequestUsage>,
+    latency: Duration,
+}
+
+/// Runs dataset cases against a text-to-cypher service and a graph database
+pub struct Evaluator {
+    http: reqwest::Client,
+    graph: Arc<dyn GraphBackend>,
+    server_url: String,
+    connection: Option<String>,
+    model: Option<String>,
+    key: Option<String>,
+}
+
+impl Evaluator {
+    /// Connect to the graph database; the service itself is only contacted per case
+    ///
+    /// The service is called with `api_key` when given, and asked to use the configured
+    /// `connection` instead of its default when one is named.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection string or the API key is invalid, or the connection
+    /// fails.
+    pub async fn connect(
+        server_url: &str,
+        falkordb_connection: &str,
+        connection: Option<String>,
+        api_key: Option<&str>,
+        model: Option<String>,
+        key: Option<String>,
+    ) -> Result<Self, BoxError> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(api_key) = api_key {
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                reqwest::header::HeaderValue::from_str(&format!("Bearer {api_key}"))?,
+            );
+        }
+        let graph = graph::connect(falkordb_connection).await?;
+
+        Ok(Self {
+            http: reqwest::Client::builder().default_headers(headers).build()?,
+            graph,
+            server_url: server_url.trim_end_matches('/').to_string(),
+            connection,
+            model,
+            key,
+        })
+    }
+
+    /// Generate, execute and judge a single case
+    pub async fn run_case(
+        &self,
+        case: &EvalCase,
+    ) -> CaseResult {
+        let generation = self.generate(case).await.unwrap_or_else(|e| Generation {
+            error: Some(e.to_string()),
+            ..Generation::default()
+        });
+
+        let mut result = CaseResult {
+            graph_name: case.graph_name.clone(),
+            question: case.question.clone(),
+            model: generation.usage.as_ref().map(|usage| usage.model.clone()),
+            generated_query: generation.query.clone(),
+            reference_query: case.reference_query.clone(),
+            valid: false,
+            correct: None,
+            error: generation.error,
+            generation_latency_ms: generation.latency.as_secs_f64() * 1000.0,
+            tokens: generation
+                .usage
+                .as_ref()
+                .map(|usage| usage.query_generation)
+                .unwrap_or_default(),
+        };
+
+        let Some(query) = &generation.query else {
+            result.correct = Some(false);
+            result.error.get_or_insert_with(|| "No query was generated".to_string());
+            return result;
+        };
+
+        let actual = match self.execute(&case.graph_name, query).await {
+            Ok(rows) => {
+                result.valid = true;
+                rows
+            }
+            Err(e) => {
+                result.correct = Some(false);
+                result.error = Some(format!("Generated query failed: {e}"));
+                return result;
+            }
+        };
+
+        let expected = match (&case.expected_rows, &case.reference_query) {
+            (Some(rows), _) => Ok(rows.clone()),
+            (None, Some(reference)) => self
+                .execute(&case.graph_name, reference)
+                .await
+                .map_err(|e| format!("Reference query failed: {e}")),
+            (None, None) => Err("No reference to compare with".to_string()),
+        };
+        match expected {
+            Ok(expected) => result.correct = Some(rows_match(&actual, &expected)),
+            Err(e) => result.error = Some(e),
+        }
+
+        result
+    }
+
+    /// Send the question to `/text_to_cypher` and collect the generated query and token usage
+    async fn generate(
+        &self,
+        case: &EvalCase,
+    ) -> Result<Generation, BoxError> {
+        let chat_request = ChatRequest {
+            messages: vec![ChatMessage {
+                role: ChatRole::User,
+                content: case.question.clone(),
+            }],
+        };
+        let mut body = serde_json::json!({
+            "graph_name": case.graph_name,
+            "chat_request": chat_request,
+            "model": self.model,
+            "key": self.key,
+        });
+        if let Some(connection) = &self.connection {
+            body["falkordb_connection"] = Value::String(connection.clone());
+        }
+
+        let started = Instant::now();
+        let response = self
+            .http
+            .post(format!("{}/text_to_cypher", self.server_url))
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP request failed with status: {}", response.status()).into());
+        }
+
+        let mut generation = Generation::default();
+        let mut stream = response.bytes_stream();
+        let mut pending = String::new();
+        while let Some(chunk) = stream.next().await {
+            pending.push_str(&String::from_utf8_lossy(&chunk?));
+            while let Some(end) = pending.find('\n') {
+                let line: String = pending.drain(..=end).collect();
+                if let Some(data) = line.trim_end().strip_prefix("data: ") {
+                    handle_event(data, &mut generation, started);
+                }
+            }
+        }
+        Ok(generation)
+    }
+
+    async fn execute(
+        &self,
+        graph_name: &str,
+        query: &str,
+    ) -> Result<Vec<Vec<Value>>, BoxError> {
+        let records = self.graph.ro_query(graph_name, query).await?;
+        Ok(records
+            .iter()
+            .map(|record| record.iter().map(falkor_value_to_json).collect())
+            .collect())
+    }
+}
+
+fn handle_event(
+    data: &str,
+    generation: &mut Generation,
+    started: Instant,
+) {
+    let Ok(Value::Object(event)) = serde_json::from_str::<Value>(data) else {
+        return;
+    };
+
+    if let Some(query) = event.get("CypherQuery").and_then(Value::as_str) {
+        generation.query = Some(query.to_string());
+        generation.latency = started.elapsed();
+    } else if let Some(error) = event.get("Error").and_then(Value::as_str) {
+        // Errors after the query was generated are judged by executing it ourselves
+        if generation.query.is_none() {
+            generation.error = Some(error.to_string());
+            generation.latency = started.elapsed();
+        }
+    } else if let Some(usage) = event.get("Usage") {
+        generation.usage = serde_json::from_value(usage.clone()).ok();
+    }
+}
+
+/// Command-line options of an evaluation run
+#[derive(Debug, clap::Args)]
+pub struct EvalArgs {
+    /// JSONL dataset of `graph_name`, `question` and `reference_query` and/or `expected_rows`
+    pub dataset: PathBuf,
+
+    /// Base URL of the text-to-cypher service
+    #[arg(long, env = "TEXT_TO_CYPHER_URL", default_value = "http://127.0.0.1:8080")]
+    pub server: String,
+
+    /// Connection string the evaluator runs the generated and reference queries on: `FalkorDB`,
+    /// Bolt or a `memory:` fixture
+    #[arg(
+        long,
+        env = "FALKORDB_CONNECTION",
+        default_value = "falkor://127.0.0.1:6379",
+        hide_env_values = true
+    )]
+    pub falkordb_connection: String,
+
+    /// Name of a connection configured on the service to generate against; the service
+    /// default is used when omitted
+    #[arg(long)]
+    pub connection: Option<String>,
+
+    /// API key for the service, when it requires one
+    #[arg(long, env = "TEXT_TO_CYPHER_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+
+    /// Model to evaluate; the service default is used when omitted
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// API key for the model; the service default is used when omitted
+    #[arg(long, env = "EVAL_KEY", hide_env_values = true)]
+    pub key: Option<String>,
+
+    /// Write the full report as JSON
+    #[arg(long)]
+    pub json: Option<PathBuf>,
+
+    /// Write the report as Markdown
+    #[arg(long)]
+    pub markdown: Option<PathBuf>,
+}
+
+/// Evaluate every case of the dataset, print the Markdown report and write the requested files
+///
+/// # Errors
+///
+/// Returns an error if the dataset cannot be loaded, the graph database cannot be reached or a
+/// report cannot be written.
+pub async fn run(args: EvalArgs) -> Result<(), BoxError> {
+    let cases = load_dataset(&args.dataset)?;
+    tracing::info!("Evaluating {} cases from {}", cases.len(), args.dataset.display());
+
+    let evaluator = Evaluator::connect(
+        &args.server,
+        &args.falkordb_connection,
+        args.connection.clone(),
+        args.api_key.as_deref(),
+        args.model.clone(),
+        args.key.clone(),
+    )
+    .await?;
+
+    let mut results = Vec::with_capacity(cases.len());
+    for (index, case) in cases.iter().enumerate() {
+        let result = evaluator.run_case(case).await;
+        tracing::info!(
+            "[{}/{}] {} - valid: {}, correct: {:?}",
+            index + 1,
+            cases.len(),
+            case.question,
+            result.valid,
+            result.correct
+        );
+        results.push(result);
+    }
+
+    let model = args
+        .model
+        .or_else(|| results.iter().find_map(|result| result.model.clone()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let report = EvalReport::new(model, args.dataset.display().to_string(), results);
+    let markdown = report.to_markdown();
+
+    if let Some(path) = &args.json {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        tracing::info!("Wrote JSON report to {}", path.display());
+    }
+    if let Some(path) = &args.markdown {
+        std::fs::write(path, &markdown)?;
+        tracing::info!("Wrote Markdown report to {}", path.display());
+    }
+
+    println!("{markdown}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(
+        valid: bool,
+        correct: Option<bool>,
+        latency: f64,
+    ) -> CaseResult {
+        CaseResult {
+            graph_name: "movies".to_string(),
+            question: "q".to_string(),
+            model: None,
+            generated_query: None,
+            reference_query: None,
+            valid,
+            correct,
+            error: None,
+            generation_latency_ms: latency,
+            tokens: TokenUsage {
+                prompt_tokens: 100,
+                completion_tokens: 10,
+            },
+        }
+    }
+
+    #[test]
+    fn test_rows_match_ignores_order_and_number_types() {
+        let actual = vec![vec![json!("Al"), json!(2)], vec![json!("Bob"), json!(1.0)]];
+        let expected = vec![vec![json!("Bob"), json!(1)], vec![json!("Al"), json!(2.0)]];
+        assert!(rows_match(&actual, &expected));
+        assert!(!rows_match(&actual, &expected[..1]));
+        assert!(!rows_match(&[vec![json!("Al")]], &[vec![json!("al")]]));
+    }
+
+    #[test]
+    fn test_rows_match_duplicates() {
+        let actual = vec![vec![json!(1)], vec![json!(1)], vec![json!(2)]];
+        let expected = vec![vec![json!(1)], vec![json!(2)], vec![json!(2)]];
+        assert!(!rows_match(&actual, &expected));
+    }
+
+    #[test]
+    fn test_summary() {
+        let results = vec![
+            result(true, Some(true), 100.0),
+            result(true, Some(false), 200.0),
+            result(false, Some(false), 300.0),
+            result(true, None, 400.0),
+        ];
+        let summary = EvalSummary::new(&results);
+        assert_eq!(summary.valid, 3);
+        assert_eq!(summary.judged, 3);
+        assert!((summary.validity_rate - 0.75).abs() < f64::EPSILON);
+        assert!((summary.execution_accuracy - 1.0 / 3.0).abs() < f64::EPSILON);
+        assert!((summary.latency_mean_ms - 250.0).abs() < f64::EPSILON);
+        assert_eq!(summary.tokens.total_tokens(), 440);
+
+        let markdown = EvalReport::new("gpt-4o-mini".to_string(), "set.jsonl".to_string(), results).to_markdown();
+        assert!(markdown.contains("| Execution accuracy | 33.3% (1/3) |"));
+    }
+
+    #[test]
+    fn test_handle_event() {
+        let mut generation = Generation::default();
+        let started = Instant::now();
+        handle_event(r#"{"CypherQuery":"MATCH (n) RETURN n"}"#, &mut generation, started);
+        handle_event(r#"{"Error":"Query execution failed"}"#, &mut generation, started);
+        handle_event(
+            r#"{"Usage":{"model":"m","query_generation":{"prompt_tokens":5,"completion_tokens":3},"answer_generation":{"prompt_tokens":0,"completion_tokens":0}}}"#,
+            &mut generation,
+            started,
+        );
+        assert_eq!(generation.query.as_deref(), Some("MATCH (n) RETURN n"));
+        assert!(generation.error.is_none());
+        assert_eq!(generation.usage.unwrap().query_generation.total_tokens(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_execute_on_any_backend() {
+        let fixture = json!({
+            "movies": {
+                "nodes": [{ "labels": ["Movie"], "properties": { "title": "Heat" } }],
+                "queries": { "MATCH (m:Movie) RETURN m.title": [["Heat"]] }
+            }
+        });
+        let path = std::env::temp_dir().join(format!("eval-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, fixture.to_string()).unwrap();
+        let evaluator = Evaluator::connect(
+            "http://127.0.0.1:8080",
+            &format!("{}{}", graph::MEMORY_PREFIX, path.display()),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        std::fs::remove_file(path).unwrap();
+
+        let rows = evaluator
+            .unwrap()
+            .execute("movies", "MATCH (m:Movie) RETURN m.title")
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![vec![json!("Heat")]]);
+    }
+}
@@ -0,0 +1,381 @@
+//! Query Feedback Module
+//!
+//! Keeps recent interactions in memory so users can rate them by request id, stores the ratings
+//! (with an optional corrected query) in `feedback.jsonl`, and lets approved queries be promoted
+//! into a per-graph example set stored in `examples.jsonl`. Both files live in the feedback
+//! directory and are reloaded at startup.
+
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+const FEEDBACK_FILE: &str = "feedback.jsonl";
+const EXAMPLES_FILE: &str = "examples.jsonl";
+const INTERACTION_CAPACITY: u64 = 10_000;
+const INTERACTION_TTL: Duration = Duration::from_hours(24);
+
+/// What the pipeline produced for a request, kept until feedback arrives or it expires
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub graph_name: String,
+    pub question: String,
+    pub schema_fingerprint: String,
+    pub generated_query: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedbackRequest {
+    /// Request id reported in the `RequestId` progress event
+    pub request_id: String,
+    pub rating: Rating,
+    /// The query that should have been generated
+    pub corrected_query: Option<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedbackEntry {
+    pub id: String,
+    pub request_id: String,
+    pub timestamp: String,
+    pub graph_name: String,
+    pub question: String,
+    pub schema_fingerprint: String,
+    pub generated_query: String,
+    pub rating: Rating,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corrected_query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl FeedbackEntry {
+    /// The query worth keeping: the correction if given, otherwise the generated query if rated up
+    #[must_use]
+    pub fn approved_query(&self) -> Option<&str> {
+        match (&self.corrected_query, self.rating) {
+            (Some(corrected), _) => Some(corrected),
+            (None, Rating::Up) => Some(&self.generated_query),
+            (None, Rating::Down) => None,
+        }
+    }
+}
+
+/// A question/query pair promoted from feedback
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Example {
+    pub graph_name: String,
+    pub question: String,
+    pub query: String,
+    pub schema_fingerprint: String,
+    pub feedback_id: String,
+}
+
+#[derive(Debug)]
+pub enum FeedbackError {
+    UnknownRequest(String),
+    UnknownFeedback(String),
+    NotPromotable(String),
+    Storage(std::io::Error),
+}
+
+impl std::fmt::Display for FeedbackError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::UnknownRequest(id) => write!(f, "Unknown or expired request id: {id}"),
+            Self::UnknownFeedback(id) => write!(f, "Unknown feedback id: {id}"),
+            Self::NotPromotable(id) => write!(
+                f,
+                "Feedback {id} has no approved query; rate it up or provide a corrected query"
+            ),
+            Self::Storage(e) => write!(f, "Failed to store feedback: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FeedbackError {}
+
+impl From<std::io::Error> for FeedbackError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Storage(err)
+    }
+}
+
+#[derive(Debug)]
+pub struct FeedbackStore {
+    dir: PathBuf,
+    interactions: Cache<String, Interaction>,
+    feedback: RwLock<Vec<FeedbackEntry>>,
+    examples: RwLock<Vec<Example>>,
+}
+
+impl FeedbackStore {
+    /// Open the store in `dir`, loading previously stored feedback and examples
+    #[must_use]
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let feedback = load_jsonl(&dir.join(FEEDBACK_FILE));
+        let examples = load_jsonl(&dir.join(EXAMPLES_FILE));
+        tracing::info!(
+            "Loaded {} feedback entries and {} examples from {}",
+            feedback.len(),
+            examples.len(),
+            dir.display()
+        );
+
+        Self {
+            dir,
+            interactions: Cache::builder()
+                .max_capacity(INTERACTION_CAPACITY)
+                .time_to_live(INTERACTION_TTL)
+                .build(),
+            feedback: RwLock::new(feedback),
+            examples: RwLock::new(examples),
+        }
+    }
+
+    /// Remember what was generated for a request so it can receive feedback later
+    pub fn remember(
+        &self,
+        request_id: &str,
+        interaction: Interaction,
+    ) {
+        self.interactions.insert(request_id.to_string(), interaction);
+    }
+
+    /// The graph a recent request was made for
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request id is unknown.
+    pub fn graph_of(
+        &self,
+        request_id: &str,
+    ) -> Result<String, FeedbackError> {
+        self.interactions
+            .get(request_id)
+            .map(|interaction| interaction.graph_name)
+            .ok_or_else(|| FeedbackError::UnknownRequest(request_id.to_string()))
+    }
+
+    /// Record feedback for a recent request
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request id is unknown or the entry cannot be persisted.
+    pub fn submit(
+        &self,
+        request: FeedbackRequest,
+    ) -> Result<FeedbackEntry, FeedbackError> {
+        let interaction = self
+            .interactions
+            .get(&request.request_id)
+            .ok_or_else(|| FeedbackError::UnknownRequest(request.request_id.clone()))?;
+
+        let entry = FeedbackEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            request_id: request.request_id,
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            graph_name: interaction.graph_name,
+            question: interaction.question,
+            schema_fingerprint: interaction.schema_fingerprint,
+            generated_query: interaction.generated_query,
+            rating: request.rating,
+            corrected_query: request.corrected_query.filter(|query| !query.trim().is_empty()),
+            comment: request.comment,
+        };
+
+        append_jsonl(&self.dir.join(FEEDBACK_FILE), &entry)?;
+        write_lock(&self.feedback).push(entry.clone());
+        Ok(entry)
+    }
+
+    /// List feedback, optionally restricted to one graph
+    #[must_use]
+    pub fn list(
+        &self,
+        graph_name: Option<&str>,
+    ) -> Vec<FeedbackEntry> {
+        read_lock(&self.feedback)
+            .iter()
+            .filter(|entry| graph_name.is_none_or(|graph_name| entry.graph_name == graph_name))
+            .cloned()
+            .collect()
+    }
+
+    /// Promote the approved query of a feedback entry into its graph's example set
+    ///
+    /// Promoting the same feedback twice returns the existing example.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the feedback is unknown, has no approved query, or cannot be persisted.
+    pub fn promote(
+        &self,
+        feedback_id: &str,
+    ) -> Result<Example, FeedbackError> {
+        if let Some(example) = read_lock(&self.examples)
+            .iter()
+            .find(|example| example.feedback_id == feedback_id)
+        {
+            return Ok(example.clone());
+        }
+
+        let entry = read_lock(&self.feedback)
+            .iter()
+            .find(|entry| entry.id == feedback_id)
+            .cloned()
+            .ok_or_else(|| FeedbackError::UnknownFeedback(feedback_id.to_string()))?;
+        let query = entry
+            .approved_query()
+            .ok_or_else(|| FeedbackError::NotPromotable(feedback_id.to_string()))?
+            .to_string();
+
+        let example = Example {
+            graph_name: entry.graph_name,
+            question: entry.question,
+            query,
+            schema_fingerprint: entry.schema_fingerprint,
+            feedback_id: entry.id,
+        };
+
+        append_jsonl(&self.dir.join(EXAMPLES_FILE), &example)?;
+        write_lock(&self.examples).push(example.clone());
+        Ok(example)
+    }
+
+    /// Export the promoted examples of a graph
+    #[must_use]
+    pub fn examples(
+        &self,
+        graph_name: &str,
+    ) -> Vec<Example> {
+        read_lock(&self.examples)
+            .iter()
+            .filter(|example| example.graph_name == graph_name)
+            .cloned()
+            .collect()
+    }
+}
+
+fn read_lock<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn write_lock<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn load_jsonl<T: serde::de::DeserializeOwned>(path: &Path) -> Vec<T> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Vec::new();
+    };
+
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(&line) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::warn!("Skipping invalid line in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn append_jsonl<T: Serialize>(
+    path: &Path,
+    value: &T,
+) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction() -> Interaction {
+        Interaction {
+            graph_name: "movies".to_string(),
+            question: "Who directed Heat?".to_string(),
+            schema_fingerprint: "abc".to_string(),
+            generated_query: "MATCH (p:Person) RETURN p".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_feedback_roundtrip_and_promotion() {
+        let dir = std::env::temp_dir().join(format!("feedback-test-{}", uuid::Uuid::new_v4()));
+        let store = FeedbackStore::open(&dir);
+        store.remember("req-1", interaction());
+
+        let entry = store
+            .submit(FeedbackRequest {
+                request_id: "req-1".to_string(),
+                rating: Rating::Down,
+                corrected_query: Some("MATCH (p:Person)-[:DIRECTED]->(:Movie {title: 'Heat'}) RETURN p".to_string()),
+                comment: None,
+            })
+            .unwrap();
+
+        let example = store.promote(&entry.id).unwrap();
+        assert!(example.query.contains("DIRECTED"));
+        assert_eq!(store.promote(&entry.id).unwrap().feedback_id, entry.id);
+
+        // Everything is reloaded from disk
+        let reopened = FeedbackStore::open(&dir);
+        assert_eq!(reopened.list(Some("movies")).len(), 1);
+        assert_eq!(reopened.examples("movies").len(), 1);
+        assert!(reopened.examples("social").is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_request_and_unapproved_feedback() {
+        let dir = std::env::temp_dir().join(format!("feedback-test-{}", uuid::Uuid::new_v4()));
+        let store = FeedbackStore::open(&dir);
+        let request = FeedbackRequest {
+            request_id: "missing".to_string(),
+            rating: Rating::Down,
+            corrected_query: None,
+            comment: None,
+        };
+        assert!(matches!(
+            store.submit(request.clone()),
+            Err(FeedbackError::UnknownRequest(_))
+        ));
+
+        store.remember("missing", interaction());
+        let entry = store.submit(request).unwrap();
+        assert!(matches!(store.promote(&entry.id), Err(FeedbackError::NotPromotable(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
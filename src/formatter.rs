@@ -12,10 +12,209 @@
 //! - Single value: `"John Doe"`
 //! - Single record: `[(:Person {name: "John"}), 25, "Engineer"]`
 //! - Multiple records: `1. (:Person {name: "John"})\n2. (:Person {name: "Jane"})`
+//!
+//! ## Token Budget
+//!
+//! [`format_query_records_within_budget`] keeps the formatted output under an approximate
+//! token budget. When the full output does not fit, it falls back to a summary containing the
+//! row count, per-column aggregates and as many leading rows as the budget allows.
 
 use falkordb::FalkorValue;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Write;
 
+/// Rough number of characters per token used for budget estimation
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Formatted query results together with a flag telling whether they were summarized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormattedRecords {
+    /// The results as given to the model, summarized if they exceed the budget
+    pub text: String,
+    /// Every row of the results, as shown to the client
+    pub full_text: String,
+    pub truncated: bool,
+    pub row_count: usize,
+}
+
+/// Estimates the number of tokens a model will need for the given text
+#[must_use]
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Formats query results, switching to a summary when the full output exceeds `token_budget`
+#[must_use]
+pub fn format_query_records_within_budget(
+    records: &[Vec<FalkorValue>],
+    token_budget: usize,
+) -> FormattedRecords {
+    let full_text = format_query_records(records);
+    if estimate_tokens(&full_text) <= token_budget {
+        return FormattedRecords {
+            text: full_text.clone(),
+            full_text,
+            truncated: false,
+            row_count: records.len(),
+        };
+    }
+
+    FormattedRecords {
+        text: summarize_query_records(records, token_budget),
+        full_text,
+        truncated: true,
+        row_count: records.len(),
+    }
+}
+
+/// Summarizes query results as a row count, per-column aggregates and the first rows that fit the budget
+fn summarize_query_records(
+    records: &[Vec<FalkorValue>],
+    token_budget: usize,
+) -> String {
+    let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+    let mut summary = String::new();
+
+    writeln!(summary, "Column summary:").unwrap();
+    for column in 0..columns {
+        let mut stats = ColumnStats::default();
+        for record in records {
+            if let Some(value) = record.get(column) {
+                stats.add(value);
+            }
+        }
+        writeln!(summary, "- column {}: {stats}", column + 1).unwrap();
+    }
+
+    // Reserve room for the header line, then add rows while they still fit
+    let header_reserve = 32;
+    let mut remaining = token_budget.saturating_sub(estimate_tokens(&summary) + header_reserve);
+    let mut rows = String::new();
+    let mut shown = 0;
+    for (idx, record) in records.iter().enumerate() {
+        let row = format!("{}. {}\n", idx + 1, format_record(record));
+        let cost = estimate_tokens(&row);
+        if cost > remaining {
+            break;
+        }
+        remaining -= cost;
+        rows.push_str(&row);
+        shown += 1;
+    }
+
+    let mut res = format!(
+        "Result truncated: {} rows returned, showing the first {shown}.\n{summary}",
+        records.len()
+    );
+    if shown > 0 {
+        writeln!(res, "First {shown} rows:").unwrap();
+        res.push_str(&rows);
+    }
+    res.trim_end().to_string()
+}
+
+/// Formats a single record as either a bare value or an array of values
+fn format_record(record: &[FalkorValue]) -> String {
+    if record.len() == 1 {
+        format_falkor_value(&record[0])
+    } else {
+        let values: Vec<String> = record.iter().map(format_falkor_value).collect();
+        format!("[{}]", values.join(", "))
+    }
+}
+
+/// Aggregates collected for a single result column
+#[derive(Default)]
+struct ColumnStats {
+    count: usize,
+    nulls: usize,
+    numbers: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    strings: HashSet<String>,
+    trues: usize,
+    falses: usize,
+    kinds: BTreeMap<String, usize>,
+}
+
+impl ColumnStats {
+    fn add(
+        &mut self,
+        value: &FalkorValue,
+    ) {
+        self.count += 1;
+        match value {
+            FalkorValue::None => self.nulls += 1,
+            #[allow(clippy::cast_precision_loss)]
+            FalkorValue::I64(i) => self.add_number(*i as f64),
+            FalkorValue::F64(f) => self.add_number(*f),
+            FalkorValue::String(s) => {
+                self.strings.insert(s.clone());
+            }
+            FalkorValue::Bool(true) => self.trues += 1,
+            FalkorValue::Bool(false) => self.falses += 1,
+            FalkorValue::Node(node) => {
+                let kind = if node.labels.is_empty() {
+                    "node".to_string()
+                } else {
+                    format!(":{}", node.labels.join(":"))
+                };
+                *self.kinds.entry(kind).or_default() += 1;
+            }
+            FalkorValue::Edge(edge) => {
+                *self.kinds.entry(format!("[:{}]", edge.relationship_type)).or_default() += 1;
+            }
+            FalkorValue::Path(_) => *self.kinds.entry("path".to_string()).or_default() += 1,
+            FalkorValue::Array(_) => *self.kinds.entry("list".to_string()).or_default() += 1,
+            FalkorValue::Map(_) => *self.kinds.entry("map".to_string()).or_default() += 1,
+            _ => *self.kinds.entry("other".to_string()).or_default() += 1,
+        }
+    }
+
+    fn add_number(
+        &mut self,
+        number: f64,
+    ) {
+        self.numbers += 1;
+        self.sum += number;
+        self.min = Some(self.min.map_or(number, |min| min.min(number)));
+        self.max = Some(self.max.map_or(number, |max| max.max(number)));
+    }
+}
+
+impl std::fmt::Display for ColumnStats {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "{} values", self.count)?;
+        if self.nulls > 0 {
+            write!(f, ", {} nulls", self.nulls)?;
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            #[allow(clippy::cast_precision_loss)]
+            let avg = self.sum / self.numbers as f64;
+            write!(
+                f,
+                ", {} numbers (min: {min}, max: {max}, avg: {avg}, sum: {})",
+                self.numbers, self.sum
+            )?;
+        }
+        if !self.strings.is_empty() {
+            write!(f, ", {} distinct strings", self.strings.len())?;
+        }
+        if self.trues + self.falses > 0 {
+            write!(f, ", {} true / {} false", self.trues, self.falses)?;
+        }
+        for (kind, count) in &self.kinds {
+            write!(f, ", {count} x {kind}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Formats query results in a compact, LLM-friendly format
 pub fn format_query_records(records: &[Vec<FalkorValue>]) -> String {
     if records.is_empty() {
@@ -54,8 +253,51 @@ pub fn format_query_records(records: &[Vec<FalkorValue>]) -> String {
     }
 }
 
+/// Convert a `FalkorDB` value into JSON, e.g. to compare it with expected rows or to hash it
+#[must_use]
+pub fn falkor_value_to_json(value: &FalkorValue) -> Value {
+    let properties = |properties: &std::collections::HashMap<String, FalkorValue>| {
+        properties
+            .iter()
+            .map(|(key, value)| (key.clone(), falkor_value_to_json(value)))
+            .collect::<serde_json::Map<_, _>>()
+    };
+
+    match value {
+        FalkorValue::Node(node) => serde_json::json!({
+            "id": node.entity_id,
+            "labels": node.labels,
+            "properties": properties(&node.properties),
+        }),
+        FalkorValue::Edge(edge) => serde_json::json!({
+            "id": edge.entity_id,
+            "type": edge.relationship_type,
+            "src": edge.src_node_id,
+            "dst": edge.dst_node_id,
+            "properties": properties(&edge.properties),
+        }),
+        FalkorValue::Path(path) => serde_json::json!({
+            "nodes": path.nodes.iter().map(|node| falkor_value_to_json(&FalkorValue::Node(node.clone()))).collect::<Vec<_>>(),
+            "relationships": path.relationships.iter().map(|edge| falkor_value_to_json(&FalkorValue::Edge(edge.clone()))).collect::<Vec<_>>(),
+        }),
+        FalkorValue::Array(values) => Value::Array(values.iter().map(falkor_value_to_json).collect()),
+        FalkorValue::Map(map) => Value::Object(properties(map)),
+        FalkorValue::Vec32(vector) => serde_json::json!(vector.values),
+        FalkorValue::String(s) | FalkorValue::Unparseable(s) => Value::String(s.clone()),
+        FalkorValue::Bool(b) => Value::Bool(*b),
+        FalkorValue::I64(i) => serde_json::json!(i),
+        FalkorValue::F64(f) => serde_json::json!(f),
+        FalkorValue::Point(point) => serde_json::json!({
+            "latitude": point.latitude,
+            "longitude": point.longitude,
+        }),
+        FalkorValue::None => Value::Null,
+    }
+}
+
 /// Formats a single `FalkorDB` value in a readable, compact format
-fn format_falkor_value(value: &FalkorValue) -> String {
+#[must_use]
+pub fn format_falkor_value(value: &FalkorValue) -> String {
     match value {
         FalkorValue::Bool(b) => b.to_string(),
         FalkorValue::I64(i) => i.to_string(),
@@ -155,6 +397,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_single_record_multiple_fields() {
         let records = vec![vec![FalkorValue::I64(42), FalkorValue::Bool(true), FalkorValue::F64(3.14)]];
         assert_eq!(format_query_records(&records), "[42, true, 3.14]");
@@ -207,4 +450,32 @@ mod tests {
         let formatted = format_falkor_value(&value);
         assert_eq!(formatted, "[1, 2, 3]");
     }
+
+    #[test]
+    fn test_within_budget_not_truncated() {
+        let records = vec![vec![FalkorValue::I64(1)], vec![FalkorValue::I64(2)]];
+        let formatted = format_query_records_within_budget(&records, 100);
+        assert!(!formatted.truncated);
+        assert_eq!(formatted.text, "1. 1\n2. 2");
+    }
+
+    #[test]
+    fn test_over_budget_summarized() {
+        let records: Vec<Vec<FalkorValue>> = (1..=1000)
+            .map(|i| vec![FalkorValue::I64(i), FalkorValue::String(format!("name-{}", i % 10))])
+            .collect();
+        let formatted = format_query_records_within_budget(&records, 200);
+        assert!(formatted.truncated);
+        assert!(formatted.text.starts_with("Result truncated: 1000 rows returned"));
+        assert!(
+            formatted
+                .text
+                .contains("column 1: 1000 values, 1000 numbers (min: 1, max: 1000, avg: 500.5")
+        );
+        assert!(formatted.text.contains("column 2: 1000 values, 10 distinct strings"));
+        assert!(formatted.text.contains("1. [1, \"name-1\"]"));
+        assert!(!formatted.text.contains("1000. "));
+        assert!(estimate_tokens(&formatted.text) <= 200);
+        assert!(formatted.full_text.ends_with("1000. [1000, \"name-0\"]"));
+    }
 }
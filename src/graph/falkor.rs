@@ -0,0 +1,71 @@
+//! `FalkorDB` graph backend
+
+use super::{GraphBackend, GraphError, Records, first_column_strings};
+use async_trait::async_trait;
+use falkordb::{FalkorAsyncClient, FalkorClientBuilder, FalkorConnectionInfo};
+
+pub struct FalkorGraphBackend {
+    client: FalkorAsyncClient,
+}
+
+impl FalkorGraphBackend {
+    /// # Errors
+    ///
+    /// Returns an error if the connection string is invalid or the client cannot be built.
+    pub async fn connect(connection: &str) -> Result<Self, GraphError> {
+        let connection_info: FalkorConnectionInfo =
+            connection.try_into().map_err(|e| format!("Invalid connection info: {e}"))?;
+
+        let client = FalkorClientBuilder::new_async()
+            .with_connection_info(connection_info)
+            .build()
+            .await
+            .map_err(|e| format!("Failed to build client: {e}"))?;
+
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl GraphBackend for FalkorGraphBackend {
+    async fn list_graphs(&self) -> Result<Vec<String>, GraphError> {
+        Ok(self.client.list_graphs().await?)
+    }
+
+    async fn ro_query(
+        &self,
+        graph_name: &str,
+        query: &str,
+    ) -> Result<Records, GraphError> {
+        let mut graph = self.client.select_graph(graph_name);
+        let result = graph.ro_query(query).execute().await?;
+        Ok(result.data.collect())
+    }
+
+    async fn labels(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        Ok(first_column_strings(
+            self.ro_query(graph_name, "CALL db.labels()").await?,
+        ))
+    }
+
+    async fn relationship_types(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        Ok(first_column_strings(
+            self.ro_query(graph_name, "CALL db.relationshipTypes()").await?,
+        ))
+    }
+
+    async fn procedures(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        Ok(first_column_strings(
+            self.ro_query(graph_name, "CALL dbms.procedures()").await?,
+        ))
+    }
+}
@@ -0,0 +1,360 @@
+//! In-memory graph backend for tests and local development
+//!
+//! Holds graphs as plain lists of nodes and edges. It answers the queries issued by schema
+//! discovery from that data, and any other query from a table of canned results, so the whole
+//! pipeline can run without a database.
+//!
+//! ## Fixture Format
+//!
+//! ```json
+//! {
+//!   "movies": {
+//!     "nodes": [
+//!       { "labels": ["Person"], "properties": { "name": "Michael Mann" } },
+//!       { "labels": ["Movie"], "properties": { "title": "Heat", "year": 1995 } }
+//!     ],
+//!     "edges": [{ "type": "DIRECTED", "src": 0, "dst": 1 }],
+//!     "queries": {
+//!       "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name": [["Michael Mann"]]
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Edges refer to nodes by their position in `nodes`; canned queries are matched after
+//! collapsing whitespace.
+
+use super::{GraphBackend, GraphError, Records, property_type_rows};
+use async_trait::async_trait;
+use falkordb::{Edge, FalkorValue};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+static NODE_SAMPLING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^MATCH \(a:(\w+)\) CALL .* LIMIT (\d+) ").expect("Pattern should be valid"));
+static RELATIONSHIP_SAMPLING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^MATCH \(\)-\[a:(\w+)\]->\(\) CALL .* LIMIT (\d+) ").expect("Pattern should be valid")
+});
+static RELATIONSHIP_EXISTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^MATCH \(s:(\w+)\)-\[a:(\w+)\]->\(t:(\w+)\) return a limit 1$").expect("Pattern should be valid")
+});
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryNode {
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub properties: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryEdge {
+    #[serde(rename = "type")]
+    pub relationship_type: String,
+    pub src: usize,
+    pub dst: usize,
+    #[serde(default)]
+    pub properties: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryGraph {
+    #[serde(default)]
+    pub nodes: Vec<MemoryNode>,
+    #[serde(default)]
+    pub edges: Vec<MemoryEdge>,
+    /// Canned results of queries that are not part of schema discovery
+    #[serde(default)]
+    pub queries: HashMap<String, Vec<Vec<Value>>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryGraphBackend {
+    graphs: BTreeMap<String, MemoryGraph>,
+}
+
+impl MemoryGraphBackend {
+    #[cfg(test)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn with_graph(
+        mut self,
+        name: impl Into<String>,
+        graph: MemoryGraph,
+    ) -> Self {
+        self.graphs.insert(name.into(), graph);
+        self
+    }
+
+    /// Load graphs from a JSON fixture file, as `memory:<path>` connections do
+    ///
+    /// Fixtures are served outside tests too, for local development and demos, but only from
+    /// connections in the configuration: requests may not name one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn from_file(path: &str) -> Result<Self, GraphError> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read graph fixture {path}: {e}"))?;
+        let graphs = serde_json::from_str(&content).map_err(|e| format!("Invalid graph fixture {path}: {e}"))?;
+        Ok(Self { graphs })
+    }
+
+    fn graph(
+        &self,
+        graph_name: &str,
+    ) -> Result<&MemoryGraph, GraphError> {
+        self.graphs
+            .get(graph_name)
+            .ok_or_else(|| format!("Graph '{graph_name}' does not exist").into())
+    }
+}
+
+impl MemoryGraph {
+    #[allow(clippy::cast_possible_wrap)]
+    fn edge(
+        &self,
+        index: usize,
+    ) -> Edge {
+        let edge = &self.edges[index];
+        Edge {
+            entity_id: index as i64,
+            relationship_type: edge.relationship_type.clone(),
+            src_node_id: edge.src as i64,
+            dst_node_id: edge.dst as i64,
+            properties: to_falkor_properties(&edge.properties),
+        }
+    }
+
+    fn has_label(
+        &self,
+        index: usize,
+        label: &str,
+    ) -> bool {
+        self.nodes.get(index).is_some_and(|node| node.labels.iter().any(|l| l == label))
+    }
+
+    /// Key/type pairs and their counts over the first `limit` property maps, as returned by the
+    /// attribute sampling queries of schema discovery
+    fn sample_attributes<'a>(
+        properties: impl Iterator<Item = &'a serde_json::Map<String, Value>>,
+        limit: usize,
+    ) -> Records {
+        let mut counts: BTreeMap<(String, &'static str), i64> = BTreeMap::new();
+        for properties in properties.take(limit) {
+            for (key, value) in properties {
+                *counts.entry((key.clone(), type_name(value))).or_default() += 1;
+            }
+        }
+
+        property_type_rows(counts)
+    }
+
+    fn query(
+        &self,
+        query: &str,
+    ) -> Result<Records, GraphError> {
+        let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if let Some((_, rows)) = self
+            .queries
+            .iter()
+            .find(|(canned, _)| canned.split_whitespace().collect::<Vec<_>>().join(" ") == query)
+        {
+            return Ok(rows.iter().map(|row| row.iter().map(json_to_falkor_value).collect()).collect());
+        }
+
+        if let Some(captures) = NODE_SAMPLING.captures(&query) {
+            let label = &captures[1];
+            let nodes = self
+                .nodes
+                .iter()
+                .filter(|node| node.labels.iter().any(|l| l == label))
+                .map(|node| &node.properties);
+            return Ok(Self::sample_attributes(nodes, captures[2].parse()?));
+        }
+
+        if let Some(captures) = RELATIONSHIP_SAMPLING.captures(&query) {
+            let relationship_type = &captures[1];
+            let edges = self
+                .edges
+                .iter()
+                .filter(|edge| edge.relationship_type == relationship_type)
+                .map(|edge| &edge.properties);
+            return Ok(Self::sample_attributes(edges, captures[2].parse()?));
+        }
+
+        if let Some(captures) = RELATIONSHIP_EXISTS.captures(&query) {
+            let (source, relationship_type, target) = (&captures[1], &captures[2], &captures[3]);
+            return Ok(self
+                .edges
+                .iter()
+                .position(|edge| {
+                    edge.relationship_type == relationship_type
+                        && self.has_label(edge.src, source)
+                        && self.has_label(edge.dst, target)
+                })
+                .map(|index| vec![vec![FalkorValue::Edge(self.edge(index))]])
+                .unwrap_or_default());
+        }
+
+        Err(format!("Query not supported by the in-memory backend: {query}").into())
+    }
+}
+
+#[async_trait]
+impl GraphBackend for MemoryGraphBackend {
+    async fn list_graphs(&self) -> Result<Vec<String>, GraphError> {
+        Ok(self.graphs.keys().cloned().collect())
+    }
+
+    async fn ro_query(
+        &self,
+        graph_name: &str,
+        query: &str,
+    ) -> Result<Records, GraphError> {
+        self.graph(graph_name)?.query(query)
+    }
+
+    async fn labels(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        let graph = self.graph(graph_name)?;
+        let mut labels: Vec<String> = graph.nodes.iter().flat_map(|node| node.labels.clone()).collect();
+        labels.sort();
+        labels.dedup();
+        Ok(labels)
+    }
+
+    async fn relationship_types(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        let graph = self.graph(graph_name)?;
+        let mut types: Vec<String> = graph.edges.iter().map(|edge| edge.relationship_type.clone()).collect();
+        types.sort();
+        types.dedup();
+        Ok(types)
+    }
+
+    async fn procedures(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError> {
+        self.graph(graph_name)?;
+        Ok(Vec::new())
+    }
+}
+
+/// Name of the value's type, as accepted by `AttributeType`
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "Boolean",
+        Value::Number(number) if number.is_f64() => "Float",
+        Value::Number(_) => "Integer",
+        Value::Array(_) => "List",
+        Value::Object(_) => "Map",
+        Value::String(_) | Value::Null => "String",
+    }
+}
+
+fn to_falkor_properties(properties: &serde_json::Map<String, Value>) -> HashMap<String, FalkorValue> {
+    properties
+        .iter()
+        .map(|(key, value)| (key.clone(), json_to_falkor_value(value)))
+        .collect()
+}
+
+/// Convert a JSON value from a fixture into a `FalkorDB` value
+#[must_use]
+pub fn json_to_falkor_value(value: &Value) -> FalkorValue {
+    match value {
+        Value::Null => FalkorValue::None,
+        Value::Bool(b) => FalkorValue::Bool(*b),
+        Value::Number(number) => number.as_i64().map_or_else(
+            || FalkorValue::F64(number.as_f64().unwrap_or_default()),
+            FalkorValue::I64,
+        ),
+        Value::String(s) => FalkorValue::String(s.clone()),
+        Value::Array(values) => FalkorValue::Array(values.iter().map(json_to_falkor_value).collect()),
+        Value::Object(map) => FalkorValue::Map(to_falkor_properties(map)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn movies() -> MemoryGraphBackend {
+        let graphs = json!({
+            "movies": {
+                "nodes": [
+                    { "labels": ["Person"], "properties": { "name": "Michael Mann", "born": 1943 } },
+                    { "labels": ["Movie"], "properties": { "title": "Heat", "rating": 8.3 } },
+                    { "labels": ["Person"], "properties": { "name": "Al Pacino" } }
+                ],
+                "edges": [
+                    { "type": "DIRECTED", "src": 0, "dst": 1 },
+                    { "type": "ACTED_IN", "src": 2, "dst": 1, "properties": { "role": "Vincent Hanna" } }
+                ],
+                "queries": {
+                    "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name": [["Michael Mann"]]
+                }
+            }
+        });
+        MemoryGraphBackend {
+            graphs: serde_json::from_value(graphs).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_labels_and_types() {
+        let backend = movies();
+        assert_eq!(backend.list_graphs().await.unwrap(), vec!["movies"]);
+        assert_eq!(backend.labels("movies").await.unwrap(), vec!["Movie", "Person"]);
+        assert_eq!(
+            backend.relationship_types("movies").await.unwrap(),
+            vec!["ACTED_IN", "DIRECTED"]
+        );
+        assert!(backend.procedures("movies").await.unwrap().is_empty());
+        assert!(backend.labels("missing").await.is_err());
+        assert!(backend.procedures("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_canned_and_unsupported_queries() {
+        let backend = movies();
+        let rows = backend
+            .ro_query("movies", "MATCH (p:Person)-[:DIRECTED]->(m:Movie)\n  RETURN p.name")
+            .await
+            .unwrap();
+        assert!(
+            matches!(rows.as_slice(), [row] if matches!(row.as_slice(), [FalkorValue::String(name)] if name == "Michael Mann"))
+        );
+        assert!(backend.ro_query("movies", "MATCH (n) DELETE n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relationship_exists() {
+        let backend = movies();
+        let found = backend
+            .ro_query("movies", "MATCH (s:Person)-[a:DIRECTED]->(t:Movie) return a limit 1")
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        let missing = backend
+            .ro_query("movies", "MATCH (s:Movie)-[a:DIRECTED]->(t:Person) return a limit 1")
+            .await
+            .unwrap();
+        assert!(missing.is_empty());
+    }
+}
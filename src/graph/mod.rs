@@ -0,0 +1,305 @@
+//! Graph Backend Module
+//!
+//! Abstracts the graph database behind the [`GraphBackend`] trait. Schema discovery, query
+//! execution and graph listing all go through it, with results expressed as rows of
+//! `FalkorValue`, the value model shared by every backend and the formatter.
+//!
+//! The backend is picked from the connection URL: `memory:<fixture.json>` serves an in-memory
+//! fixture, `bolt://`, `neo4j://` and `memgraph://` (with their `+s`/`+ssc` variants) connect over
+//! Bolt, and anything else is a `FalkorDB` connection string.
+
+pub mod bolt;
+pub mod falkor;
+pub mod memory;
+
+pub use bolt::{BoltFlavor, BoltGraphBackend};
+pub use falkor::FalkorGraphBackend;
+pub use memory::MemoryGraphBackend;
+
+use crate::dialect::{Dialect, STRING_LITERAL};
+use async_trait::async_trait;
+use falkordb::FalkorValue;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock};
+
+pub type GraphError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Query result rows
+pub type Records = Vec<Vec<FalkorValue>>;
+
+/// Prefix selecting the in-memory backend, e.g. `memory:tests/movies.json`
+pub const MEMORY_PREFIX: &str = "memory:";
+
+static RETURN_KEYWORD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bRETURN\s+(?:DISTINCT\s+)?").expect("Pattern should be valid"));
+static RETURN_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:ORDER\s+BY|SKIP|LIMIT|UNION)\b").expect("Pattern should be valid"));
+static ALIAS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)^(.*?)\s+AS\s+(\S+)$").expect("Pattern should be valid"));
+static PROPERTY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:`[^`]+`|\w+)\s*\.\s*(?:`([^`]+)`|(\w+))$").expect("Pattern should be valid"));
+
+/// Whether sampled properties belong to nodes or relationships
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Node,
+    Relationship,
+}
+
+#[async_trait]
+pub trait GraphBackend: Send + Sync {
+    async fn list_graphs(&self) -> Result<Vec<String>, GraphError>;
+
+    /// Run a read-only query
+    async fn ro_query(
+        &self,
+        graph_name: &str,
+        query: &str,
+    ) -> Result<Records, GraphError>;
+
+    /// Node labels of the graph
+    async fn labels(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError>;
+
+    async fn relationship_types(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError>;
+
+    /// Key/type pairs found on up to `sample_size` elements with `label`, as rows of
+    /// `[[key, type], count]` ordered by key
+    ///
+    /// The default implementation runs the `FalkorDB` sampling query, which relies on `typeof`.
+    async fn property_types(
+        &self,
+        graph_name: &str,
+        kind: ElementKind,
+        label: &str,
+        sample_size: usize,
+    ) -> Result<Records, GraphError> {
+        let pattern = match kind {
+            ElementKind::Node => format!("(a:{label})"),
+            ElementKind::Relationship => format!("()-[a:{label}]->()"),
+        };
+        let query = format!(
+            r"
+            MATCH {pattern}
+            CALL {{
+                WITH a
+                RETURN [k IN keys(a) | [k, typeof(a[k])]] AS types
+            }}
+            WITH types
+            LIMIT {sample_size}
+            UNWIND types AS kt
+            RETURN kt, count(1)
+            ORDER BY kt[0]
+            "
+        );
+
+        tracing::info!("Collecting attributes for label '{}': {}", label, query);
+        self.ro_query(graph_name, &query).await
+    }
+
+    /// Names of the procedures that can be called in queries
+    #[allow(dead_code)]
+    async fn procedures(
+        &self,
+        graph_name: &str,
+    ) -> Result<Vec<String>, GraphError>;
+}
+
+/// Connect to the backend described by `connection`
+///
+/// `memory:` fixtures are served here for any caller, so requests naming one are refused before
+/// they get this far; see `Principal::authorize_connection`.
+///
+/// # Errors
+///
+/// Returns an error if the connection string is invalid or the connection fails.
+pub async fn connect(connection: &str) -> Result<Arc<dyn GraphBackend>, GraphError> {
+    if let Some(fixture) = connection.strip_prefix(MEMORY_PREFIX) {
+        return Ok(Arc::new(MemoryGraphBackend::from_file(fixture)?));
+    }
+
+    if bolt::flavor_of_connection(connection).is_some() {
+        return Ok(Arc::new(BoltGraphBackend::connect(connection).await?));
+    }
+
+    Ok(Arc::new(FalkorGraphBackend::connect(connection).await?))
+}
+
+/// The Cypher dialect of the backend described by `connection`
+#[must_use]
+pub fn dialect_for(connection: &str) -> Dialect {
+    match bolt::flavor_of_connection(connection) {
+        Some(BoltFlavor::Neo4j) => Dialect::Neo4j,
+        Some(BoltFlavor::Memgraph) => Dialect::Memgraph,
+        // The in-memory backend stands in for FalkorDB
+        None => Dialect::Falkordb,
+    }
+}
+
+/// An item of a `RETURN` clause, as written in the query
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Column {
+    /// The returned expression, e.g. `count(m)`
+    pub expression: String,
+    /// The property read, for `variable.property` items
+    pub property: Option<String>,
+    pub alias: Option<String>,
+}
+
+impl Column {
+    /// The column name the database reports: the alias, or the expression as written
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.expression)
+    }
+}
+
+/// The columns of the query's final `RETURN` clause, in order
+#[must_use]
+pub fn return_columns(query: &str) -> Vec<Column> {
+    // Blank out string literals without moving anything, so offsets still match the query
+    let blanked = STRING_LITERAL.replace_all(query, |captures: &regex::Captures| {
+        let literal = &captures[0];
+        format!("{0}{1}{0}", &literal[..1], "_".repeat(literal.len() - 2))
+    });
+    let Some(clause) = RETURN_KEYWORD.find_iter(&blanked).last() else {
+        return Vec::new();
+    };
+    let end = RETURN_END
+        .find(&blanked[clause.end()..])
+        .map_or(blanked.len(), |end| clause.end() + end.start());
+
+    split_top_level(&query[clause.end()..end])
+        .into_iter()
+        .map(|item| {
+            let (expression, alias) = ALIAS.captures(item).map_or((item, None), |captures| {
+                let expression = captures.get(1).map_or(item, |m| m.as_str().trim());
+                (expression, Some(captures[2].trim_matches('`').to_string()))
+            });
+            let property = PROPERTY
+                .captures(expression)
+                .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+                .map(|m| m.as_str().to_string());
+            Column {
+                expression: expression.to_string(),
+                property,
+                alias,
+            }
+        })
+        .collect()
+}
+
+/// Split on commas outside of brackets and string literals
+fn split_top_level(items: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (index, c) in items.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(items[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(items[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// The string values of the first column
+fn first_column_strings(records: Records) -> Vec<String> {
+    records
+        .into_iter()
+        .filter_map(|record| match record.into_iter().next() {
+            Some(FalkorValue::String(value)) => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Rows of `[[key, type], count]` in the shape returned by [`GraphBackend::property_types`]
+fn property_type_rows(counts: BTreeMap<(String, &'static str), i64>) -> Records {
+    counts
+        .into_iter()
+        .map(|((key, type_name), count)| {
+            vec![
+                FalkorValue::Array(vec![
+                    FalkorValue::String(key),
+                    FalkorValue::String(type_name.to_string()),
+                ]),
+                FalkorValue::I64(count),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_return_columns() {
+        let names = |query: &str| -> Vec<String> {
+            return_columns(query).iter().map(|column| column.name().to_string()).collect()
+        };
+        assert_eq!(
+            names("MATCH (p:Person)-[:DIRECTED]->(m) RETURN p.name AS director, count(m), m"),
+            vec!["director", "count(m)", "m"]
+        );
+        assert_eq!(
+            names(
+                "CALL { MATCH (n) RETURN n } RETURN DISTINCT n.name, [x IN [1, 2] | x] AS `list` ORDER BY n.name LIMIT 5"
+            ),
+            vec!["n.name", "list"]
+        );
+        assert_eq!(
+            names("MATCH (n) WHERE n.name = 'a, b' RETURN n.name = 'a, b' AS matches, n"),
+            vec!["matches", "n"]
+        );
+        assert_eq!(
+            names("MATCH (n) RETURN n.name, 'RETURN x LIMIT 1' AS note"),
+            vec!["n.name", "note"]
+        );
+        assert!(return_columns("CREATE (n)").is_empty());
+
+        assert_eq!(
+            return_columns("MATCH (p:Person) WITH p RETURN p.name, p.`ssn` AS id, count(p) AS n, p ORDER BY p.name"),
+            vec![
+                Column {
+                    expression: "p.name".to_string(),
+                    property: Some("name".to_string()),
+                    alias: None,
+                },
+                Column {
+                    expression: "p.`ssn`".to_string(),
+                    property: Some("ssn".to_string()),
+                    alias: Some("id".to_string()),
+                },
+                Column {
+                    expression: "count(p)".to_string(),
+                    property: None,
+                    alias: Some("n".to_string()),
+                },
+                Column {
+                    expression: "p".to_string(),
+                    property: None,
+                    alias: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Liveness and readiness checks
+//!
+//! `/healthz` only tells an orchestrator that the process answers. `/readyz` runs the checks
+//! here — every graph database connection, the templates, the default model and key, and the MCP
+//! server — and only says whether all of them passed; admins get each result from
+//! `/readyz/details`, so a failing dependency can be told apart from the rest. Results are reused
+//! for [`READINESS_TTL`], so frequent probes do not open a connection to every database each time.
+
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::graph;
+use crate::template::TemplateEngine;
+
+/// How long a connection check may take before it fails
+pub const CONNECTION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the results of the dependency checks are reused
+pub const READINESS_TTL: Duration = Duration::from_secs(5);
+
+static LAST_CHECKS: Mutex<Option<(Instant, Vec<Check>)>> = Mutex::const_new(None);
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// Not applicable to this deployment; does not affect readiness
+    Skip,
+}
+
+/// A named check with its outcome
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Check {
+    /// E.g. `templates` or `connection:replica`
+    pub name: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+impl Check {
+    fn new(
+        name: impl Into<String>,
+        status: CheckStatus,
+        detail: Option<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail,
+            duration_ms: None,
+        }
+    }
+
+    /// A failed check with the reason
+    #[must_use]
+    pub fn failed(
+        name: &str,
+        detail: &str,
+    ) -> Self {
+        Self::new(name, CheckStatus::Fail, Some(detail.to_string()))
+    }
+}
+
+/// Response of `GET /readyz`
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct ReadinessStatus {
+    /// Whether no check failed
+    pub ready: bool,
+}
+
+/// Response of `GET /readyz/details`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Readiness {
+    /// Whether no check failed
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Readiness {
+    #[must_use]
+    pub fn new(checks: Vec<Check>) -> Self {
+        let ready = checks.iter().all(|check| check.status != CheckStatus::Fail);
+        Self { ready, checks }
+    }
+}
+
+/// The results of `run` if none were kept in the last [`READINESS_TTL`], else the kept ones
+///
+/// Concurrent callers wait for the same run rather than starting their own.
+pub async fn cached_checks<F, Fut>(run: F) -> Vec<Check>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Vec<Check>>,
+{
+    let mut last = LAST_CHECKS.lock().await;
+    if let Some((checked_at, checks)) = last.as_ref()
+        && checked_at.elapsed() < READINESS_TTL
+    {
+        return checks.clone();
+    }
+    let checks = run().await;
+    *last = Some((Instant::now(), checks.clone()));
+    checks
+}
+
+/// State of the MCP server for the readiness check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum McpState {
+    /// Not started, for the given reason
+    Disabled(String),
+    Running,
+    /// Started but no longer running, with the error it stopped on
+    Stopped(String),
+}
+
+static MCP_STATE: RwLock<McpState> = RwLock::new(McpState::Disabled(String::new()));
+
+/// Record the state of the MCP server
+pub fn set_mcp_state(state: McpState) {
+    if let Ok(mut current) = MCP_STATE.write() {
+        *current = state;
+    }
+}
+
+/// Report the state of the MCP server; a disabled server is skipped, a stopped one fails
+#[must_use]
+pub fn check_mcp() -> Check {
+    let state = MCP_STATE.read().map_or_else(|e| e.into_inner().clone(), |state| state.clone());
+    match state {
+        McpState::Disabled(reason) => Check::new("mcp", CheckStatus::Skip, Some(reason).filter(|r| !r.is_empty())),
+        McpState::Running => Check::new("mcp", CheckStatus::Pass, None),
+        McpState::Stopped(error) => Check::new("mcp", CheckStatus::Fail, Some(error)),
+    }
+}
+
+/// Check that every template still loads and only uses the variables it is given
+#[must_use]
+pub fn check_templates() -> Check {
+    match TemplateEngine::check() {
+        Ok(()) => Check::new("templates", CheckStatus::Pass, None),
+        Err(e) => Check::new("templates", CheckStatus::Fail, Some(e.to_string())),
+    }
+}
+
+/// Check that a setting the service needs by default, e.g. `DEFAULT_MODEL`, is configured
+#[must_use]
+pub fn check_configured(
+    name: &str,
+    configured: bool,
+) -> Check {
+    if configured {
+        Check::new(name, CheckStatus::Pass, None)
+    } else {
+        Check::new(name, CheckStatus::Fail, Some("not configured".to_string()))
+    }
+}
+
+/// Connect to the graph database and list its graphs, within [`CONNECTION_CHECK_TIMEOUT`]
+pub async fn check_connection(
+    name: &str,
+    connection: &str,
+) -> Check {
+    let started = Instant::now();
+    let result = tokio::time::timeout(CONNECTION_CHECK_TIMEOUT, async {
+        graph::connect(connection).await?.list_graphs().await
+    })
+    .await;
+    let (status, detail) = match result {
+        Ok(Ok(_)) => (CheckStatus::Pass, None),
+        Ok(Err(e)) => (CheckStatus::Fail, Some(e.to_string())),
+        Err(_) => (
+            CheckStatus::Fail,
+            Some(format!("timed out after {}s", CONNECTION_CHECK_TIMEOUT.as_secs())),
+        ),
+    };
+    Check {
+        duration_ms: Some(u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX)),
+        ..Check::new(format!("connection:{name}"), status, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let skipped = Check::new("mcp", CheckStatus::Skip, Some("DEFAULT_KEY not set".to_string()));
+        let ready = Readiness::new(vec![check_configured("default_model", true), skipped.clone()]);
+        assert!(ready.ready);
+
+        let not_ready = Readiness::new(vec![check_configured("default_key", false), skipped]);
+        assert!(!not_ready.ready);
+        assert_eq!(
+            serde_json::to_value(&not_ready.checks[0]).unwrap(),
+            serde_json::json!({"name": "default_key", "status": "fail", "detail": "not configured"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_checks() {
+        let first = cached_checks(|| async { vec![check_configured("default_model", true)] }).await;
+        let second = cached_checks(|| async { vec![check_configured("default_model", false)] }).await;
+        assert_eq!(first[0].status, CheckStatus::Pass);
+        assert_eq!(second[0].status, CheckStatus::Pass);
+    }
+
+    #[tokio::test]
+    async fn test_check_connection() {
+        let check = check_connection("fixture", "memory:/nonexistent/fixture.json").await;
+        assert_eq!(check.name, "connection:fixture");
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.duration_ms.is_some());
+    }
+}
@@ -1,6 +1,25 @@
+pub mod access;
+pub mod audit;
+pub mod auth;
 pub mod chat;
+pub mod config;
+pub mod cors;
+pub mod dialect;
 pub mod error;
+pub mod eval;
+pub mod feedback;
 pub mod formatter;
+pub mod graph;
+pub mod guard;
+pub mod health;
+pub mod limits;
+pub mod llm;
 pub mod mcp;
+pub mod metrics;
+pub mod pii;
 pub mod schema;
+pub mod shutdown;
+pub mod telemetry;
 pub mod template;
+pub mod tls;
+pub mod usage;
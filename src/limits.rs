@@ -0,0 +1,748 @@
+//! Rate Limits and Daily Quotas
+//!
+//! `/text_to_cypher` requests are counted against a token bucket per API key and per client IP,
+//! and the model tokens they use against a daily quota per key and per IP (days are UTC). A
+//! request over a limit is refused with `429 Too Many Requests` and a `Retry-After` header before
+//! its event stream opens. Counters live in memory, or in a Redis-compatible server shared by
+//! every instance when `limits.backend` is a `redis://` URL. A backend that cannot be reached
+//! lets requests through rather than failing them.
+//!
+//! The client IP is the peer address of the connection. Only when the peer is one of
+//! `server.trusted_proxies` is its `X-Forwarded-For` header read, taking the last address that is
+//! not itself a trusted proxy, so that clients cannot pick the IP they are counted against.
+
+use crate::config::{LimitSettings, Settings};
+use actix_web::HttpRequest;
+use actix_web::http::header::HeaderMap;
+use chrono::{NaiveDate, Utc};
+use moka::sync::Cache;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Prefix of the keys the shared backend stores counters under
+const REDIS_PREFIX: &str = "text-to-cypher:limits";
+
+/// Atomically refill the buckets in `KEYS`, each with its capacity and refill rate per
+/// millisecond in `ARGV`, and take a token from each only if all of them have one; returns the
+/// milliseconds until each has a token, all 0 if the tokens were taken
+const TAKE_TOKENS_SCRIPT: &str = r"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local levels = {}
+local waits = {}
+local limited = false
+for i, key in ipairs(KEYS) do
+    local capacity = tonumber(ARGV[2 * i - 1])
+    local per_ms = tonumber(ARGV[2 * i])
+    local state = redis.call('HMGET', key, 'tokens', 'updated')
+    local tokens = tonumber(state[1]) or capacity
+    local updated = tonumber(state[2]) or now
+    levels[i] = math.min(capacity, tokens + math.max(0, now - updated) * per_ms)
+    waits[i] = 0
+    if levels[i] < 1 then
+        waits[i] = math.ceil((1 - levels[i]) / per_ms)
+        limited = true
+    end
+end
+if not limited then
+    for i, key in ipairs(KEYS) do
+        local capacity = tonumber(ARGV[2 * i - 1])
+        local per_ms = tonumber(ARGV[2 * i])
+        redis.call('HSET', key, 'tokens', tostring(levels[i] - 1), 'updated', now)
+        redis.call('PEXPIRE', key, math.ceil(capacity / per_ms) + 1000)
+    end
+end
+return waits
+";
+
+/// Keys the shared backend is asked to look at per `SCAN` call
+const SCAN_COUNT: usize = 100;
+
+/// How long idle in-memory buckets and quotas are kept
+const MEMORY_RETENTION: Duration = Duration::from_hours(48);
+
+static LIMITER: OnceLock<Limiter> = OnceLock::new();
+
+/// A token bucket: `burst` requests at once, refilled at `requests_per_minute`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+    /// Requests allowed at once; defaults to `requests_per_minute`
+    pub burst: Option<u32>,
+}
+
+impl RateLimit {
+    #[must_use]
+    pub fn capacity(&self) -> f64 {
+        f64::from(self.burst.unwrap_or(self.requests_per_minute))
+    }
+
+    #[must_use]
+    pub fn per_second(&self) -> f64 {
+        f64::from(self.requests_per_minute) / 60.0
+    }
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, Default)]
+pub struct Subjects {
+    /// Name of the API key; `None` when authentication is disabled
+    pub key: Option<String>,
+    pub ip: Option<String>,
+    /// Replaces `limits.per_key` for the key
+    pub key_rate_limit: Option<RateLimit>,
+    /// Replaces `limits.daily_tokens_per_key` for the key
+    pub key_daily_tokens: Option<u64>,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limited {
+    pub message: String,
+    pub retry_after: Duration,
+}
+
+/// Tokens left in a bucket, as reported by the admin endpoint
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BucketState {
+    pub subject: String,
+    pub tokens: f64,
+}
+
+/// Model tokens used by a subject on a day, as reported by the admin endpoint
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaState {
+    pub subject: String,
+    pub day: String,
+    pub used_tokens: u64,
+}
+
+/// Response of `GET /limits`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LimitsReport {
+    pub backend: String,
+    pub per_key: Option<RateLimit>,
+    pub per_ip: Option<RateLimit>,
+    pub daily_tokens_per_key: Option<u64>,
+    pub daily_tokens_per_ip: Option<u64>,
+    pub buckets: Vec<BucketState>,
+    pub quotas: Vec<QuotaState>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// How long until a token is available, if the bucket is empty
+    fn wait(
+        &self,
+        limit: RateLimit,
+    ) -> Option<Duration> {
+        (self.tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second()))
+    }
+
+    fn refill(
+        &mut self,
+        limit: RateLimit,
+        now: Instant,
+    ) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = limit.per_second().mul_add(elapsed, self.tokens).min(limit.capacity());
+        self.updated = now;
+    }
+}
+
+enum Backend {
+    Memory {
+        buckets: Cache<String, Arc<Mutex<Bucket>>>,
+        quotas: Cache<String, Arc<AtomicU64>>,
+    },
+    Redis {
+        client: redis::Client,
+        connection: tokio::sync::Mutex<Option<redis::aio::MultiplexedConnection>>,
+    },
+}
+
+/// The configured limits and their counters
+pub struct Limiter {
+    settings: LimitSettings,
+    backend: Backend,
+}
+
+impl Limiter {
+    /// Build the limiter from the settings, falling back to in-memory counters if the shared
+    /// backend URL is invalid
+    #[must_use]
+    pub fn new(settings: LimitSettings) -> Self {
+        let backend = if settings.backend == "memory" {
+            None
+        } else {
+            redis::Client::open(settings.backend.as_str())
+                .inspect_err(|e| tracing::error!("Invalid limits backend, counting in memory: {e}"))
+                .ok()
+                .map(|client| Backend::Redis {
+                    client,
+                    connection: tokio::sync::Mutex::new(None),
+                })
+        };
+        let backend = backend.unwrap_or_else(|| Backend::Memory {
+            buckets: Cache::builder().time_to_idle(MEMORY_RETENTION).build(),
+            quotas: Cache::builder().time_to_live(MEMORY_RETENTION).build(),
+        });
+        Self { settings, backend }
+    }
+
+    /// The limiter built from [`Settings`]
+    pub fn get() -> &'static Self {
+        LIMITER.get_or_init(|| Self::new(Settings::get().limits.clone()))
+    }
+
+    /// Check the quotas, then take a token from each bucket of the subjects, or from none of them
+    /// if one is empty
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Limited`] with the wait before retrying if a quota is used up or a bucket is
+    /// empty.
+    pub async fn check(
+        &self,
+        subjects: &Subjects,
+    ) -> Result<(), Limited> {
+        let day = Utc::now().date_naive();
+        for (subject, quota) in self.quota_subjects(subjects) {
+            let used = self.quota_used(&subject, day).await;
+            if used >= quota {
+                return Err(Limited {
+                    message: format!("Daily quota of {quota} model tokens used up for {subject}"),
+                    retry_after: until_tomorrow(),
+                });
+            }
+        }
+        let buckets = self.bucket_subjects(subjects);
+        if let Some((index, wait)) = self.take(&buckets).await {
+            let (subject, limit) = &buckets[index];
+            return Err(Limited {
+                message: format!(
+                    "Rate limit of {} requests per minute exceeded for {subject}",
+                    limit.requests_per_minute
+                ),
+                retry_after: wait,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count the model tokens a request used against the daily quotas of its subjects
+    pub async fn record_tokens(
+        &self,
+        subjects: &Subjects,
+        tokens: u64,
+    ) {
+        if tokens == 0 {
+            return;
+        }
+        let day = Utc::now().date_naive();
+        for (subject, _) in self.quota_subjects(subjects) {
+            self.add_quota(&subject, day, tokens).await;
+        }
+    }
+
+    fn bucket_subjects(
+        &self,
+        subjects: &Subjects,
+    ) -> Vec<(String, RateLimit)> {
+        let key = subjects.key.as_ref().and_then(|key| {
+            let limit = subjects.key_rate_limit.or(self.settings.per_key)?;
+            Some((format!("key:{key}"), limit))
+        });
+        let ip = subjects
+            .ip
+            .as_ref()
+            .and_then(|ip| Some((format!("ip:{ip}"), self.settings.per_ip?)));
+        key.into_iter().chain(ip).collect()
+    }
+
+    fn quota_subjects(
+        &self,
+        subjects: &Subjects,
+    ) -> Vec<(String, u64)> {
+        let key = subjects.key.as_ref().and_then(|key| {
+            let quota = subjects.key_daily_tokens.or(self.settings.daily_tokens_per_key)?;
+            Some((format!("key:{key}"), quota))
+        });
+        let ip = subjects
+            .ip
+            .as_ref()
+            .and_then(|ip| Some((format!("ip:{ip}"), self.settings.daily_tokens_per_ip?)));
+        key.into_iter().chain(ip).collect()
+    }
+
+    /// Take a token from every bucket if each has one, otherwise return the position of the first
+    /// empty bucket and how long until it has a token
+    async fn take(
+        &self,
+        subjects: &[(String, RateLimit)],
+    ) -> Option<(usize, Duration)> {
+        match &self.backend {
+            Backend::Memory { buckets, .. } => {
+                let buckets: Vec<_> = subjects
+                    .iter()
+                    .map(|(subject, limit)| {
+                        buckets.get_with(subject.clone(), || {
+                            Arc::new(Mutex::new(Bucket {
+                                tokens: limit.capacity(),
+                                updated: Instant::now(),
+                            }))
+                        })
+                    })
+                    .collect();
+                // Subjects come in the same order for every request, so locking them in turn
+                // cannot deadlock
+                let mut buckets: Vec<_> = buckets
+                    .iter()
+                    .map(|bucket| bucket.lock().unwrap_or_else(std::sync::PoisonError::into_inner))
+                    .collect();
+                let now = Instant::now();
+                for (bucket, (_, limit)) in buckets.iter_mut().zip(subjects) {
+                    bucket.refill(*limit, now);
+                }
+                let limited = buckets
+                    .iter()
+                    .zip(subjects)
+                    .enumerate()
+                    .find_map(|(index, (bucket, (_, limit)))| Some((index, bucket.wait(*limit)?)));
+                if limited.is_none() {
+                    for bucket in &mut buckets {
+                        bucket.tokens -= 1.0;
+                    }
+                }
+                limited
+            }
+            Backend::Redis { .. } => {
+                if subjects.is_empty() {
+                    return None;
+                }
+                let mut script = redis::cmd("EVAL");
+                script.arg(TAKE_TOKENS_SCRIPT).arg(subjects.len());
+                for (subject, _) in subjects {
+                    script.arg(format!("{REDIS_PREFIX}:bucket:{subject}"));
+                }
+                for (_, limit) in subjects {
+                    script.arg(limit.capacity()).arg(limit.per_second() / 1000.0);
+                }
+                let waits: Vec<u64> = self.query_redis(&script).await.unwrap_or_default();
+                waits
+                    .into_iter()
+                    .enumerate()
+                    .find(|(_, wait)| *wait > 0)
+                    .map(|(index, wait)| (index, Duration::from_millis(wait)))
+            }
+        }
+    }
+
+    async fn quota_used(
+        &self,
+        subject: &str,
+        day: NaiveDate,
+    ) -> u64 {
+        match &self.backend {
+            Backend::Memory { quotas, .. } => quotas
+                .get(&quota_key(subject, day))
+                .map_or(0, |used| used.load(Ordering::Relaxed)),
+            Backend::Redis { .. } => {
+                let mut get = redis::cmd("GET");
+                get.arg(format!("{REDIS_PREFIX}:quota:{}", quota_key(subject, day)));
+                self.query_redis::<Option<u64>>(&get).await.flatten().unwrap_or(0)
+            }
+        }
+    }
+
+    async fn add_quota(
+        &self,
+        subject: &str,
+        day: NaiveDate,
+        tokens: u64,
+    ) {
+        match &self.backend {
+            Backend::Memory { quotas, .. } => {
+                quotas
+                    .get_with(quota_key(subject, day), || Arc::new(AtomicU64::new(0)))
+                    .fetch_add(tokens, Ordering::Relaxed);
+            }
+            Backend::Redis { .. } => {
+                let key = format!("{REDIS_PREFIX}:quota:{}", quota_key(subject, day));
+                let mut increment = redis::cmd("INCRBY");
+                increment.arg(&key).arg(tokens);
+                let mut expire = redis::cmd("EXPIRE");
+                expire.arg(&key).arg(MEMORY_RETENTION.as_secs());
+                if self.query_redis::<u64>(&increment).await.is_some() {
+                    let _: Option<bool> = self.query_redis(&expire).await;
+                }
+            }
+        }
+    }
+
+    /// The limits and the current counters
+    pub async fn report(&self) -> LimitsReport {
+        let mut buckets = Vec::new();
+        let mut quotas = Vec::new();
+        match &self.backend {
+            Backend::Memory {
+                buckets: bucket_cache,
+                quotas: quota_cache,
+            } => {
+                let now = Instant::now();
+                for (subject, bucket) in bucket_cache {
+                    let Some(limit) = self.limit_of(&subject) else {
+                        continue;
+                    };
+                    let mut bucket = bucket.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+                    bucket.refill(limit, now);
+                    buckets.push(BucketState {
+                        subject: subject.to_string(),
+                        tokens: bucket.tokens,
+                    });
+                }
+                for (key, used) in quota_cache {
+                    if let Some((subject, day)) = key.rsplit_once('@') {
+                        quotas.push(QuotaState {
+                            subject: subject.to_string(),
+                            day: day.to_string(),
+                            used_tokens: used.load(Ordering::Relaxed),
+                        });
+                    }
+                }
+            }
+            Backend::Redis { .. } => {
+                let prefix = format!("{REDIS_PREFIX}:quota:");
+                for key in self.scan_keys(&format!("{prefix}*")).await {
+                    let mut get = redis::cmd("GET");
+                    get.arg(&key);
+                    let used: Option<u64> = self.query_redis(&get).await.flatten();
+                    if let Some((subject, day)) = key.trim_start_matches(&prefix).rsplit_once('@') {
+                        quotas.push(QuotaState {
+                            subject: subject.to_string(),
+                            day: day.to_string(),
+                            used_tokens: used.unwrap_or(0),
+                        });
+                    }
+                }
+            }
+        }
+        buckets.sort_by(|a, b| a.subject.cmp(&b.subject));
+        quotas.sort_by(|a, b| (&a.day, &a.subject).cmp(&(&b.day, &b.subject)));
+
+        LimitsReport {
+            backend: match self.backend {
+                Backend::Memory { .. } => "memory".to_string(),
+                Backend::Redis { .. } => "redis".to_string(),
+            },
+            per_key: self.settings.per_key,
+            per_ip: self.settings.per_ip,
+            daily_tokens_per_key: self.settings.daily_tokens_per_key,
+            daily_tokens_per_ip: self.settings.daily_tokens_per_ip,
+            buckets,
+            quotas,
+        }
+    }
+
+    /// The rate limit of an in-memory bucket, from the key's settings or the defaults
+    fn limit_of(
+        &self,
+        subject: &str,
+    ) -> Option<RateLimit> {
+        subject.strip_prefix("key:").map_or(self.settings.per_ip, |key| {
+            Settings::get()
+                .auth
+                .keys
+                .iter()
+                .find(|settings| settings.name == key)
+                .and_then(|settings| settings.rate_limit)
+                .or(self.settings.per_key)
+        })
+    }
+
+    /// Keys of the shared backend matching `pattern`, walked with `SCAN` so that the server is
+    /// not blocked the way `KEYS` would
+    async fn scan_keys(
+        &self,
+        pattern: &str,
+    ) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = 0_u64;
+        loop {
+            let mut scan = redis::cmd("SCAN");
+            scan.arg(cursor).arg("MATCH").arg(pattern).arg("COUNT").arg(SCAN_COUNT);
+            let Some((next, batch)) = self.query_redis::<(u64, Vec<String>)>(&scan).await else {
+                break;
+            };
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Run a command on the shared backend, reconnecting once if the connection was lost;
+    /// `None` if the backend cannot be reached
+    async fn query_redis<T: redis::FromRedisValue>(
+        &self,
+        command: &redis::Cmd,
+    ) -> Option<T> {
+        let Backend::Redis { client, connection } = &self.backend else {
+            return None;
+        };
+        for _ in 0..2 {
+            // Multiplexed connections are cheap to clone, so the lock is only held to connect
+            let mut active = {
+                let mut connection = connection.lock().await;
+                if connection.is_none() {
+                    match client.get_multiplexed_async_connection().await {
+                        Ok(connected) => *connection = Some(connected),
+                        Err(e) => {
+                            tracing::warn!("Limits backend unavailable, not enforcing limits: {e}");
+                            return None;
+                        }
+                    }
+                }
+                connection.clone()?
+            };
+            match command.query_async(&mut active).await {
+                Ok(value) => return Some(value),
+                Err(e) if e.is_io_error() || e.is_connection_dropped() => *connection.lock().await = None,
+                Err(e) => {
+                    tracing::warn!("Limits backend command failed, not enforcing limits: {e}");
+                    return None;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Key of a subject's quota counter for a day
+fn quota_key(
+    subject: &str,
+    day: NaiveDate,
+) -> String {
+    format!("{subject}@{day}")
+}
+
+/// Time until the next UTC midnight, when the daily quotas reset
+fn until_tomorrow() -> Duration {
+    let now = Utc::now();
+    now.date_naive()
+        .succ_opt()
+        .and_then(|tomorrow| tomorrow.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| (midnight.and_utc() - now).to_std().ok())
+        .unwrap_or(Duration::from_hours(1))
+}
+
+/// The IP the request is counted against: the peer, or the client a trusted proxy forwarded for
+#[must_use]
+pub fn client_addr(request: &HttpRequest) -> String {
+    client_ip(
+        request.peer_addr().map(|addr| addr.ip()),
+        request.headers(),
+        &Settings::get().server.trusted_proxies,
+    )
+}
+
+fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> String {
+    let Some(peer) = peer else {
+        return "unknown".to_string();
+    };
+    if !trusted_proxies.contains(&peer) {
+        return peer.to_string();
+    }
+    // Each proxy appends the address it received the request from, so read from the right
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect();
+    let mut client = peer.to_string();
+    for hop in hops.iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(ip) if trusted_proxies.contains(&ip) => client = ip.to_string(),
+            Ok(ip) => return ip.to_string(),
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        requests_per_minute: 60,
+        burst: Some(2),
+    };
+
+    fn subjects(key: Option<&str>) -> Subjects {
+        Subjects {
+            key: key.map(ToString::to_string),
+            ip: Some("10.0.0.1".to_string()),
+            ..Subjects::default()
+        }
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: LIMIT.capacity(),
+            updated: start,
+        };
+        assert_eq!(bucket.wait(LIMIT), None);
+        bucket.tokens -= 2.0;
+        assert_eq!(bucket.wait(LIMIT), Some(Duration::from_secs(1)));
+
+        let later = start + Duration::from_millis(1500);
+        bucket.refill(LIMIT, later);
+        assert_eq!(bucket.wait(LIMIT), None);
+        bucket.tokens -= 1.0;
+        assert!(bucket.wait(LIMIT).is_some_and(|wait| wait <= Duration::from_millis(500)));
+
+        bucket.refill(LIMIT, later + Duration::from_secs(3600));
+        assert!((bucket.tokens - LIMIT.capacity()).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_per_subject() {
+        let limiter = Limiter::new(LimitSettings {
+            per_ip: Some(LIMIT),
+            ..LimitSettings::default()
+        });
+
+        assert!(limiter.check(&subjects(None)).await.is_ok());
+        assert!(limiter.check(&subjects(None)).await.is_ok());
+        let limited = limiter.check(&subjects(None)).await.unwrap_err();
+        assert_eq!(
+            limited.message,
+            "Rate limit of 60 requests per minute exceeded for ip:10.0.0.1"
+        );
+        assert!(limited.retry_after <= Duration::from_secs(1));
+
+        let other_ip = Subjects {
+            ip: Some("10.0.0.2".to_string()),
+            ..Subjects::default()
+        };
+        assert!(limiter.check(&other_ip).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_refused_request_takes_no_tokens() {
+        let limiter = Limiter::new(LimitSettings {
+            per_key: Some(RateLimit {
+                requests_per_minute: 1,
+                burst: Some(3),
+            }),
+            per_ip: Some(RateLimit {
+                requests_per_minute: 1,
+                burst: Some(1),
+            }),
+            ..LimitSettings::default()
+        });
+
+        assert!(limiter.check(&subjects(Some("analytics"))).await.is_ok());
+        let limited = limiter.check(&subjects(Some("analytics"))).await.unwrap_err();
+        assert_eq!(
+            limited.message,
+            "Rate limit of 1 requests per minute exceeded for ip:10.0.0.1"
+        );
+
+        // The refused request left the key's bucket alone
+        for ip in ["10.0.0.2", "10.0.0.3"] {
+            let other_ip = Subjects {
+                ip: Some(ip.to_string()),
+                ..subjects(Some("analytics"))
+            };
+            assert!(limiter.check(&other_ip).await.is_ok());
+        }
+        let other_ip = Subjects {
+            ip: Some("10.0.0.4".to_string()),
+            ..subjects(Some("analytics"))
+        };
+        let limited = limiter.check(&other_ip).await.unwrap_err();
+        assert_eq!(
+            limited.message,
+            "Rate limit of 1 requests per minute exceeded for key:analytics"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_daily_quotas() {
+        let limiter = Limiter::new(LimitSettings {
+            daily_tokens_per_key: Some(1000),
+            ..LimitSettings::default()
+        });
+        let analytics = Subjects {
+            key_daily_tokens: Some(100),
+            ..subjects(Some("analytics"))
+        };
+
+        assert!(limiter.check(&analytics).await.is_ok());
+        limiter.record_tokens(&analytics, 60).await;
+        assert!(limiter.check(&analytics).await.is_ok());
+        limiter.record_tokens(&analytics, 60).await;
+        let limited = limiter.check(&analytics).await.unwrap_err();
+        assert_eq!(
+            limited.message,
+            "Daily quota of 100 model tokens used up for key:analytics"
+        );
+        assert!(limited.retry_after <= Duration::from_hours(24));
+
+        // Other keys get the default quota and requests without a key have none
+        assert!(limiter.check(&subjects(Some("ops"))).await.is_ok());
+        assert!(limiter.check(&subjects(None)).await.is_ok());
+
+        let report = limiter.report().await;
+        assert_eq!(report.backend, "memory");
+        assert_eq!(report.quotas.len(), 1);
+        assert_eq!(report.quotas[0].subject, "key:analytics");
+        assert_eq!(report.quotas[0].used_tokens, 120);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        use actix_web::http::header::{HeaderName, HeaderValue};
+
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let peer: IpAddr = "203.0.113.7".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_static("1.2.3.4, 198.51.100.9, 10.0.0.2"),
+        );
+
+        // Headers from untrusted peers are ignored
+        assert_eq!(client_ip(Some(peer), &headers, &[proxy]), "203.0.113.7");
+        assert_eq!(client_ip(Some(proxy), &headers, &[]), "10.0.0.2");
+        // The forged leftmost hop is skipped in favor of the last untrusted one
+        assert_eq!(client_ip(Some(proxy), &headers, &[proxy]), "198.51.100.9");
+        assert_eq!(client_ip(Some(proxy), &HeaderMap::new(), &[proxy]), "10.0.0.2");
+        assert_eq!(client_ip(None, &headers, &[proxy]), "unknown");
+    }
+}
@@ -0,0 +1,268 @@
+//! Scripted mock chat backend
+//!
+//! Answers by matching the prompt against an ordered list of regex rules and returning the
+//! canned response of the first match; streamed answers are split into word chunks. Token usage
+//! is estimated from the prompt and response length, so the whole pipeline (including usage
+//! accounting) runs deterministically without a provider.
+//!
+//! ## Script Format
+//!
+//! `mock:` (or `mock:default`) uses the built-in script; `mock:<path>` loads a JSON script:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "pattern": "(?i)who directed", "call": "chat", "response": "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name" },
+//!     { "pattern": "(?i)who directed", "call": "stream", "response": "Michael Mann directed Heat." }
+//!   ],
+//!   "default_query": "MATCH (n) RETURN count(n) AS nodes",
+//!   "default_answer": "There are some nodes in the graph."
+//! }
+//! ```
+
+use super::{BackendError, ChatBackend, ChatCompletion, ChatEventStream, ChatStreamEvent};
+use crate::formatter::estimate_tokens;
+use crate::usage::TokenUsage;
+use async_trait::async_trait;
+use genai::chat::ChatRequest;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_QUERY: &str = "MATCH (n) RETURN count(n) AS nodes";
+const DEFAULT_ANSWER: &str = "This is a mock answer based on the query results.";
+
+/// Which model call a rule answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockCall {
+    /// The non-streamed call generating the Cypher query
+    Chat,
+    /// The streamed call generating the final answer
+    Stream,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockRule {
+    /// Regex matched against the whole prompt (system prompt and all messages)
+    pub pattern: String,
+    /// Restrict the rule to one call; applies to both when omitted
+    #[serde(default)]
+    pub call: Option<MockCall>,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockScript {
+    #[serde(default)]
+    pub rules: Vec<MockRule>,
+    #[serde(default = "default_query")]
+    pub default_query: String,
+    #[serde(default = "default_answer")]
+    pub default_answer: String,
+}
+
+fn default_query() -> String {
+    DEFAULT_QUERY.to_string()
+}
+
+fn default_answer() -> String {
+    DEFAULT_ANSWER.to_string()
+}
+
+impl Default for MockScript {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default_query: default_query(),
+            default_answer: default_answer(),
+        }
+    }
+}
+
+pub struct MockBackend {
+    script: MockScript,
+    patterns: Vec<Regex>,
+}
+
+impl MockBackend {
+    /// # Errors
+    ///
+    /// Returns an error if a rule pattern is not a valid regex.
+    pub fn new(script: MockScript) -> Result<Self, BackendError> {
+        let patterns = script
+            .rules
+            .iter()
+            .map(|rule| Regex::new(&rule.pattern).map_err(|e| format!("Invalid mock pattern '{}': {e}", rule.pattern)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { script, patterns })
+    }
+
+    /// Build the backend from the part of the model name after `mock:`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script file cannot be read or parsed.
+    pub fn from_spec(spec: &str) -> Result<Self, BackendError> {
+        if spec.is_empty() || spec == "default" {
+            return Self::new(MockScript::default());
+        }
+
+        let content = std::fs::read_to_string(spec).map_err(|e| format!("Failed to read mock script {spec}: {e}"))?;
+        let script = serde_json::from_str(&content).map_err(|e| format!("Invalid mock script {spec}: {e}"))?;
+        Self::new(script)
+    }
+
+    fn respond(
+        &self,
+        call: MockCall,
+        prompt: &str,
+    ) -> &str {
+        self.script
+            .rules
+            .iter()
+            .zip(&self.patterns)
+            .find(|(rule, pattern)| rule.call.is_none_or(|rule_call| rule_call == call) && pattern.is_match(prompt))
+            .map_or_else(
+                || match call {
+                    MockCall::Chat => self.script.default_query.as_str(),
+                    MockCall::Stream => self.script.default_answer.as_str(),
+                },
+                |(rule, _)| rule.response.as_str(),
+            )
+    }
+}
+
+/// The text of all messages of a request, in order
+fn prompt_text(request: &ChatRequest) -> String {
+    request
+        .system
+        .iter()
+        .map(String::as_str)
+        .chain(request.messages.iter().filter_map(|message| message.content.text_as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn estimate_usage(
+    prompt: &str,
+    response: &str,
+) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: estimate_tokens(prompt) as u64,
+        completion_tokens: estimate_tokens(response) as u64,
+    }
+}
+
+#[async_trait]
+impl ChatBackend for MockBackend {
+    fn provider(&self) -> String {
+        "Mock".to_string()
+    }
+
+    async fn exec_chat(
+        &self,
+        _model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatCompletion, BackendError> {
+        let prompt = prompt_text(&request);
+        let response = self.respond(MockCall::Chat, &prompt);
+        Ok(ChatCompletion {
+            content: Some(response.to_string()),
+            usage: estimate_usage(&prompt, response),
+        })
+    }
+
+    async fn exec_chat_stream(
+        &self,
+        _model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatEventStream, BackendError> {
+        let prompt = prompt_text(&request);
+        let response = self.respond(MockCall::Stream, &prompt);
+
+        let mut events: Vec<Result<ChatStreamEvent, BackendError>> = response
+            .split_inclusive(' ')
+            .map(|chunk| Ok(ChatStreamEvent::Chunk(chunk.to_string())))
+            .collect();
+        events.push(Ok(ChatStreamEvent::End(Some(estimate_usage(&prompt, response)))));
+        Ok(Box::pin(futures_util::stream::iter(events)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn backend() -> MockBackend {
+        MockBackend::new(MockScript {
+            rules: vec![
+                MockRule {
+                    pattern: "(?i)who directed".to_string(),
+                    call: Some(MockCall::Chat),
+                    response: "MATCH (p:Person)-[:DIRECTED]->(m) RETURN p".to_string(),
+                },
+                MockRule {
+                    pattern: "(?i)who directed".to_string(),
+                    call: Some(MockCall::Stream),
+                    response: "Michael Mann directed it.".to_string(),
+                },
+            ],
+            ..MockScript::default()
+        })
+        .unwrap()
+    }
+
+    fn request(question: &str) -> ChatRequest {
+        ChatRequest::new(vec![genai::chat::ChatMessage::user(question)]).with_system("You write Cypher")
+    }
+
+    #[tokio::test]
+    async fn test_chat_matches_rules_and_falls_back() {
+        let backend = backend();
+        let completion = backend.exec_chat("mock:", request("Who directed Heat?")).await.unwrap();
+        assert_eq!(
+            completion.content.as_deref(),
+            Some("MATCH (p:Person)-[:DIRECTED]->(m) RETURN p")
+        );
+        assert!(completion.usage.prompt_tokens > 0);
+
+        let completion = backend.exec_chat("mock:", request("How many nodes?")).await.unwrap();
+        assert_eq!(completion.content.as_deref(), Some(DEFAULT_QUERY));
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_chunks_then_usage() {
+        let events: Vec<_> = backend()
+            .exec_chat_stream("mock:", request("who directed Heat?"))
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                ChatStreamEvent::Chunk(chunk) => Some(chunk.as_str()),
+                ChatStreamEvent::End(_) => None,
+            })
+            .collect();
+        assert_eq!(text, "Michael Mann directed it.");
+        assert!(events.len() > 2);
+        assert!(matches!(events.last(), Some(ChatStreamEvent::End(Some(_)))));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_rejected() {
+        let script = MockScript {
+            rules: vec![MockRule {
+                pattern: "(".to_string(),
+                call: None,
+                response: String::new(),
+            }],
+            ..MockScript::default()
+        };
+        assert!(MockBackend::new(script).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! Chat Backend Module
+//!
+//! Abstracts the AI provider behind the [`ChatBackend`] trait so the pipeline does not depend on
+//! `genai` directly. Models prefixed with `mock:` are served by the scripted [`MockBackend`],
+//! which needs no network access; every other model goes through [`GenAiBackend`].
+
+pub mod mock;
+
+pub use mock::MockBackend;
+
+use crate::usage::TokenUsage;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use genai::ModelIden;
+use genai::chat::ChatRequest;
+use genai::resolver::{AuthData, AuthResolver};
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Prefix selecting the mock backend, e.g. `mock:` or `mock:tests/mock_script.json`
+pub const MOCK_PREFIX: &str = "mock:";
+
+/// A completed, non-streamed model response
+#[derive(Debug, Clone, Default)]
+pub struct ChatCompletion {
+    pub content: Option<String>,
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatStreamEvent {
+    Chunk(String),
+    /// End of the stream, with the usage when the provider reports it
+    End(Option<TokenUsage>),
+}
+
+pub type ChatEventStream = Pin<Box<dyn futures_util::Stream<Item = Result<ChatStreamEvent, BackendError>> + Send>>;
+
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    /// Name of the provider serving the model, shown in status messages
+    fn provider(&self) -> String;
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatCompletion, BackendError>;
+
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatEventStream, BackendError>;
+}
+
+/// Create the backend serving `model`, authenticating with `key` when given
+///
+/// # Errors
+///
+/// Returns an error if the mock script cannot be loaded or no provider serves the model.
+pub async fn backend_for(
+    model: &str,
+    key: Option<&str>,
+) -> Result<Arc<dyn ChatBackend>, BackendError> {
+    if let Some(script) = model.strip_prefix(MOCK_PREFIX) {
+        return Ok(Arc::new(MockBackend::from_spec(script)?));
+    }
+
+    Ok(Arc::new(GenAiBackend::new(model, key).await?))
+}
+
+/// Backend for the providers supported by `genai`
+pub struct GenAiBackend {
+    client: genai::Client,
+    service_target: genai::ServiceTarget,
+}
+
+impl GenAiBackend {
+    /// # Errors
+    ///
+    /// Returns an error if no provider serves the model.
+    pub async fn new(
+        model: &str,
+        key: Option<&str>,
+    ) -> Result<Self, genai::Error> {
+        let client = key.map_or_else(genai::Client::default, |key| {
+            let key = key.to_string();
+            let auth_resolver = AuthResolver::from_resolver_fn(
+                move |model_iden: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
+                    let ModelIden {
+                        adapter_kind,
+                        model_name,
+                    } = model_iden;
+                    tracing::info!("Using custom auth provider for {adapter_kind} (model: {model_name})");
+
+                    // Use the provided key instead of reading from environment
+                    Ok(Some(AuthData::from_single(key.clone())))
+                },
+            );
+            genai::Client::builder().with_auth_resolver(auth_resolver).build()
+        });
+
+        let service_target = client.resolve_service_target(model).await?;
+        Ok(Self { client, service_target })
+    }
+}
+
+#[async_trait]
+impl ChatBackend for GenAiBackend {
+    fn provider(&self) -> String {
+        format!("{:?}", self.service_target.model.adapter_kind)
+    }
+
+    async fn exec_chat(
+        &self,
+        model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatCompletion, BackendError> {
+        let response = self.client.exec_chat(model, request, None).await?;
+        let usage = TokenUsage::from(&response.usage);
+        Ok(ChatCompletion {
+            content: response.content_text_into_string(),
+            usage,
+        })
+    }
+
+    async fn exec_chat_stream(
+        &self,
+        model: &str,
+        request: ChatRequest,
+    ) -> Result<ChatEventStream, BackendError> {
+        // Ask the provider to report usage in the stream end event
+        let chat_options = genai::chat::ChatOptions::default().with_capture_usage(true);
+        let response = self.client.exec_chat_stream(model, request, Some(&chat_options)).await?;
+
+        let stream = response.stream.filter_map(|event| async move {
+            match event {
+                Ok(genai::chat::ChatStreamEvent::Chunk(chunk)) => Some(Ok(ChatStreamEvent::Chunk(chunk.content))),
+                Ok(genai::chat::ChatStreamEvent::End(end_event)) => Some(Ok(ChatStreamEvent::End(
+                    end_event.captured_usage.as_ref().map(TokenUsage::from),
+                ))),
+                Ok(genai::chat::ChatStreamEvent::Start | genai::chat::ChatStreamEvent::ReasoningChunk(_)) => None,
+                Err(e) => Some(Err(e.into())),
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+}
@@ -1,20 +1,22 @@
 #![allow(clippy::needless_for_each)]
+// Selecting on the whole pipeline future nests deeper than the default limit allows
+#![recursion_limit = "256"]
 
 use actix_web::HttpResponse;
 use actix_web::http::StatusCode;
-use actix_web::{App, HttpServer, Responder, Result, post};
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpServer, Responder, Result, post, web};
 use actix_web_lab::sse::{self, Sse};
-use falkordb::FalkorClientBuilder;
-use falkordb::FalkorConnectionInfo;
 use futures_util::StreamExt;
-use genai::ModelIden;
-use genai::resolver::AuthData;
-use genai::resolver::AuthResolver;
 use moka::sync::Cache;
 use serde::{Deserialize, Serialize};
-use std::sync::OnceLock;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing_subscriber::fmt;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use utoipa::OpenApi;
 use utoipa::ToSchema;
 use utoipa_swagger_ui::SwaggerUi;
@@ -22,18 +24,9 @@ use utoipa_swagger_ui::SwaggerUi;
 // Macro for functions returning ()
 macro_rules! send {
     ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return;
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return;
-            }
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return;
         }
     };
 }
@@ -41,18 +34,9 @@ macro_rules! send {
 // Macro for functions returning Option<T>
 macro_rules! send_option {
     ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return None;
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return None;
-            }
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return None;
         }
     };
 }
@@ -60,18 +44,9 @@ macro_rules! send_option {
 // Macro for functions returning Result<T, ()>
 macro_rules! send_result {
     ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return Err(());
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return Err(());
-            }
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return Err(());
         }
     };
 }
@@ -79,71 +54,80 @@ macro_rules! send_result {
 // Macro for functions returning Result<T, ()> - same name, different internal marker
 macro_rules! try_send {
     ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return Err(());
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return Err(());
-            }
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return Err(());
         }
     };
 }
 
-// Macro for functions returning Result<String, Box<dyn Error>>
-macro_rules! try_send_boxed {
+// Macro for functions returning String (returns empty string on error)
+macro_rules! send_or_empty {
     ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return Err("Client disconnected".into());
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return Err(format!("Serialization failed: {}", e).into());
-            }
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return String::new();
         }
     };
 }
 
-// Macro for functions returning String (returns empty string on error)
-macro_rules! send_or_empty {
-    ($tx:expr, $progress:expr) => {
-        match serde_json::to_string(&$progress) {
-            Ok(json) => {
-                let event = sse::Event::Data(sse::Data::new(json));
-                if $tx.send(event).await.is_err() {
-                    tracing::warn!("Client disconnected, stopping stream");
-                    return String::new();
-                }
-            }
-            Err(e) => {
-                tracing::error!("Failed to serialize progress update: {}", e);
-                return String::new();
-            }
+// Macro for functions returning RequestOutcome (returns the given outcome on error)
+macro_rules! send_or_outcome {
+    ($tx:expr, $progress:expr, $outcome:expr) => {
+        if $tx.send($progress).await.is_err() {
+            tracing::warn!("Client disconnected, stopping stream");
+            return $outcome;
         }
     };
 }
 
+mod access;
+mod audit;
+mod auth;
 mod chat;
+mod cli;
+mod config;
+mod cors;
+mod dialect;
 mod error;
+mod feedback;
 mod formatter;
+mod graph;
+mod guard;
+mod health;
+mod limits;
+mod llm;
 mod mcp;
+mod metrics;
+mod pii;
 mod schema;
+mod shutdown;
+mod telemetry;
 mod template;
+mod tls;
+mod usage;
+mod websocket;
 
+use access::AccessPolicy;
+use audit::{AuditConfig, AuditEntry, AuditLog, AuditRecord};
+use auth::{KeyStore, Principal};
 use chat::{ChatMessage, ChatRequest, ChatRole};
-use formatter::format_query_records;
+use config::Settings;
+use dialect::Dialect;
+use error::ApiError;
+use feedback::{Example, FeedbackEntry, FeedbackRequest, FeedbackStore, Interaction};
+use formatter::{FormattedRecords, format_query_records_within_budget};
+use graph::GraphError;
+use guard::SystemMessagePolicy;
+use health::{McpState, Readiness, ReadinessStatus};
+use limits::{Limiter, LimitsReport, Subjects};
+use llm::{ChatBackend, ChatEventStream, ChatStreamEvent};
 use mcp::run_mcp_server;
-use template::TemplateEngine;
+use metrics::{Metrics, RequestOutcome, Stage};
+use pii::{Placeholders, Redactor};
+use shutdown::{Phase, SHUTDOWN_MESSAGE, Shutdown};
+use template::{TemplateEngine, TemplateError, TemplateScope};
+use usage::{ModelPrice, RequestUsage, TokenUsage, UsageLedger, UsageReport};
 
 use crate::schema::discovery::Schema;
 
@@ -153,9 +137,32 @@ struct AppConfig {
     falkordb_connection: String,
     default_model: Option<String>,
     default_key: Option<String>,
-    schema_cache: Cache<String, String>,
+    /// Whether requests may select the mock model, which reads its script from the server's disk
+    allow_mock_models: bool,
+    /// Discovered schemas by resolved connection and graph name, since graphs of the same name
+    /// on different connections differ
+    schema_cache: Cache<(String, String), String>,
+    answer_token_budget: usize,
+    model_token_budgets: HashMap<String, usize>,
+    model_prices: HashMap<String, ModelPrice>,
+    usage_ledger: Arc<UsageLedger>,
+    audit_log: Option<Arc<AuditLog>>,
+    feedback_store: Arc<FeedbackStore>,
 }
 
+/// Default token budget for query results placed in the answer prompt
+const DEFAULT_ANSWER_TOKEN_BUDGET: usize = 4000;
+
+/// Default directory for stored feedback and promoted examples
+const DEFAULT_FEEDBACK_DIR: &str = "feedback";
+
+/// Sent to the client when a prompt template fails to render; the details, which name template
+/// files on the server, are only logged
+const PROMPT_RENDER_ERROR: &str = "Failed to render the prompt; see the server logs for details";
+
+/// Seconds the HTTP workers wait for idle keep-alive connections once the streams are drained
+const SHUTDOWN_GRACE_SECS: u64 = 5;
+
 static APP_CONFIG: OnceLock<AppConfig> = OnceLock::new();
 
 impl AppConfig {
@@ -166,19 +173,42 @@ impl AppConfig {
             std::env::var("FALKORDB_CONNECTION").unwrap_or_else(|_| "falkor://127.0.0.1:6379".to_string());
         let default_model = std::env::var("DEFAULT_MODEL").ok();
         let default_key = std::env::var("DEFAULT_KEY").ok();
-        let schema_cache = Cache::new(100);
+        let allow_mock_models = std::env::var("LLM_ALLOW_MOCK")
+            .ok()
+            .and_then(|allow| allow.trim().parse().ok())
+            .unwrap_or(false);
+        let schema_cache = Cache::new(Settings::get().schema.cache_capacity);
+        let answer_token_budget = std::env::var("ANSWER_TOKEN_BUDGET")
+            .ok()
+            .and_then(|budget| budget.parse().ok())
+            .unwrap_or(DEFAULT_ANSWER_TOKEN_BUDGET);
+        let model_token_budgets = std::env::var("MODEL_TOKEN_BUDGETS")
+            .map(|budgets| Self::parse_model_token_budgets(&budgets))
+            .unwrap_or_default();
+        let model_prices = std::env::var("MODEL_PRICES")
+            .map(|prices| usage::parse_price_table(&prices))
+            .unwrap_or_default();
+        let feedback_dir = std::env::var("FEEDBACK_DIR").unwrap_or_else(|_| DEFAULT_FEEDBACK_DIR.to_string());
 
         tracing::info!(
-            "Loaded configuration - env_file_loaded: {}, default_model: {:?}",
+            "Loaded configuration - env_file_loaded: {}, default_model: {:?}, answer_token_budget: {}",
             env_loaded,
-            default_model
+            default_model,
+            answer_token_budget
         );
 
         Self {
             falkordb_connection,
             default_model,
             default_key,
+            allow_mock_models,
             schema_cache,
+            answer_token_budget,
+            model_token_budgets,
+            model_prices,
+            usage_ledger: Arc::new(UsageLedger::new()),
+            audit_log: AuditConfig::from_env().map(|audit_config| Arc::new(AuditLog::new(audit_config))),
+            feedback_store: Arc::new(FeedbackStore::open(feedback_dir)),
         }
     }
 
@@ -186,6 +216,81 @@ impl AppConfig {
         APP_CONFIG.get_or_init(Self::load)
     }
 
+    /// Parse `model=tokens` pairs separated by commas, skipping malformed entries
+    fn parse_model_token_budgets(budgets: &str) -> HashMap<String, usize> {
+        budgets
+            .split(',')
+            .filter_map(|entry| {
+                let (model, budget) = entry.split_once('=')?;
+                match budget.trim().parse() {
+                    Ok(budget) => Some((model.trim().to_string(), budget)),
+                    Err(e) => {
+                        tracing::warn!("Ignoring invalid token budget for model '{}': {}", model.trim(), e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Connection string of the graph, from its configuration file section or `FALKORDB_CONNECTION`,
+    /// either of which may name a configured connection
+    fn connection_for(
+        &self,
+        graph_name: &str,
+    ) -> String {
+        let settings = Settings::get();
+        let connection = settings
+            .graph(graph_name)
+            .and_then(|graph| graph.falkordb_connection.as_deref())
+            .unwrap_or(&self.falkordb_connection);
+        settings.resolve_connection(connection)
+    }
+
+    /// The connections the readiness check probes, by name: every configured connection, plus the
+    /// default and graph-section connection strings that do not name one
+    fn readiness_connections(&self) -> Vec<(String, String)> {
+        let settings = Settings::get();
+        let mut connections: Vec<(String, String)> = settings
+            .connections
+            .keys()
+            .map(|name| (name.clone(), settings.resolve_connection(name)))
+            .collect();
+        if !settings.is_named_connection(&self.falkordb_connection) {
+            connections.push(("default".to_string(), self.falkordb_connection.clone()));
+        }
+        for (graph_name, graph) in &settings.graphs {
+            if let Some(connection) = &graph.falkordb_connection
+                && !settings.is_named_connection(connection)
+            {
+                connections.push((format!("graphs.{graph_name}"), connection.clone()));
+            }
+        }
+        connections
+    }
+
+    /// Refuse a model the client may not select: the mock model is for `DEFAULT_MODEL` and the
+    /// command line, and requests may only name it when `LLM_ALLOW_MOCK` is set
+    fn check_requested_model(
+        &self,
+        model: Option<&str>,
+    ) -> Result<(), ApiError> {
+        match model {
+            Some(model) if model.starts_with(llm::MOCK_PREFIX) && !self.allow_mock_models => Err(ApiError::BadRequest(
+                "The mock model can only be selected by the server configuration".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Token budget for query results in the answer prompt of the given model
+    fn token_budget_for(
+        &self,
+        model: &str,
+    ) -> usize {
+        self.model_token_budgets.get(model).copied().unwrap_or(self.answer_token_budget)
+    }
+
     /// Check if MCP server should be started based on configuration completeness
     #[allow(clippy::cognitive_complexity)]
     fn should_start_mcp_server(&self) -> bool {
@@ -199,8 +304,10 @@ impl AppConfig {
             tracing::info!("MCP server will be started: both DEFAULT_MODEL and DEFAULT_KEY are configured");
         } else if !has_model {
             tracing::warn!("MCP server not started: DEFAULT_MODEL not set");
+            health::set_mcp_state(McpState::Disabled("DEFAULT_MODEL not set".to_string()));
         } else if !has_key {
             tracing::warn!("MCP server not started: DEFAULT_KEY not set");
+            health::set_mcp_state(McpState::Disabled("DEFAULT_KEY not set".to_string()));
         }
 
         should_start
@@ -213,7 +320,11 @@ struct TextToCypherRequest {
     chat_request: ChatRequest,
     model: Option<String>,
     key: Option<String>,
+    /// Name of a configured connection to use instead of the graph's; connection strings need
+    /// the admin scope and `server.allow_raw_connections`
     falkordb_connection: Option<String>,
+    /// Cypher dialect to generate; defaults to the dialect of the connection's backend
+    dialect: Option<Dialect>,
 }
 
 impl std::fmt::Debug for TextToCypherRequest {
@@ -225,7 +336,8 @@ impl std::fmt::Debug for TextToCypherRequest {
         debug_struct
             .field("graph_name", &self.graph_name)
             .field("chat_request", &self.chat_request)
-            .field("model", &self.model);
+            .field("model", &self.model)
+            .field("dialect", &self.dialect);
 
         if self.key.is_some() {
             debug_struct.field("key", &"***");
@@ -238,21 +350,102 @@ impl std::fmt::Debug for TextToCypherRequest {
     }
 }
 
+/// Server-side details of a request that are not part of the request body
+#[derive(Debug, Clone)]
+struct RequestContext {
+    request_id: String,
+    /// Who usage is filed under: the name of the API key, or a fingerprint of the model key when
+    /// authentication is disabled
+    account: String,
+    caller: String,
+    /// Who the request's model tokens are counted against
+    subjects: Subjects,
+    /// Labels and properties of the graph hidden from the caller
+    access: Option<AccessPolicy>,
+}
+
+impl RequestContext {
+    /// The context of a request made by `principal` from `client_addr`, under a new request ID
+    fn new(
+        principal: &Principal,
+        request: &TextToCypherRequest,
+        client_addr: &str,
+        subjects: Subjects,
+    ) -> Self {
+        let account = principal
+            .name()
+            .map_or_else(|| usage::key_id(request.key.as_deref()), ToString::to_string);
+        Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            caller: format!("{account}@{client_addr}"),
+            account,
+            subjects,
+            access: Settings::get().access_policy(&request.graph_name, principal.name()).cloned(),
+        }
+    }
+}
+
+/// Results of the generated query as given to the model, with the originals of redacted values
+struct QueryResult {
+    records: FormattedRecords,
+    placeholders: Placeholders,
+    /// Hash of the records as the database returned them, for the audit log
+    hash: String,
+}
+
+/// The final answer, sent to the client as its re-hydrated text
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Answer {
+    text: String,
+    /// What the model wrote, with placeholders in place of the redacted values; follow-up
+    /// questions carry this rather than the text so the originals never reach the model
+    #[serde(skip)]
+    model_output: String,
+}
+
+// Documented as the string it is serialized as
+impl utoipa::PartialSchema for Answer {
+    fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+        String::schema()
+    }
+}
+
+impl ToSchema for Answer {}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 enum Progress {
+    /// Identifies the request, e.g. for submitting feedback; always the first event
+    RequestId(String),
     Status(String),
     Schema(String),
     CypherQuery(String),
     CypherResult(String),
     ModelOutputChunk(String),
-    Result(String),
+    Result(#[schema(inline)] Answer),
+    /// A problem that does not stop the request, e.g. a possible prompt injection
+    Warning(String),
     Error(String),
+    Usage(RequestUsage),
 }
 
+/// The schema cache key of a graph on a resolved connection
+fn schema_cache_key(
+    falkordb_connection: &str,
+    graph_name: &str,
+) -> (String, String) {
+    (falkordb_connection.to_string(), graph_name.to_string())
+}
+
+/// Drop the cached schemas of `graph_name` on every connection
 fn process_clear_schema_cache(graph_name: &str) {
     tracing::info!("Clearing schema cache for graph: {graph_name}");
     let cache = AppConfig::get().schema_cache.clone();
-    cache.invalidate(graph_name);
+    for (key, _) in &cache {
+        if key.1 == graph_name {
+            cache.invalidate(&*key);
+        }
+    }
 }
 
 #[utoipa::path(
@@ -260,7 +453,7 @@ fn process_clear_schema_cache(graph_name: &str) {
     path = "/get_schema/{graph_name}",
     params(
         ("graph_name" = String, Path, description = "Name of the graph to get schema for"),
-        ("falkordb_connection" = Option<String>, Query, description = "Optional FalkorDB connection string to override default")
+        ("falkordb_connection" = Option<String>, Query, description = "Optional name of a configured connection to use instead of the default; connection strings need the admin scope and server.allow_raw_connections")
     ),
     responses(
         (status = 200, description = "Graph schema as JSON string", body = String)
@@ -268,18 +461,30 @@ fn process_clear_schema_cache(graph_name: &str) {
 )]
 #[actix_web::get("/get_schema/{graph_name}")]
 async fn get_schema_endpoint(
+    principal: Principal,
     graph_name: actix_web::web::Path<String>,
     query: actix_web::web::Query<GetSchemaQuery>,
 ) -> Result<impl Responder, actix_web::Error> {
     let graph_name = graph_name.into_inner();
-    let falkordb_connection = query
-        .falkordb_connection
-        .as_ref()
-        .unwrap_or_else(|| &AppConfig::get().falkordb_connection);
+    principal.authorize_graph(&graph_name)?;
+    if let Some(connection) = &query.falkordb_connection {
+        principal.authorize_connection(connection)?;
+    }
+    let falkordb_connection = query.falkordb_connection.as_deref().map_or_else(
+        || AppConfig::get().connection_for(&graph_name),
+        |connection| Settings::get().resolve_connection(connection),
+    );
 
     tracing::info!("Getting schema for graph: {}", graph_name);
 
-    match get_graph_schema_string(falkordb_connection, &graph_name).await {
+    let access = Settings::get().access_policy(&graph_name, principal.name());
+    let schema = get_graph_schema_string(&falkordb_connection, &graph_name)
+        .await
+        .and_then(|schema| match access {
+            Some(access) => Ok(access.filter_schema_json(&schema)?),
+            None => Ok(schema),
+        });
+    match schema {
         Ok(schema) => Ok(HttpResponse::Ok().json(schema)),
         Err(e) => {
             tracing::error!("Failed to get schema for graph {}: {}", graph_name, e);
@@ -298,9 +503,12 @@ async fn get_schema_endpoint(
     )
 )]
 #[actix_web::get("/list_graphs")]
-async fn list_graphs_endpoint() -> Result<impl Responder, actix_web::Error> {
+async fn list_graphs_endpoint(principal: Principal) -> Result<impl Responder, actix_web::Error> {
     match get_graphs_list().await {
-        Ok(graphs) => Ok(HttpResponse::Ok().json(graphs)),
+        Ok(mut graphs) => {
+            graphs.retain(|graph| principal.allows_graph(graph));
+            Ok(HttpResponse::Ok().json(graphs))
+        }
         Err(e) => {
             tracing::error!("Failed to list graphs: {}", e);
             Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -310,22 +518,261 @@ async fn list_graphs_endpoint() -> Result<impl Responder, actix_web::Error> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/list_connections",
+    responses(
+        (status = 200, description = "Names of the configured connections the API key may select", body = Vec<String>)
+    )
+)]
+#[actix_web::get("/list_connections")]
+async fn list_connections_endpoint(principal: Principal) -> impl Responder {
+    let connections: Vec<&String> = Settings::get()
+        .connections
+        .keys()
+        .filter(|name| principal.allows_connection(name))
+        .collect();
+    HttpResponse::Ok().json(connections)
+}
+
 #[utoipa::path(
     post,
     path = "/clear_schema_cache/{graph_name}",
     params(
-        ("graph_name" = String, Path, description = "Name of the graph to clear from cache")
+        ("graph_name" = String, Path, description = "Name of the graph to clear from the cache, on every connection")
     ),
     responses(
-        (status = 200, description = "Schema cache cleared successfully")
+        (status = 200, description = "Schema cache cleared successfully"),
+        (status = 403, description = "The API key may not access the graph", body = error::ErrorResponse)
     )
 )]
-#[post("/clear_schema_cache/{graph_name}")]
-async fn clear_schema_cache(graph_name: actix_web::web::Path<String>) -> impl Responder {
+#[post("/clear_schema_cache/{graph_name}", wrap = "from_fn(auth::require_admin)")]
+async fn clear_schema_cache(
+    principal: Principal,
+    graph_name: actix_web::web::Path<String>,
+) -> Result<impl Responder, ApiError> {
     let graph_name = graph_name.into_inner();
+    principal.authorize_graph(&graph_name)?;
     tracing::info!("Clearing schema cache for graph: {}", graph_name);
     process_clear_schema_cache(&graph_name);
-    HttpResponse::new(StatusCode::OK)
+    Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Service metrics in Prometheus text format", content_type = "text/plain")
+    )
+)]
+#[actix_web::get("/metrics", wrap = "from_fn(auth::require_admin)")]
+async fn metrics_endpoint() -> impl Responder {
+    match Metrics::get().render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            tracing::error!("Failed to render metrics: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/usage",
+    responses(
+        (status = 200, description = "Token usage and estimated cost per API key and per graph", body = UsageReport)
+    )
+)]
+#[actix_web::get("/usage", wrap = "from_fn(auth::require_admin)")]
+async fn usage_endpoint() -> impl Responder {
+    HttpResponse::Ok().json(AppConfig::get().usage_ledger.report())
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "The service is running")
+    )
+)]
+#[actix_web::get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Every dependency check passed", body = ReadinessStatus),
+        (status = 503, description = "At least one dependency check failed", body = ReadinessStatus)
+    )
+)]
+#[actix_web::get("/readyz")]
+async fn readyz(shutdown: web::Data<Shutdown>) -> impl Responder {
+    let status = ReadinessStatus {
+        ready: readiness(&shutdown).await.ready,
+    };
+    if status.ready {
+        HttpResponse::Ok().json(status)
+    } else {
+        HttpResponse::ServiceUnavailable().json(status)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz/details",
+    responses(
+        (status = 200, description = "Every dependency check passed", body = Readiness),
+        (status = 503, description = "At least one dependency check failed", body = Readiness)
+    )
+)]
+#[actix_web::get("/readyz/details", wrap = "from_fn(auth::require_admin)")]
+async fn readyz_details(shutdown: web::Data<Shutdown>) -> impl Responder {
+    let readiness = readiness(&shutdown).await;
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+/// The dependency checks, reused for a few seconds, and whether the server is shutting down
+async fn readiness(shutdown: &Shutdown) -> Readiness {
+    let mut checks = health::cached_checks(|| async {
+        let config = AppConfig::get();
+        let connection_checks = config
+            .readiness_connections()
+            .into_iter()
+            .map(|(name, connection)| async move { health::check_connection(&name, &connection).await });
+        let mut checks = futures::future::join_all(connection_checks).await;
+        checks.push(health::check_templates());
+        checks.push(health::check_configured(
+            "default_model",
+            config.default_model.is_some(),
+        ));
+        checks.push(health::check_configured("default_key", config.default_key.is_some()));
+        checks
+    })
+    .await;
+    checks.push(health::check_mcp());
+    if shutdown.is_draining() {
+        checks.push(health::Check::failed("shutdown", "draining in-flight requests"));
+    }
+    Readiness::new(checks)
+}
+
+#[utoipa::path(
+    get,
+    path = "/limits",
+    responses(
+        (status = 200, description = "Rate limits, daily quotas and their current counters", body = LimitsReport)
+    )
+)]
+#[actix_web::get("/limits", wrap = "from_fn(auth::require_admin)")]
+async fn limits_endpoint() -> impl Responder {
+    let report = Limiter::get().report().await;
+    HttpResponse::Ok().json(report)
+}
+
+#[utoipa::path(
+    post,
+    path = "/feedback",
+    request_body = FeedbackRequest,
+    responses(
+        (status = 200, description = "Feedback stored", body = FeedbackEntry),
+        (status = 403, description = "The API key may not access the graph of the request", body = error::ErrorResponse),
+        (status = 404, description = "Unknown or expired request id", body = error::ErrorResponse)
+    )
+)]
+#[post("/feedback")]
+async fn submit_feedback(
+    principal: Principal,
+    req: actix_web::web::Json<FeedbackRequest>,
+) -> Result<impl Responder, ApiError> {
+    let entry = process_submit_feedback(&principal, req.into_inner())?;
+    tracing::info!(
+        "Stored {:?} feedback {} for request {}",
+        entry.rating,
+        entry.id,
+        entry.request_id
+    );
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Store feedback on a recent request, if the caller may use the request's graph
+fn process_submit_feedback(
+    principal: &Principal,
+    request: FeedbackRequest,
+) -> Result<FeedbackEntry, ApiError> {
+    let store = &AppConfig::get().feedback_store;
+    principal.authorize_graph(&store.graph_of(&request.request_id)?)?;
+    Ok(store.submit(request)?)
+}
+
+#[derive(Debug, Deserialize)]
+struct ListFeedbackQuery {
+    graph_name: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/feedback",
+    params(
+        ("graph_name" = Option<String>, Query, description = "Only list feedback for this graph")
+    ),
+    responses(
+        (status = 200, description = "Stored feedback", body = Vec<FeedbackEntry>)
+    )
+)]
+#[actix_web::get("/feedback", wrap = "from_fn(auth::require_admin)")]
+async fn list_feedback(query: actix_web::web::Query<ListFeedbackQuery>) -> impl Responder {
+    HttpResponse::Ok().json(AppConfig::get().feedback_store.list(query.graph_name.as_deref()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/feedback/{feedback_id}/promote",
+    params(
+        ("feedback_id" = String, Path, description = "Id of the feedback to promote")
+    ),
+    responses(
+        (status = 200, description = "Feedback promoted into the graph's example set", body = Example),
+        (status = 400, description = "Feedback has no approved query", body = error::ErrorResponse),
+        (status = 404, description = "Unknown feedback id", body = error::ErrorResponse)
+    )
+)]
+#[post("/feedback/{feedback_id}/promote", wrap = "from_fn(auth::require_admin)")]
+async fn promote_feedback(feedback_id: actix_web::web::Path<String>) -> Result<impl Responder, ApiError> {
+    let example = AppConfig::get().feedback_store.promote(&feedback_id)?;
+    tracing::info!(
+        "Promoted feedback {} into examples for graph {}",
+        feedback_id,
+        example.graph_name
+    );
+    Ok(HttpResponse::Ok().json(example))
+}
+
+#[utoipa::path(
+    get,
+    path = "/examples/{graph_name}",
+    params(
+        ("graph_name" = String, Path, description = "Name of the graph to export examples for")
+    ),
+    responses(
+        (status = 200, description = "Promoted question/query examples of the graph", body = Vec<Example>)
+    )
+)]
+#[actix_web::get("/examples/{graph_name}")]
+async fn export_examples(
+    principal: Principal,
+    graph_name: actix_web::web::Path<String>,
+) -> Result<impl Responder, ApiError> {
+    principal.authorize_graph(&graph_name)?;
+    Ok(HttpResponse::Ok().json(AppConfig::get().feedback_store.examples(&graph_name)))
 }
 
 #[utoipa::path(
@@ -333,14 +780,51 @@ async fn clear_schema_cache(graph_name: actix_web::web::Path<String>) -> impl Re
     path = "/text_to_cypher",
     request_body = TextToCypherRequest,
     responses(
-        (status = 200, description = "Stream text to Cypher conversion progress", content_type = "text/event-stream")
+        (status = 200, description = "Stream text to Cypher conversion progress", content_type = "text/event-stream"),
+        (status = 403, description = "The API key may not access the graph or connection", body = error::ErrorResponse),
+        (status = 429, description = "Rate limit or daily quota exceeded; see the Retry-After header", body = error::ErrorResponse)
     )
 )]
 #[post("/text_to_cypher")]
-async fn text_to_cypher(req: actix_web::web::Json<TextToCypherRequest>) -> Result<impl Responder, actix_web::Error> {
+#[allow(clippy::future_not_send)] // actix handlers run on a single-threaded worker
+async fn text_to_cypher(
+    http_request: actix_web::HttpRequest,
+    principal: Principal,
+    shutdown: web::Data<Shutdown>,
+    req: actix_web::web::Json<TextToCypherRequest>,
+) -> Result<impl Responder, actix_web::Error> {
     let mut request = req.into_inner();
     let config = AppConfig::get();
 
+    if shutdown.is_draining() {
+        return Err(ApiError::ServiceUnavailable("The server is shutting down".to_string()).into());
+    }
+    principal.authorize_graph(&request.graph_name)?;
+    if let Some(connection) = &request.falkordb_connection {
+        principal.authorize_connection(connection)?;
+    }
+    if let Err(e) = config.check_requested_model(request.model.as_deref()) {
+        Metrics::get().record_request(RequestOutcome::InvalidRequest);
+        return Err(e.into());
+    }
+
+    // Continue the caller's trace if a traceparent header was sent
+    let span = tracing::info_span!("text_to_cypher", graph = %request.graph_name, model = tracing::field::Empty);
+    telemetry::set_parent_from_headers(&span, http_request.headers());
+    let client_addr = limits::client_addr(&http_request);
+
+    // Refuse requests over a limit before the event stream opens
+    let subjects = principal.limit_subjects(&client_addr);
+    if let Err(limited) = Limiter::get().check(&subjects).await {
+        tracing::warn!(
+            "Rate limited request for graph {}: {}",
+            request.graph_name,
+            limited.message
+        );
+        Metrics::get().record_request(RequestOutcome::RateLimited);
+        return Err(ApiError::from(limited).into());
+    }
+
     // Apply defaults from .env file if values are not provided
     if request.model.is_none() {
         request.model.clone_from(&config.default_model);
@@ -354,140 +838,412 @@ async fn text_to_cypher(req: actix_web::web::Json<TextToCypherRequest>) -> Resul
 
     // Ensure we have a model after applying defaults
     if request.model.is_none() {
+        Metrics::get().record_request(RequestOutcome::InvalidRequest);
         // Send error via SSE instead of returning HTTP error
         tokio::spawn(async move {
-            let error_event = sse::Event::Data(sse::Data::new(
-                serde_json::to_string(&Progress::Error(
-                    "Model must be provided either in request or as DEFAULT_MODEL in .env file".to_string(),
-                ))
-                .unwrap_or_else(|_| r#"{"Error":"Serialization failed"}"#.to_string()),
-            ));
-            let _ = tx.send(error_event).await;
+            let error = Progress::Error(
+                "Model must be provided either in request or as DEFAULT_MODEL in .env file".to_string(),
+            );
+            let _ = tx.send(error).await;
         });
-        let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, actix_web::Error>);
-        return Ok(Sse::from_stream(stream));
+        return Ok(progress_events(rx));
     }
 
     let model = request.model.as_ref().unwrap(); // Safe to unwrap after the check above
 
-    let client = request.key.as_ref().map_or_else(genai::Client::default, |key| {
-        let key = key.clone(); // Clone the key for use in the closure
-        let auth_resolver = AuthResolver::from_resolver_fn(
-            move |model_iden: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
-                let ModelIden {
-                    adapter_kind,
-                    model_name,
-                } = model_iden;
-                tracing::info!("Using custom auth provider for {adapter_kind} (model: {model_name})");
-
-                // Use the provided key instead of reading from environment
-                Ok(Some(AuthData::from_single(key.clone())))
-            },
-        );
-        genai::Client::builder().with_auth_resolver(auth_resolver).build()
-    });
-
-    // Handle service target resolution errors via SSE
-    let service_target = match client.resolve_service_target(model).await {
-        Ok(target) => target,
+    // Handle backend resolution errors via SSE
+    let backend = match llm::backend_for(model, request.key.as_deref()).await {
+        Ok(backend) => backend,
         Err(e) => {
+            Metrics::get().record_model_error(model);
+            Metrics::get().record_request(RequestOutcome::InvalidRequest);
             // Send error via SSE instead of returning HTTP error
             tokio::spawn(async move {
-                let error_event = sse::Event::Data(sse::Data::new(
-                    serde_json::to_string(&Progress::Error(format!("Failed to resolve service target: {e}")))
-                        .unwrap_or_else(|_| r#"{"Error":"Serialization failed"}"#.to_string()),
-                ));
-                let _ = tx.send(error_event).await;
+                let _ = tx.send(Progress::Error(format!("Failed to resolve service target: {e}"))).await;
             });
-            let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, actix_web::Error>);
-            return Ok(Sse::from_stream(stream));
+            return Ok(progress_events(rx));
         }
     };
 
-    tokio::spawn(async move {
-        process_text_to_cypher_request(request, client, service_target, tx).await;
-    });
+    span.record("model", model.as_str());
 
-    let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, actix_web::Error>);
+    let context = RequestContext::new(&principal, &request, &client_addr, subjects);
+    spawn_pipeline(request, context, backend, tx, span, &shutdown);
 
-    Ok(Sse::from_stream(stream))
+    Ok(progress_events(rx))
+}
+
+/// Run the pipeline in the background, counted as in flight, cutting the stream off with a final
+/// event if it outlives the shutdown deadline; cancelling the returned token stops it
+fn spawn_pipeline(
+    request: TextToCypherRequest,
+    context: RequestContext,
+    backend: Arc<dyn ChatBackend>,
+    tx: mpsc::Sender<Progress>,
+    span: tracing::Span,
+    shutdown: &Shutdown,
+) -> CancellationToken {
+    let in_flight = shutdown.track();
+    let shutdown = shutdown.clone();
+    let cancel = CancellationToken::new();
+    let cancelled = cancel.clone();
+    let stop = async move {
+        tokio::select! {
+            () = cancelled.cancelled() => RequestOutcome::Cancelled,
+            () = shutdown.reached(Phase::Expired) => RequestOutcome::Shutdown,
+        }
+    };
+    tokio::spawn(
+        async move {
+            let _in_flight = in_flight;
+            process_text_to_cypher_request(request, context, backend, tx, stop).await;
+        }
+        .instrument(span),
+    );
+    cancel
+}
+
+/// The server-sent event stream of the progress events the pipeline writes to `rx`
+fn progress_events(
+    rx: mpsc::Receiver<Progress>
+) -> Sse<impl futures_util::Stream<Item = Result<sse::Event, Infallible>>> {
+    let events = tokio_stream::wrappers::ReceiverStream::new(rx).map(|progress| {
+        let json = serde_json::to_string(&progress).unwrap_or_else(|e| {
+            tracing::error!("Failed to serialize progress update: {}", e);
+            r#"{"Error":"Serialization failed"}"#.to_string()
+        });
+        Ok(sse::Event::Data(sse::Data::new(json)))
+    });
+    Sse::from_stream(events)
 }
 
-#[allow(clippy::cognitive_complexity)]
 async fn process_text_to_cypher_request(
     request: TextToCypherRequest,
-    client: genai::Client,
-    service_target: genai::ServiceTarget,
-    tx: mpsc::Sender<sse::Event>,
+    context: RequestContext,
+    backend: Arc<dyn ChatBackend>,
+    tx: mpsc::Sender<Progress>,
+    stop: impl Future<Output = RequestOutcome>,
 ) {
-    tracing::info!("Processing text to Cypher request: {request:?}");
+    tracing::info!(
+        "Processing text to Cypher request {} for graph {}",
+        context.request_id,
+        request.graph_name
+    );
+    tracing::debug!("Text to Cypher request: {request:?}");
+    send!(tx, Progress::RequestId(context.request_id.clone()));
 
     let model = request
         .model
         .as_ref()
         .expect("Model should be available after applying defaults");
 
-    let falkordb_connection = request
-        .clone()
-        .falkordb_connection
-        .unwrap_or_else(|| AppConfig::get().falkordb_connection.clone());
+    let _in_flight = Metrics::get().in_flight_guard();
+    let mut usage = RequestUsage::new(model);
+    let mut audit = AuditRecord {
+        request_id: context.request_id.clone(),
+        caller: context.caller.clone(),
+        graph: request.graph_name.clone(),
+        model: model.clone(),
+        question: last_user_question(&request.chat_request).to_string(),
+        ..AuditRecord::default()
+    };
+    // A stopped pipeline is still accounted for, with the tokens it used so far
+    let outcome = tokio::select! {
+        outcome = run_text_to_cypher_pipeline(
+            &request,
+            context.access.as_ref(),
+            backend.as_ref(),
+            &tx,
+            &mut usage,
+            &mut audit,
+        ) => outcome,
+        outcome = stop => outcome,
+    };
+    match outcome {
+        RequestOutcome::Shutdown => {
+            tracing::warn!("Closing in-flight request at the shutdown deadline");
+            let _ = tx.send(Progress::Error(SHUTDOWN_MESSAGE.to_string())).await;
+        }
+        RequestOutcome::Cancelled => tracing::info!("Request {} was cancelled", context.request_id),
+        _ => {}
+    }
+    Metrics::get().record_request(outcome);
+    write_audit_entry(audit, outcome);
+    Limiter::get()
+        .record_tokens(&context.subjects, usage.total().total_tokens())
+        .await;
+    record_and_send_usage(&request, &context.account, usage, &tx).await;
+}
+
+/// The content of the last user message, which is the question being answered
+fn last_user_question(chat_request: &ChatRequest) -> &str {
+    chat_request
+        .messages
+        .iter()
+        .rev()
+        .find(|message| message.role == ChatRole::User)
+        .map_or("", |message| message.content.as_str())
+}
+
+fn write_audit_entry(
+    record: AuditRecord,
+    outcome: RequestOutcome,
+) {
+    let Some(audit_log) = &AppConfig::get().audit_log else {
+        return;
+    };
+
+    let entry = AuditEntry::new(record, outcome.as_str(), audit_log.redaction());
+    if let Err(e) = audit_log.append(&entry) {
+        tracing::error!("Failed to write audit entry for request {}: {}", entry.request_id, e);
+    }
+}
+
+/// Apply the system message and injection policies, returning the request to process, or `None`
+/// if it was rejected
+async fn screen_request(
+    request: &TextToCypherRequest,
+    tx: &mpsc::Sender<Progress>,
+    audit: &mut AuditRecord,
+) -> Option<TextToCypherRequest> {
+    let settings = &Settings::get().guard;
+    let screening = guard::screen(&request.chat_request, settings);
+
+    if !screening.signals.is_empty() {
+        let signals = guard::signal_names(&screening.signals);
+        tracing::warn!(
+            "Possible prompt injection in request {}: {}",
+            audit.request_id,
+            signals.join(", ")
+        );
+        send_option!(
+            tx,
+            Progress::Warning(format!("Possible prompt injection detected: {}", signals.join(", ")))
+        );
+        audit.injection_signals = signals;
+    }
+
+    if let Some(reason) = screening.rejection {
+        send_option!(tx, Progress::Error(reason));
+        return None;
+    }
+
+    if screening.system_messages > 0 && settings.system_messages == SystemMessagePolicy::Strip {
+        tracing::warn!(
+            "Stripped {} system message(s) from request {}",
+            screening.system_messages,
+            audit.request_id
+        );
+        send_option!(
+            tx,
+            Progress::Warning(format!(
+                "Ignored {} system message(s) supplied by the client",
+                screening.system_messages
+            ))
+        );
+    }
+
+    Some(TextToCypherRequest {
+        chat_request: screening.chat_request,
+        ..request.clone()
+    })
+}
+
+#[allow(clippy::cognitive_complexity)]
+async fn run_text_to_cypher_pipeline(
+    request: &TextToCypherRequest,
+    access: Option<&AccessPolicy>,
+    backend: &dyn ChatBackend,
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut RequestUsage,
+    audit: &mut AuditRecord,
+) -> RequestOutcome {
+    let model = usage.model.clone();
+    let metrics = Metrics::get();
+
+    // Step 0: Apply the prompt-injection defenses to the client's messages
+    let Some(request) = &screen_request(request, tx, audit).await else {
+        return RequestOutcome::Rejected;
+    };
+
+    let falkordb_connection = request.falkordb_connection.as_deref().map_or_else(
+        || AppConfig::get().connection_for(&request.graph_name),
+        |connection| Settings::get().resolve_connection(connection),
+    );
 
     // Step 1: Send processing status
-    send_processing_status(&request, &service_target, &tx).await;
+    send_processing_status(request, backend, tx).await;
 
     // Step 2: Discover schema
-    let Some(schema) = get_or_discover_schema(&falkordb_connection, &request.graph_name, &tx).await else {
-        send!(tx, Progress::Error("Failed to discover schema".to_string()));
-        return;
+    let timer = metrics.stage_timer(Stage::Discovery);
+    let schema = get_or_discover_schema(&falkordb_connection, &request.graph_name, access, tx).await;
+    timer.observe_duration();
+    let Some(schema) = schema else {
+        send_or_outcome!(
+            tx,
+            Progress::Error("Failed to discover schema".to_string()),
+            RequestOutcome::SchemaError
+        );
+        return RequestOutcome::SchemaError;
     };
 
     // Step 3: Generate and execute cypher query
-    let Some(query) = generate_cypher_query(&request, &schema, &client, model, &tx).await else {
-        return;
+    let timer = metrics.stage_timer(Stage::Generation);
+    let dialect = request
+        .dialect
+        .or_else(|| Settings::get().graph(&request.graph_name).and_then(|graph| graph.dialect))
+        .unwrap_or_else(|| graph::dialect_for(&falkordb_connection));
+    let query = generate_cypher_query(
+        request,
+        &schema,
+        dialect,
+        backend,
+        &model,
+        tx,
+        &mut usage.query_generation,
+    )
+    .await;
+    timer.observe_duration();
+    let Some(query) = query else {
+        return RequestOutcome::GenerationError;
     };
+    // Audit what the model generated even if the access policy rejects it
+    audit.cypher = Some(query.clone());
+    let Some(query) = enforce_access_policy(query, access, tx).await else {
+        return RequestOutcome::Rejected;
+    };
+    audit.cypher = Some(query.clone());
+    AppConfig::get().feedback_store.remember(
+        &audit.request_id,
+        Interaction {
+            graph_name: request.graph_name.clone(),
+            question: audit.question.clone(),
+            schema_fingerprint: audit::sha256_hex(&schema),
+            generated_query: query.clone(),
+        },
+    );
 
     // Step 4: Execute the query and get results
-    let Ok(query_result) = execute_cypher_query(&query, &request.graph_name, &tx).await else {
-        return;
+    let timer = metrics.stage_timer(Stage::Execution);
+    let query_result =
+        execute_cypher_query(&query, &falkordb_connection, &request.graph_name, access, &model, tx).await;
+    timer.observe_duration();
+    let Ok(query_result) = query_result else {
+        return RequestOutcome::ExecutionError;
     };
+    audit.row_count = Some(query_result.records.row_count);
+    audit.result = Some(query_result.records.full_text.clone());
+    audit.result_hash = Some(query_result.hash.clone());
 
     // Step 5: Generate final answer using AI
-    generate_final_answer(&request, &query, &query_result, &client, model, &tx).await;
+    let timer = metrics.stage_timer(Stage::Answer);
+    let answer = generate_final_answer(
+        request,
+        &query,
+        &query_result,
+        backend,
+        &model,
+        tx,
+        &mut usage.answer_generation,
+    )
+    .await;
+    timer.observe_duration();
+
+    match answer {
+        Some(answer) if !answer.is_empty() => RequestOutcome::Success,
+        _ => RequestOutcome::AnswerError,
+    }
 }
 
+async fn record_and_send_usage(
+    request: &TextToCypherRequest,
+    account: &str,
+    mut usage: RequestUsage,
+    tx: &mpsc::Sender<Progress>,
+) {
+    let config = AppConfig::get();
+    usage.estimated_cost = config
+        .model_prices
+        .get(&usage.model)
+        .map(|price| price.estimate_cost(&usage.total()));
+
+    config.usage_ledger.record(account, &request.graph_name, &usage);
+    tracing::info!(
+        "Request used {} tokens for graph {} (key {}): {:?}",
+        usage.total().total_tokens(),
+        request.graph_name,
+        account,
+        usage
+    );
+
+    send!(tx, Progress::Usage(usage));
+}
+
+#[tracing::instrument(skip(falkordb_connection, access, tx))]
 async fn get_or_discover_schema(
     falkordb_connection: &str,
     graph_name: &str,
-    tx: &mpsc::Sender<sse::Event>,
+    access: Option<&AccessPolicy>,
+    tx: &mpsc::Sender<Progress>,
 ) -> Option<String> {
     let cache = AppConfig::get().schema_cache.clone();
-    let schema = match cache.get(graph_name) {
+    let key = schema_cache_key(falkordb_connection, graph_name);
+    let cached = cache.get(&key);
+    Metrics::get().record_schema_cache(cached.is_some());
+    let schema = match cached {
         Some(schema) => schema,
         None => match discover_and_send_schema(falkordb_connection, graph_name, tx).await {
             Ok(schema) => schema,
             Err(()) => return None,
         },
     };
+    cache.insert(key, schema.clone());
+
+    // The cache holds the full schema; callers only see what their access policy allows
+    let schema = match access.map(|access| access.filter_schema_json(&schema)) {
+        None => schema,
+        Some(Ok(filtered)) => filtered,
+        Some(Err(e)) => {
+            tracing::error!("Failed to apply the access policy to the schema of graph {graph_name}: {e}");
+            send_option!(tx, Progress::Error("Failed to apply the access policy".to_string()));
+            return None;
+        }
+    };
     send_option!(tx, Progress::Schema(schema.clone()));
-    cache.insert(graph_name.to_string(), schema.clone());
-    Some(schema.clone())
+    Some(schema)
 }
 
 #[allow(clippy::cognitive_complexity)]
+#[tracing::instrument(skip_all, fields(model = %model))]
 async fn generate_cypher_query(
     request: &TextToCypherRequest,
     schema: &str,
-    client: &genai::Client,
+    dialect: Dialect,
+    backend: &dyn ChatBackend,
     model: &str,
-    tx: &mpsc::Sender<sse::Event>,
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut TokenUsage,
 ) -> Option<String> {
     send_option!(
         tx,
         Progress::Status(String::from("Generating Cypher query using schema ..."))
     );
 
-    let genai_chat_request = generate_create_cypher_query_chat_request(&request.chat_request, schema);
-    let query = execute_chat(client, model, genai_chat_request, tx).await;
+    let model_family = backend.provider().to_lowercase();
+    let scope = TemplateScope {
+        graph: &request.graph_name,
+        model_family: &model_family,
+    };
+    let genai_chat_request =
+        match generate_create_cypher_query_chat_request(&request.chat_request, scope, schema, dialect) {
+            Ok(genai_chat_request) => genai_chat_request,
+            Err(e) => {
+                tracing::error!("Failed to render the query generation prompt: {e}");
+                send_option!(tx, Progress::Error(PROMPT_RENDER_ERROR.to_string()));
+                return None;
+            }
+        };
+    let query = execute_chat(backend, model, genai_chat_request, tx, usage).await;
 
     if query.trim().is_empty() {
         tracing::warn!("No query generated from AI model");
@@ -496,23 +1252,81 @@ async fn generate_cypher_query(
     }
 
     let clean_query = query.replace('\n', " ").replace("```", "").trim().to_string();
-    send_option!(tx, Progress::CypherQuery(clean_query.clone()));
+    if let Err(e) = dialect.validate(&clean_query) {
+        tracing::warn!("Generated query is not valid for {dialect}: {e}: {clean_query}");
+        send_option!(
+            tx,
+            Progress::Error(format!("Generated query is not valid for {dialect}: {e}"))
+        );
+        return None;
+    }
+
     Some(clean_query)
 }
 
+/// Check the generated query against the caller's access policy and send the query that will run,
+/// or `None` if the policy rejects it
+async fn enforce_access_policy(
+    query: String,
+    access: Option<&AccessPolicy>,
+    tx: &mpsc::Sender<Progress>,
+) -> Option<String> {
+    let query = match access.map(|access| access.enforce(&query)) {
+        None => query,
+        Some(Ok(enforced)) => {
+            if !enforced.rewritten.is_empty() {
+                tracing::warn!("Rewrote hidden properties in generated query: {query}");
+                send_option!(
+                    tx,
+                    Progress::Warning(format!(
+                        "Hidden properties were replaced with null: {}",
+                        enforced.rewritten.join(", ")
+                    ))
+                );
+            }
+            enforced.query
+        }
+        Some(Err(reason)) => {
+            tracing::warn!("Generated query violates the access policy: {reason}: {query}");
+            send_option!(
+                tx,
+                Progress::Error(format!("Generated query violates the access policy: {reason}"))
+            );
+            return None;
+        }
+    };
+
+    send_option!(tx, Progress::CypherQuery(query.clone()));
+    Some(query)
+}
+
 #[allow(clippy::cognitive_complexity)]
 async fn execute_cypher_query(
     query: &str,
+    falkordb_connection: &str,
     graph_name: &str,
-    tx: &mpsc::Sender<sse::Event>,
-) -> Result<String, ()> {
+    access: Option<&AccessPolicy>,
+    model: &str,
+    tx: &mpsc::Sender<Progress>,
+) -> Result<QueryResult, ()> {
     send_result!(tx, Progress::Status(String::from("Executing Cypher query...")));
     tracing::info!("Executing Cypher Query: {}", query);
 
-    match execute_query(query, graph_name, tx).await {
+    let token_budget = AppConfig::get().token_budget_for(model);
+    match execute_query(query, falkordb_connection, graph_name, access, token_budget).await {
         Ok(result) => {
-            tracing::info!("Query executed successfully, result: {}", result);
-            send_result!(tx, Progress::CypherResult(result.clone()));
+            tracing::info!(
+                "Query executed successfully (truncated: {}, redacted: {}), result: {}",
+                result.records.truncated,
+                !result.placeholders.is_empty(),
+                result.records.text
+            );
+            // The client sees every row with the real values; only the model is given the
+            // summary and the placeholders
+            send_result!(
+                tx,
+                Progress::CypherResult(result.placeholders.rehydrate(&result.records.full_text))
+            );
             Ok(result)
         }
         Err(e) => {
@@ -523,56 +1337,78 @@ async fn execute_cypher_query(
     }
 }
 
+#[tracing::instrument(skip_all, fields(model = %model))]
 async fn generate_final_answer(
     request: &TextToCypherRequest,
     query: &str,
-    query_result: &str,
-    client: &genai::Client,
+    query_result: &QueryResult,
+    backend: &dyn ChatBackend,
     model: &str,
-    tx: &mpsc::Sender<sse::Event>,
-) {
-    send!(
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut TokenUsage,
+) -> Option<String> {
+    send_option!(
         tx,
         Progress::Status(String::from(
             "Generating answer from chat history and Cypher output using AI model..."
         ))
     );
 
-    let genai_chat_request = generate_answer_chat_request(&request.chat_request, query, query_result);
-    execute_chat_stream(client, model, genai_chat_request, tx).await;
+    let model_family = backend.provider().to_lowercase();
+    let scope = TemplateScope {
+        graph: &request.graph_name,
+        model_family: &model_family,
+    };
+    let genai_chat_request = match generate_answer_chat_request(&request.chat_request, scope, query, query_result) {
+        Ok(genai_chat_request) => genai_chat_request,
+        Err(e) => {
+            tracing::error!("Failed to render the answer prompt: {e}");
+            send_option!(tx, Progress::Error(PROMPT_RENDER_ERROR.to_string()));
+            return None;
+        }
+    };
+    Some(
+        execute_chat_stream(
+            backend,
+            model,
+            genai_chat_request,
+            &query_result.placeholders,
+            tx,
+            usage,
+        )
+        .await,
+    )
 }
 
+#[tracing::instrument(skip(access))]
 async fn execute_query(
     query: &str,
+    falkordb_connection: &str,
     graph_name: &str,
-    tx: &mpsc::Sender<sse::Event>,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let connection_info: FalkorConnectionInfo = AppConfig::get()
-        .falkordb_connection
-        .as_str()
-        .try_into()
-        .map_err(|e| format!("Invalid connection info: {e}"))?;
-
-    let client = FalkorClientBuilder::new_async()
-        .with_connection_info(connection_info)
-        .build()
-        .await
-        .map_err(|e| format!("Failed to build client: {e}"))?;
-
-    let graph_name = graph_name.to_string();
-    let query = query.to_string();
-
-    // Run the FalkorDB operations in a blocking context
-    let result = tokio::task::spawn_blocking(move || execute_query_blocking(&client, &graph_name, &query))
-        .await
-        .map_err(|e| format!("Failed to execute blocking task: {e}"))?;
-
-    let formatted_result = match result {
-        Ok(records) => format_query_records(&records),
+    access: Option<&AccessPolicy>,
+    token_budget: usize,
+) -> Result<QueryResult, GraphError> {
+    let backend = graph::connect(falkordb_connection).await?;
+
+    let formatted_result = match backend.ro_query(graph_name, query).await {
+        Ok(mut records) => {
+            let hash = audit::records_hash(&records);
+            if let Some(access) = access {
+                access.redact(&mut records);
+            }
+            let mut placeholders = Placeholders::default();
+            if let Some(redactor) = Redactor::for_graph(graph_name) {
+                redactor.redact(&mut records, &graph::return_columns(query), &mut placeholders);
+            }
+            QueryResult {
+                records: format_query_records_within_budget(&records, token_budget),
+                placeholders,
+                hash,
+            }
+        }
         Err(e) => {
-            let error_msg = format!("Query execution failed: {e}");
-            try_send_boxed!(tx, Progress::Error(error_msg.clone()));
-            return Err(error_msg.into());
+            Metrics::get().record_falkordb_error("query");
+            return Err(e);
         }
     };
 
@@ -586,66 +1422,52 @@ async fn get_graph_schema_string(
     let cache = AppConfig::get().schema_cache.clone();
 
     // Check cache first
-    if let Some(cached_schema) = cache.get(graph_name) {
+    let key = schema_cache_key(falkordb_connection, graph_name);
+    let cached = cache.get(&key);
+    Metrics::get().record_schema_cache(cached.is_some());
+    if let Some(cached_schema) = cached {
         return Ok(cached_schema);
     }
 
     // If not in cache, discover it
-    let schema = discover_graph_schema(falkordb_connection, graph_name).await;
+    let schema = discover_graph_schema(falkordb_connection, graph_name).await?;
     let schema_json = serde_json::to_string(&schema).map_err(|e| format!("Failed to serialize schema: {e}"))?;
 
     // Cache the result
-    cache.insert(graph_name.to_string(), schema_json.clone());
+    cache.insert(key, schema_json.clone());
 
     Ok(schema_json)
 }
 
-async fn get_graphs_list() -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
-    let connection_info: FalkorConnectionInfo = AppConfig::get()
-        .falkordb_connection
-        .as_str()
-        .try_into()
-        .map_err(|e| format!("Invalid connection info: {e}"))?;
+async fn get_graphs_list() -> Result<Vec<String>, GraphError> {
+    let backend = graph::connect(&Settings::get().resolve_connection(&AppConfig::get().falkordb_connection)).await?;
 
-    let client = FalkorClientBuilder::new_async()
-        .with_connection_info(connection_info)
-        .build()
-        .await
-        .map_err(|e| format!("Failed to build client: {e}"))?;
-
-    // Call the async version directly
-    let graphs = client.list_graphs().await.map_err(|e| format!("Failed to list graphs: {e}"))?;
+    let graphs = backend.list_graphs().await.map_err(|e| {
+        Metrics::get().record_falkordb_error("list_graphs");
+        format!("Failed to list graphs: {e}")
+    })?;
     Ok(graphs)
 }
 
-fn execute_query_blocking(
-    client: &falkordb::FalkorAsyncClient,
-    graph_name: &str,
-    query: &str,
-) -> Result<Vec<Vec<falkordb::FalkorValue>>, Box<dyn std::error::Error + Send + Sync>> {
-    // Create a new Tokio runtime for this blocking operation
-    let rt = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to create runtime: {e}"))?;
-
-    rt.block_on(async {
-        let mut graph = client.select_graph(graph_name);
-        let query_result = graph
-            .ro_query(query)
-            .execute()
-            .await
-            .map_err(|e| format!("Query execution failed: {e}"))?;
-
-        let mut records = Vec::new();
-        for record in query_result.data {
-            records.push(record);
-        }
-        Ok(records)
-    })
+/// Client content as it is placed in a prompt, wrapped in data delimiters when enabled
+fn user_content(
+    content: &str,
+    delimited: bool,
+) -> String {
+    if delimited {
+        guard::delimit(content)
+    } else {
+        content.to_string()
+    }
 }
 
 fn generate_create_cypher_query_chat_request(
     chat_request: &ChatRequest,
+    scope: TemplateScope<'_>,
     ontology: &str,
-) -> genai::chat::ChatRequest {
+    dialect: Dialect,
+) -> Result<genai::chat::ChatRequest, TemplateError> {
+    let delimited = Settings::get().guard.delimit_user_content;
     let mut chat_req = genai::chat::ChatRequest::default();
     for (index, message) in chat_request.messages.iter().enumerate() {
         let is_last_user_message = index == chat_request.messages.len() - 1 && message.role == ChatRole::User;
@@ -654,38 +1476,44 @@ fn generate_create_cypher_query_chat_request(
             ChatRole::User => {
                 if is_last_user_message {
                     // Special processing for the last user message
-                    let processed_content = process_last_user_message(&message.content);
+                    let processed_content =
+                        TemplateEngine::render_user_prompt(scope, &user_content(&message.content, delimited))?;
                     genai::chat::ChatMessage::user(processed_content)
                 } else {
-                    genai::chat::ChatMessage::user(message.content.clone())
+                    genai::chat::ChatMessage::user(user_content(&message.content, delimited))
                 }
             }
-            ChatRole::Assistant => genai::chat::ChatMessage::assistant(message.content.clone()),
+            ChatRole::Assistant => genai::chat::ChatMessage::assistant(user_content(&message.content, delimited)),
             ChatRole::System => genai::chat::ChatMessage::system(message.content.clone()),
         };
 
         chat_req = chat_req.append_message(genai_message);
     }
 
-    chat_req = chat_req.with_system(TemplateEngine::render_system_prompt(ontology).unwrap_or_else(|e| {
-        tracing::error!("Failed to load system prompt template: {}", e);
-        format!("Generate OpenCypher statements using this ontology: {ontology}")
-    }));
+    chat_req = chat_req.with_system(TemplateEngine::render_system_prompt(
+        scope,
+        ontology,
+        dialect,
+        &AppConfig::get().feedback_store.examples(scope.graph),
+        delimited,
+    )?);
 
     // Pretty print the chat request as JSON for logging
     if let Ok(pretty_json) = serde_json::to_string_pretty(&chat_req) {
-        tracing::info!("Generated genai chat request:\n{}", pretty_json);
+        tracing::debug!("Generated genai chat request:\n{}", pretty_json);
     } else {
-        tracing::info!("Generated genai chat request: {:?}", chat_req);
+        tracing::debug!("Generated genai chat request: {:?}", chat_req);
     }
-    chat_req
+    Ok(chat_req)
 }
 
 fn generate_answer_chat_request(
     chat_request: &ChatRequest,
+    scope: TemplateScope<'_>,
     cypher_query: &str,
-    cypher_result: &str,
-) -> genai::chat::ChatRequest {
+    cypher_result: &QueryResult,
+) -> Result<genai::chat::ChatRequest, TemplateError> {
+    let delimited = Settings::get().guard.delimit_user_content;
     let mut chat_req = genai::chat::ChatRequest::default();
     for (index, message) in chat_request.messages.iter().enumerate() {
         let is_last_user_message = index == chat_request.messages.len() - 1 && message.role == ChatRole::User;
@@ -694,13 +1522,21 @@ fn generate_answer_chat_request(
             ChatRole::User => {
                 if is_last_user_message {
                     // Special processing for the last user message
-                    let processed_content = process_last_request_prompt(&message.content, cypher_query, cypher_result);
+                    let processed_content = TemplateEngine::render_last_request_prompt(
+                        scope,
+                        &user_content(&message.content, delimited),
+                        cypher_query,
+                        &cypher_result.records.text,
+                        cypher_result.records.truncated,
+                        delimited,
+                        !cypher_result.placeholders.is_empty(),
+                    )?;
                     genai::chat::ChatMessage::user(processed_content)
                 } else {
-                    genai::chat::ChatMessage::user(message.content.clone())
+                    genai::chat::ChatMessage::user(user_content(&message.content, delimited))
                 }
             }
-            ChatRole::Assistant => genai::chat::ChatMessage::assistant(message.content.clone()),
+            ChatRole::Assistant => genai::chat::ChatMessage::assistant(user_content(&message.content, delimited)),
             ChatRole::System => genai::chat::ChatMessage::system(message.content.clone()),
         };
 
@@ -709,54 +1545,173 @@ fn generate_answer_chat_request(
 
     // Pretty print the chat request as JSON for logging
     if let Ok(pretty_json) = serde_json::to_string_pretty(&chat_req) {
-        tracing::info!("Generated genai chat request:\n{}", pretty_json);
+        tracing::debug!("Generated genai chat request:\n{}", pretty_json);
     } else {
-        tracing::info!("Generated genai chat request: {:?}", chat_req);
+        tracing::debug!("Generated genai chat request: {:?}", chat_req);
     }
-    chat_req
-}
-
-fn process_last_request_prompt(
-    content: &str,
-    cypher_query: &str,
-    cypher_result: &str,
-) -> String {
-    TemplateEngine::render_last_request_prompt(content, cypher_query, cypher_result).unwrap_or_else(|e| {
-        tracing::error!("Failed to load last_request_prompt template: {}", e);
-        format!("Generate an answer for: {content}")
-    })
+    Ok(chat_req)
 }
 
 #[allow(clippy::pedantic)]
 #[derive(OpenApi)]
 #[openapi(
-    paths(text_to_cypher, clear_schema_cache, list_graphs_endpoint, get_schema_endpoint),
+    paths(
+        healthz,
+        readyz,
+        readyz_details,
+        text_to_cypher,
+        clear_schema_cache,
+        list_graphs_endpoint,
+        list_connections_endpoint,
+        get_schema_endpoint,
+        usage_endpoint,
+        limits_endpoint,
+        metrics_endpoint,
+        submit_feedback,
+        list_feedback,
+        promote_feedback,
+        export_examples,
+        websocket::connect
+    ),
     components(schemas(
         TextToCypherRequest,
+        Dialect,
         Progress,
+        Readiness,
+        ReadinessStatus,
+        health::Check,
+        health::CheckStatus,
+        RequestUsage,
+        TokenUsage,
+        UsageReport,
+        usage::UsageEntry,
+        usage::UsageTotals,
+        LimitsReport,
+        limits::RateLimit,
+        limits::BucketState,
+        limits::QuotaState,
+        FeedbackRequest,
+        FeedbackEntry,
+        feedback::Rating,
+        Example,
         ChatRequest,
         ChatMessage,
         ChatRole,
+        websocket::ClientMessage,
+        websocket::ServerMessage,
         error::ErrorResponse
-    ))
+    )),
+    modifiers(&BearerAuth)
 )]
 struct ApiDoc;
 
+/// Documents the bearer API key when authentication is enabled, so Swagger UI can send it
+struct BearerAuth;
+
+impl utoipa::Modify for BearerAuth {
+    fn modify(
+        &self,
+        openapi: &mut utoipa::openapi::OpenApi,
+    ) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+
+        if KeyStore::get().is_none() {
+            return;
+        }
+        openapi.components.get_or_insert_with(Default::default).add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        openapi.security = Some(vec![SecurityRequirement::new("api_key", Vec::<String>::new())]);
+    }
+}
+
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    fmt().with_max_level(tracing::Level::INFO).init();
+async fn main() -> std::process::ExitCode {
+    let cli = <cli::Cli as clap::Parser>::parse();
+    let command = cli.command.unwrap_or(cli::Command::Serve);
+
+    // Only the server logs progress by default; commands keep quiet unless something goes wrong
+    let default_filter = if matches!(command, cli::Command::Serve) {
+        "info"
+    } else {
+        "warn"
+    };
+    let tracer_provider = telemetry::init_tracing(default_filter);
+
+    if let Err(e) = Settings::init(cli.config.as_deref()) {
+        eprintln!("Error: {e}");
+        telemetry::shutdown_tracing(tracer_provider);
+        return std::process::ExitCode::FAILURE;
+    }
+
+    let result: Result<(), cli::CliError> = match command {
+        cli::Command::Serve => serve().await.map_err(Into::into),
+        cli::Command::Ask(args) => cli::ask(args).await,
+        cli::Command::Schema(args) => cli::schema(args).await,
+        cli::Command::Cache {
+            command: cli::CacheCommand::Clear { graph, server, api_key },
+        } => cli::clear_cache(graph, server, api_key).await,
+        cli::Command::Eval(args) => ::text_to_cypher::eval::run(args).await,
+    };
 
+    telemetry::shutdown_tracing(tracer_provider);
+    match result {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+async fn serve() -> std::io::Result<()> {
     // Initialize configuration from .env file
     let config = AppConfig::get();
+    let settings = Settings::get();
+
+    // Refuse to start with templates that cannot be loaded rather than fail every request
+    TemplateEngine::init().map_err(std::io::Error::other)?;
+    tls::init(&settings.tls).map_err(std::io::Error::other)?;
+
+    match KeyStore::get() {
+        None => tracing::warn!("API key authentication is disabled; every endpoint is open"),
+        Some(keys) if keys.is_empty() => tracing::warn!("API key authentication is enabled but no keys are configured"),
+        Some(keys) => tracing::info!("API key authentication is enabled with {} keys", keys.len()),
+    }
 
-    tracing::info!("Starting server at http://localhost:8080/swagger-ui/");
+    tracing::info!(
+        "Starting server at {}://{}:{}/swagger-ui/",
+        if settings.tls.enabled() { "https" } else { "http" },
+        settings.server.host,
+        settings.server.port
+    );
+
+    let shutdown = Shutdown::default();
+    let drain_timeout = Duration::from_secs(settings.server.shutdown_timeout_secs);
+    let on_signal = shutdown.clone();
+    tokio::spawn(async move {
+        match shutdown::signal().await {
+            Ok(()) => on_signal.begin(drain_timeout),
+            Err(e) => tracing::error!("Failed to install shutdown signal handlers: {e}"),
+        }
+    });
 
     // Conditionally start MCP server based on configuration
     let mcp_handle = if config.should_start_mcp_server() {
-        Some(tokio::spawn(async {
-            if let Err(e) = run_mcp_server().await {
-                tracing::error!("MCP server error: {}", e);
-            }
+        health::set_mcp_state(McpState::Running);
+        let shutdown = shutdown.clone();
+        Some(tokio::spawn(async move {
+            let stopped = match run_mcp_server(shutdown.clone(), drain_timeout).await {
+                Ok(()) => "stopped".to_string(),
+                Err(e) => {
+                    tracing::error!("MCP server error: {}", e);
+                    e.to_string()
+                }
+            };
+            health::set_mcp_state(McpState::Stopped(stopped));
+            // Take the HTTP server down with it, as gracefully as on SIGTERM
+            shutdown.begin(drain_timeout);
         }))
     } else {
         None
@@ -764,40 +1719,70 @@ async fn main() -> std::io::Result<()> {
 
     // Start the HTTP server with Swagger UI at /swagger-ui/
     // OpenAPI documentation will be available at /api-doc/openapi.json
-    // Swagger UI will be accessible at:
-    // http://localhost:8080/swagger-ui/
 
-    let http_server = HttpServer::new(|| {
+    let app_shutdown = web::Data::new(shutdown.clone());
+    let http_server = HttpServer::new(move || {
         App::new()
+            .app_data(app_shutdown.clone())
+            .wrap(from_fn(auth::authenticate))
+            // Outermost, so that preflight requests are answered without an API key
+            .wrap(cors::middleware(&Settings::get().cors))
+            .service(healthz)
+            .service(readyz)
+            .service(readyz_details)
             .service(text_to_cypher)
             .service(clear_schema_cache)
             .service(list_graphs_endpoint)
+            .service(list_connections_endpoint)
             .service(get_schema_endpoint)
-            .service(SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-doc/openapi.json", ApiDoc::openapi()))
+            .service(usage_endpoint)
+            .service(limits_endpoint)
+            .service(metrics_endpoint)
+            .service(submit_feedback)
+            .service(list_feedback)
+            .service(promote_feedback)
+            .service(export_examples)
+            .service(websocket::connect)
+            .service(
+                SwaggerUi::new("/swagger-ui/{_:.*}")
+                    .url("/api-doc/openapi.json", ApiDoc::openapi())
+                    .config(utoipa_swagger_ui::Config::default().persist_authorization(true)),
+            )
     })
-    .bind(("0.0.0.0", 8080))?
+    // Signals are handled above so that streams are drained before the workers stop
+    .disable_signals()
+    .shutdown_timeout(SHUTDOWN_GRACE_SECS);
+    let address = (settings.server.host.as_str(), settings.server.port);
+    let http_server = match tls::server_config() {
+        Some(config) => http_server.bind_rustls_0_23(address, config)?,
+        None => http_server.bind(address)?,
+    }
     .run();
 
-    // Run server(s) concurrently
-    if let Some(mcp_handle) = mcp_handle {
-        // Run both HTTP and MCP servers
-        tokio::select! {
-            result = http_server => {
-                tracing::info!("HTTP server stopped");
-                result
-            }
-            _ = mcp_handle => {
-                tracing::info!("MCP server stopped");
-                Ok(())
-            }
-        }
-    } else {
-        // Run only HTTP server
+    // Stopping the server closes the connections of its workers, so only stop accepting new ones
+    // until the in-flight streams have finished or been cut off
+    let http_handle = http_server.handle();
+    let draining = shutdown.clone();
+    tokio::spawn(async move {
+        draining.reached(Phase::Draining).await;
+        http_handle.pause().await;
+        draining.drained().await;
+        http_handle.stop(true).await;
+    });
+
+    if mcp_handle.is_none() {
         tracing::info!("Running HTTP server only");
-        let result = http_server.await;
-        tracing::info!("HTTP server stopped");
-        result
     }
+    let result = http_server.await;
+    tracing::info!("HTTP server stopped");
+
+    // The MCP server shuts down on the same signal; wait for it to finish draining
+    if let Some(mcp_handle) = mcp_handle {
+        shutdown.begin(drain_timeout);
+        let _ = mcp_handle.await;
+        tracing::info!("MCP server stopped");
+    }
+    result
 }
 
 #[derive(Deserialize)]
@@ -808,45 +1793,42 @@ struct GetSchemaQuery {
 async fn discover_graph_schema(
     falkordb_connection: &str,
     graph_name: &str,
-) -> Schema {
-    let connection_info: FalkorConnectionInfo = falkordb_connection.try_into().expect("Invalid connection info");
-
-    let client = FalkorClientBuilder::new_async()
-        .with_connection_info(connection_info)
-        .build()
-        .await
-        .expect("Failed to build client");
-
-    // Select the specified graph
-    let mut graph = client.select_graph(graph_name);
-    let schema = Schema::discover_from_graph(&mut graph, 100)
-        .await
-        .expect("Failed to discover schema from graph");
+) -> Result<Schema, GraphError> {
+    let settings = Settings::get();
+    let backend = graph::connect(falkordb_connection).await?;
+    let schema = Schema::discover_from_graph(
+        backend.as_ref(),
+        graph_name,
+        settings.sample_size_for(graph_name),
+        settings.schema.probe_concurrency,
+    )
+    .await
+    .map_err(|e| format!("Failed to discover schema from graph: {e}"))?;
 
     // Print the discovered schema
     tracing::info!("Discovered schema: {schema}");
-    schema
-}
-
-fn process_last_user_message(question: &str) -> String {
-    TemplateEngine::render_user_prompt(question).unwrap_or_else(|e| {
-        tracing::error!("Failed to load user prompt template: {}", e);
-        format!("Generate an OpenCypher statement for: {question}")
-    })
+    Ok(schema)
 }
 
 #[allow(clippy::cognitive_complexity)]
 async fn discover_and_send_schema(
     falkordb_connection: &str,
     graph_name: &str,
-    tx: &mpsc::Sender<sse::Event>,
+    tx: &mpsc::Sender<Progress>,
 ) -> Result<String, ()> {
     try_send!(
         tx,
         Progress::Status(format!("Discovering schema for graph: {graph_name}"))
     );
 
-    let schema = discover_graph_schema(falkordb_connection, graph_name).await;
+    let schema = match discover_graph_schema(falkordb_connection, graph_name).await {
+        Ok(schema) => schema,
+        Err(e) => {
+            tracing::error!("Failed to discover schema: {}", e);
+            try_send!(tx, Progress::Error(format!("Failed to discover schema: {e}")));
+            return Err(());
+        }
+    };
 
     // Serialize and handle errors inline
     let Ok(json_schema) = serde_json::to_string(&schema) else {
@@ -861,81 +1843,363 @@ async fn discover_and_send_schema(
 
 async fn send_processing_status(
     request: &TextToCypherRequest,
-    service_target: &genai::ServiceTarget,
-    tx: &mpsc::Sender<sse::Event>,
+    backend: &dyn ChatBackend,
+    tx: &mpsc::Sender<Progress>,
 ) {
-    let adapter_kind = service_target.model.adapter_kind;
     let model_name = request.model.as_deref().unwrap_or("unknown");
     send!(
         tx,
         Progress::Status(format!(
-            "Processing query for graph: {} using model: {} ({:?})",
-            request.graph_name, model_name, adapter_kind
+            "Processing query for graph: {} using model: {} ({})",
+            request.graph_name,
+            model_name,
+            backend.provider()
         ))
     );
 }
 
 async fn execute_chat(
-    client: &genai::Client,
+    backend: &dyn ChatBackend,
     model: &str,
     genai_chat_request: genai::chat::ChatRequest,
-    tx: &mpsc::Sender<sse::Event>,
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut TokenUsage,
 ) -> String {
     // Make the actual request to the model
-    let chat_response = match client.exec_chat(model, genai_chat_request, None).await {
+    let chat_response = match backend.exec_chat(model, genai_chat_request).await {
         Ok(response) => response,
         Err(e) => {
+            Metrics::get().record_model_error(model);
             let error_update = Progress::Error(format!("Chat request failed: {e}"));
             send_or_empty!(tx, error_update);
             return String::from("NO ANSWER");
         }
     };
-    chat_response
-        .content_text_into_string()
-        .unwrap_or_else(|| String::from("NO ANSWER"))
+    usage.add(&chat_response.usage);
+    chat_response.content.unwrap_or_else(|| String::from("NO ANSWER"))
 }
 
 async fn execute_chat_stream(
-    client: &genai::Client,
+    backend: &dyn ChatBackend,
     model: &str,
     genai_chat_request: genai::chat::ChatRequest,
-    tx: &mpsc::Sender<sse::Event>,
+    placeholders: &Placeholders,
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut TokenUsage,
 ) -> String {
     // Make the actual request to the model
-    let chat_response = match client.exec_chat_stream(model, genai_chat_request, None).await {
+    let chat_stream = match backend.exec_chat_stream(model, genai_chat_request).await {
         Ok(response) => response,
         Err(e) => {
+            Metrics::get().record_model_error(model);
             let error_update = Progress::Error(format!("Chat request failed: {e}"));
             send_or_empty!(tx, error_update);
             return String::new();
         }
     };
 
-    process_chat_stream(chat_response, tx).await
+    process_chat_stream(chat_stream, model, placeholders, tx, usage).await
 }
 
 #[allow(clippy::cognitive_complexity)]
 async fn process_chat_stream(
-    chat_response: genai::chat::ChatStreamResponse,
-    tx: &mpsc::Sender<sse::Event>,
+    mut stream: ChatEventStream,
+    model: &str,
+    placeholders: &Placeholders,
+    tx: &mpsc::Sender<Progress>,
+    usage: &mut TokenUsage,
 ) -> String {
     let mut answer = String::new();
+    // What the model wrote, with placeholders in place of the redacted values, for the logs
+    let mut model_output = String::new();
+    let mut rehydrator = placeholders.rehydrator();
 
-    // Extract the response stream
-    let mut stream = chat_response.stream;
-    while let Some(Ok(stream_event)) = stream.next().await {
+    while let Some(stream_result) = stream.next().await {
+        let stream_event = match stream_result {
+            Ok(stream_event) => stream_event,
+            Err(e) => {
+                tracing::error!("Chat stream failed: {}", e);
+                Metrics::get().record_model_error(model);
+                break;
+            }
+        };
         match stream_event {
-            genai::chat::ChatStreamEvent::Start => {}
-            genai::chat::ChatStreamEvent::Chunk(chunk) => {
-                answer.push_str(&chunk.content);
-                send_or_empty!(tx, Progress::ModelOutputChunk(chunk.content));
+            ChatStreamEvent::Chunk(chunk) => {
+                model_output.push_str(&chunk);
+                let chunk = rehydrator.push(&chunk);
+                if chunk.is_empty() {
+                    continue;
+                }
+                answer.push_str(&chunk);
+                send_or_empty!(tx, Progress::ModelOutputChunk(chunk));
+            }
+            ChatStreamEvent::End(captured_usage) => {
+                if let Some(captured_usage) = captured_usage {
+                    usage.add(&captured_usage);
+                }
             }
-            genai::chat::ChatStreamEvent::ReasoningChunk(_chunk) => {}
-            genai::chat::ChatStreamEvent::End(_end_event) => {}
         }
     }
 
-    tracing::info!("Final answer: {}", answer);
-    send_or_empty!(tx, Progress::Result(answer.clone()));
+    let rest = rehydrator.finish();
+    if !rest.is_empty() {
+        answer.push_str(&rest);
+        send_or_empty!(tx, Progress::ModelOutputChunk(rest));
+    }
+
+    tracing::info!("Final answer: {} characters", answer.chars().count());
+    tracing::debug!("Final answer before re-hydration: {}", model_output);
+    send_or_empty!(
+        tx,
+        Progress::Result(Answer {
+            text: answer.clone(),
+            model_output,
+        })
+    );
     answer
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use llm::mock::{MockCall, MockRule, MockScript};
+
+    /// Run the pipeline for `question` on a `memory:` fixture of `graph_name`, returning the
+    /// progress events in the order they were sent
+    async fn run_pipeline(
+        graph_name: &str,
+        question: &str,
+        rules: Vec<MockRule>,
+    ) -> Vec<Progress> {
+        let fixture = serde_json::json!({
+            graph_name: {
+                "nodes": [
+                    { "labels": ["Person"], "properties": { "name": "Michael Mann" } },
+                    { "labels": ["Movie"], "properties": { "title": "Heat" } }
+                ],
+                "edges": [{ "type": "DIRECTED", "src": 0, "dst": 1 }],
+                "queries": {
+                    "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name": [["Michael Mann"]]
+                }
+            }
+        });
+        let path = std::env::temp_dir().join(format!("pipeline-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, fixture.to_string()).unwrap();
+
+        let request = TextToCypherRequest {
+            graph_name: graph_name.to_string(),
+            chat_request: ChatRequest {
+                messages: vec![ChatMessage {
+                    role: ChatRole::User,
+                    content: question.to_string(),
+                }],
+            },
+            model: Some("mock:".to_string()),
+            key: None,
+            falkordb_connection: Some(format!("{}{}", graph::MEMORY_PREFIX, path.display())),
+            dialect: None,
+        };
+        let context = RequestContext {
+            request_id: "req-1".to_string(),
+            account: "tests".to_string(),
+            caller: "tests@local".to_string(),
+            subjects: Subjects::default(),
+            access: None,
+        };
+        let script = MockScript {
+            rules,
+            ..MockScript::default()
+        };
+        let backend = Arc::new(llm::MockBackend::new(script).unwrap());
+
+        let (tx, mut rx) = mpsc::channel(100);
+        process_text_to_cypher_request(request, context, backend, tx, std::future::pending()).await;
+        std::fs::remove_file(path).unwrap();
+
+        let mut events = Vec::new();
+        while let Some(progress) = rx.recv().await {
+            events.push(progress);
+        }
+        events
+    }
+
+    fn rule(
+        call: MockCall,
+        response: &str,
+    ) -> MockRule {
+        MockRule {
+            pattern: "(?i)who directed".to_string(),
+            call: Some(call),
+            response: response.to_string(),
+        }
+    }
+
+    /// The names of the events, leaving out status updates and answer chunks
+    fn milestones(events: &[Progress]) -> Vec<&'static str> {
+        events
+            .iter()
+            .filter_map(|progress| match progress {
+                Progress::RequestId(_) => Some("RequestId"),
+                Progress::Schema(_) => Some("Schema"),
+                Progress::CypherQuery(_) => Some("CypherQuery"),
+                Progress::CypherResult(_) => Some("CypherResult"),
+                Progress::Result(_) => Some("Result"),
+                Progress::Warning(_) => Some("Warning"),
+                Progress::Error(_) => Some("Error"),
+                Progress::Usage(_) => Some("Usage"),
+                Progress::Status(_) | Progress::ModelOutputChunk(_) => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_answers_from_the_graph() {
+        let events = run_pipeline(
+            "pipeline_answer",
+            "Who directed Heat?",
+            vec![
+                rule(MockCall::Chat, "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name"),
+                rule(MockCall::Stream, "Michael Mann directed Heat."),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            milestones(&events),
+            vec!["RequestId", "Schema", "CypherQuery", "CypherResult", "Result", "Usage"]
+        );
+        assert!(matches!(&events[0], Progress::RequestId(id) if id == "req-1"));
+        for event in &events {
+            match event {
+                Progress::Schema(schema) => assert!(schema.contains("Person") && schema.contains("DIRECTED")),
+                Progress::CypherQuery(query) => {
+                    assert_eq!(query, "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name");
+                }
+                Progress::CypherResult(result) => assert!(result.contains("Michael Mann")),
+                Progress::Result(answer) => assert_eq!(answer.text, "Michael Mann directed Heat."),
+                Progress::Usage(usage) => {
+                    assert_eq!(usage.model, "mock:");
+                    assert!(usage.total().total_tokens() > 0);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_reports_query_errors() {
+        let events = run_pipeline(
+            "pipeline_error",
+            "Who directed Heat?",
+            vec![rule(MockCall::Chat, "MATCH (m:Movie) RETURN m.title")],
+        )
+        .await;
+
+        assert_eq!(
+            milestones(&events),
+            vec!["RequestId", "Schema", "CypherQuery", "Error", "Usage"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_pipeline_reports_usage() {
+        let request = TextToCypherRequest {
+            graph_name: "cancelled".to_string(),
+            chat_request: ChatRequest {
+                messages: vec![ChatMessage {
+                    role: ChatRole::User,
+                    content: "Who directed Heat?".to_string(),
+                }],
+            },
+            model: Some("mock:".to_string()),
+            key: None,
+            falkordb_connection: None,
+            dialect: None,
+        };
+        let context = RequestContext {
+            request_id: "req-1".to_string(),
+            account: "tests".to_string(),
+            caller: "tests@local".to_string(),
+            subjects: Subjects::default(),
+            access: None,
+        };
+        let backend = Arc::new(llm::MockBackend::new(MockScript::default()).unwrap());
+
+        // With no room for the status update the pipeline waits until it is cancelled
+        let (tx, mut rx) = mpsc::channel(1);
+        let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+        let stop = async move {
+            let _ = cancelled.await;
+            RequestOutcome::Cancelled
+        };
+        let pipeline = tokio::spawn(process_text_to_cypher_request(request, context, backend, tx, stop));
+        assert!(matches!(rx.recv().await, Some(Progress::RequestId(_))));
+        cancel.send(()).unwrap();
+
+        let mut events = Vec::new();
+        while let Some(progress) = rx.recv().await {
+            events.push(progress);
+        }
+        pipeline.await.unwrap();
+        assert!(matches!(events.last(), Some(Progress::Usage(_))));
+        assert!(!events.iter().any(|progress| matches!(progress, Progress::Result(_))));
+    }
+
+    #[test]
+    fn test_schema_cache_is_per_connection() {
+        let cache = &AppConfig::get().schema_cache;
+        cache.insert(schema_cache_key("falkor://a:6379", "cache_movies"), "a".to_string());
+        cache.insert(schema_cache_key("falkor://b:6379", "cache_movies"), "b".to_string());
+        cache.insert(schema_cache_key("falkor://a:6379", "cache_other"), "other".to_string());
+        assert_eq!(
+            cache.get(&schema_cache_key("falkor://b:6379", "cache_movies")).as_deref(),
+            Some("b")
+        );
+
+        process_clear_schema_cache("cache_movies");
+        assert!(cache.get(&schema_cache_key("falkor://a:6379", "cache_movies")).is_none());
+        assert!(cache.get(&schema_cache_key("falkor://b:6379", "cache_movies")).is_none());
+        assert!(cache.get(&schema_cache_key("falkor://a:6379", "cache_other")).is_some());
+    }
+
+    #[test]
+    fn test_feedback_needs_the_graph() {
+        let store = &AppConfig::get().feedback_store;
+        store.remember(
+            "feedback-req",
+            Interaction {
+                graph_name: "payroll".to_string(),
+                question: "Who earns most?".to_string(),
+                schema_fingerprint: String::new(),
+                generated_query: "MATCH (p:Person) RETURN p.name".to_string(),
+            },
+        );
+        let keys = KeyStore::from_settings(&config::AuthSettings {
+            keys_file: None,
+            keys: vec![auth::ApiKeySettings {
+                name: "analytics".to_string(),
+                key: Some("secret".to_string()),
+                key_sha256: None,
+                scopes: vec![auth::Scope::Query],
+                graphs: Some(vec!["movies".to_string()]),
+                connections: Vec::new(),
+                rate_limit: None,
+                daily_tokens: None,
+            }],
+        })
+        .unwrap();
+        let principal = keys.lookup("secret").unwrap();
+
+        let feedback = FeedbackRequest {
+            request_id: "feedback-req".to_string(),
+            rating: feedback::Rating::Up,
+            corrected_query: None,
+            comment: None,
+        };
+        assert!(matches!(
+            process_submit_feedback(&principal, feedback),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(store.list(Some("payroll")).is_empty());
+    }
+}
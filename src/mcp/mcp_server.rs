@@ -2,7 +2,10 @@ use std::time::Duration;
 
 use rust_mcp_sdk::mcp_server::{HyperServerOptions, hyper_server};
 
+use crate::config::Settings;
+use crate::mcp::proxy;
 use crate::mcp::server_handler::MyServerHandler;
+use crate::shutdown::{Phase, Shutdown};
 use rust_mcp_sdk::schema::{
     Implementation, InitializeResult, LATEST_PROTOCOL_VERSION, ServerCapabilities, ServerCapabilitiesResources,
     ServerCapabilitiesTools,
@@ -10,12 +13,15 @@ use rust_mcp_sdk::schema::{
 
 use rust_mcp_sdk::error::SdkResult;
 
-/// Run the MCP server.
+/// Run the MCP server until `shutdown` begins, giving open sessions `drain_timeout` to finish.
 ///
 /// # Errors
 ///
 /// Returns an error if the server fails to start or encounters a runtime error.
-pub async fn run_mcp_server() -> SdkResult<()> {
+pub async fn run_mcp_server(
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) -> SdkResult<()> {
     // Note: Tracing is already initialized in main, no need to initialize it again
 
     // STEP 1: Define server details and capabilities
@@ -65,18 +71,62 @@ Example: First check resources, then ask 'Who are all the people?' for a social
     let handler = MyServerHandler {};
 
     // STEP 3: instantiate HyperServer, providing `server_details` , `handler` and HyperServerOptions
+    let settings = &Settings::get().mcp;
+    let proxied = proxy::needed(Settings::get());
     let server = hyper_server::create_server(
         server_details,
         handler,
         HyperServerOptions {
-            host: "0.0.0.0".to_string(),
-            port: 3001,
+            // Behind the proxy, on any free loopback port
+            host: if proxied {
+                "127.0.0.1".to_string()
+            } else {
+                settings.host.clone()
+            },
+            port: if proxied { 0 } else { settings.port },
             ping_interval: Duration::from_secs(5),
             ..Default::default()
         },
     );
 
-    server.start().await?;
+    let handle = server.server_handle();
+    let shutdown_handle = handle.clone();
+    let draining = shutdown.clone();
+    tokio::spawn(async move {
+        draining.reached(Phase::Draining).await;
+        shutdown_handle.graceful_shutdown(Some(drain_timeout));
+    });
 
-    Ok(())
+    if !proxied {
+        server.start().await?;
+        return Ok(());
+    }
+
+    let mut serving = tokio::spawn(server.start());
+    let upstream = tokio::select! {
+        upstream = handle.listening() => upstream,
+        result = &mut serving => return Ok(result.map_err(std::io::Error::other)??),
+    };
+    let Some(upstream) = upstream else {
+        return Ok(serving.await.map_err(std::io::Error::other)??);
+    };
+    let front = proxy::start(&settings.host, settings.port, upstream)?;
+    let front_handle = front.handle();
+    let front = tokio::spawn(front);
+    tracing::info!(
+        "MCP server is available at {}://{}:{}/sse",
+        if Settings::get().tls.enabled() { "https" } else { "http" },
+        settings.host,
+        settings.port
+    );
+
+    let pause_handle = front_handle.clone();
+    tokio::spawn(async move {
+        shutdown.reached(Phase::Draining).await;
+        pause_handle.pause().await;
+    });
+    let result = serving.await.map_err(std::io::Error::other)?;
+    front_handle.stop(true).await;
+    let _ = front.await;
+    Ok(result?)
 }
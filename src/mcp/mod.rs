@@ -1,4 +1,5 @@
 pub mod mcp_server;
+pub mod proxy;
 pub mod server_handler;
 pub mod tools;
 
@@ -0,0 +1,244 @@
+//! HTTPS, CORS and API keys in front of the MCP server
+//!
+//! The MCP SDK builds and serves its routes itself, exposing neither its router nor its TLS
+//! configuration. So with `[tls]`, `[cors]` or `[auth]` configured, the MCP server listens on the
+//! loopback interface and this proxy serves the MCP port instead: it terminates TLS with the
+//! certificate of the HTTP API, applies the same CORS policy and forwards every request, streaming
+//! the responses so that SSE sessions stay open.
+//!
+//! With authentication enabled every request needs a bearer key with the query scope. The SDK
+//! does not show its handlers the HTTP request, so the proxy hands the key over in the
+//! `initialize` message, as the [`CALLER_CAPABILITY`] experimental client capability, and the
+//! handler calls the HTTP API with it. A session stays bound to the key that initialized it.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use actix_web::dev::Server;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderName};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use futures_util::TryStreamExt;
+use moka::sync::Cache;
+use serde_json::Value;
+
+use crate::audit::sha256_hex;
+use crate::auth::{self, KeyStore, Scope};
+use crate::config::Settings;
+use crate::error::ApiError;
+use crate::{cors, tls};
+
+/// Experimental client capability carrying the caller's API key to the MCP handler, as
+/// `{"key": "..."}`
+pub const CALLER_CAPABILITY: &str = "falkordb/caller";
+
+/// How long an idle session stays bound to its key
+const SESSION_IDLE: Duration = Duration::from_hours(24);
+
+/// Seconds the proxy waits for open connections once the MCP server has stopped
+const SHUTDOWN_GRACE_SECS: u64 = 5;
+
+/// Headers that only apply to one connection and are not forwarded
+const HOP_BY_HOP: &[HeaderName] = &[
+    header::CONNECTION,
+    header::HOST,
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+];
+
+/// Whether the MCP server must be served through the proxy
+#[must_use]
+pub const fn needed(settings: &Settings) -> bool {
+    settings.tls.enabled() || settings.cors.enabled() || settings.auth.enabled()
+}
+
+struct Upstream {
+    base_url: String,
+    client: reqwest::Client,
+    /// Digest of the key each session was initialized with, by session ID
+    sessions: Cache<String, String>,
+}
+
+/// Serve the MCP port, forwarding requests to the MCP server listening at `upstream`
+///
+/// # Errors
+///
+/// Returns an error if the MCP port cannot be bound.
+pub fn start(
+    host: &str,
+    port: u16,
+    upstream: SocketAddr,
+) -> std::io::Result<Server> {
+    let upstream = web::Data::new(Upstream {
+        base_url: format!("http://{upstream}"),
+        client: reqwest::Client::new(),
+        sessions: Cache::builder().time_to_idle(SESSION_IDLE).build(),
+    });
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(cors::middleware(&Settings::get().cors))
+            .app_data(upstream.clone())
+            .default_service(web::to(forward))
+    })
+    // Stopped by the MCP server task, after the MCP server has drained its sessions
+    .disable_signals()
+    .shutdown_timeout(SHUTDOWN_GRACE_SECS);
+    let server = match tls::server_config() {
+        Some(config) => server.bind_rustls_0_23((host, port), config)?,
+        None => server.bind((host, port))?,
+    };
+    Ok(server.run())
+}
+
+#[allow(clippy::future_not_send)] // actix handlers run on a single-threaded worker
+async fn forward(
+    request: HttpRequest,
+    body: web::Bytes,
+    upstream: web::Data<Upstream>,
+) -> HttpResponse {
+    let body = match KeyStore::get() {
+        Some(keys) => match authorize(keys, &request, &upstream.sessions, body) {
+            Ok(body) => body,
+            Err(e) => return actix_web::ResponseError::error_response(&e),
+        },
+        None => body,
+    };
+    let path = request.uri().path_and_query().map_or("/", |path| path.as_str());
+    let Ok(method) = reqwest::Method::from_bytes(request.method().as_str().as_bytes()) else {
+        return HttpResponse::MethodNotAllowed().finish();
+    };
+    let mut outgoing = upstream.client.request(method, format!("{}{path}", upstream.base_url));
+    for (name, value) in request.headers() {
+        if !HOP_BY_HOP.contains(name) {
+            outgoing = outgoing.header(name.as_str(), value.as_bytes());
+        }
+    }
+
+    let response = match outgoing.body(body).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to forward {} {path} to the MCP server: {e}", request.method());
+            return HttpResponse::BadGateway().body("The MCP server is unavailable");
+        }
+    };
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut reply = HttpResponse::build(status);
+    for (name, value) in response.headers() {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_str().as_bytes()),
+            header::HeaderValue::from_bytes(value.as_bytes()),
+        ) && !HOP_BY_HOP.contains(&name)
+        {
+            reply.append_header((name, value));
+        }
+    }
+    reply.streaming(response.bytes_stream().map_err(std::io::Error::other))
+}
+
+/// Check the caller's key and bind the session it posts to, returning the body to forward with
+/// the key added to an `initialize` message
+fn authorize(
+    keys: &KeyStore,
+    request: &HttpRequest,
+    sessions: &Cache<String, String>,
+    body: web::Bytes,
+) -> Result<web::Bytes, ApiError> {
+    let key = auth::bearer_token(request.headers())
+        .ok_or_else(|| ApiError::Unauthorized("Missing bearer API key".to_string()))?;
+    let principal = keys
+        .lookup(key)
+        .ok_or_else(|| ApiError::Unauthorized("Unknown API key".to_string()))?;
+    if !principal.has_scope(Scope::Query) {
+        tracing::warn!(
+            "API key '{}' denied MCP {} {}: missing the query scope",
+            principal.name().unwrap_or_default(),
+            request.method(),
+            request.path()
+        );
+        return Err(ApiError::Forbidden(format!(
+            "API key '{}' lacks the query scope",
+            principal.name().unwrap_or_default()
+        )));
+    }
+
+    let Some(session) = session_id(request) else {
+        return Ok(body);
+    };
+    let digest = sha256_hex(key);
+    if sessions.get_with(session.to_string(), || digest.clone()) != digest {
+        return Err(ApiError::Forbidden(format!(
+            "MCP session {session} belongs to another API key"
+        )));
+    }
+    Ok(with_caller_key(body, key))
+}
+
+/// The `sessionId` query parameter of a message posted to a session
+fn session_id(request: &HttpRequest) -> Option<&str> {
+    request
+        .query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("sessionId="))
+        .filter(|session| !session.is_empty())
+}
+
+/// Add `key` to the client capabilities of an `initialize` message, replacing any the client sent
+fn with_caller_key(
+    body: web::Bytes,
+    key: &str,
+) -> web::Bytes {
+    let Ok(mut message) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    if message.get("method").and_then(Value::as_str) != Some("initialize") {
+        return body;
+    }
+    let Some(params) = message.get_mut("params").and_then(Value::as_object_mut) else {
+        return body;
+    };
+    let capabilities = params
+        .entry("capabilities")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Some(capabilities) = capabilities.as_object_mut() {
+        let experimental = capabilities
+            .entry("experimental")
+            .or_insert_with(|| Value::Object(serde_json::Map::new()));
+        if let Some(experimental) = experimental.as_object_mut() {
+            experimental.insert(CALLER_CAPABILITY.to_string(), serde_json::json!({ "key": key }));
+        }
+    }
+    serde_json::to_vec(&message).map_or(body, web::Bytes::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_caller_key() {
+        let initialize = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-06-18",
+                "clientInfo": { "name": "client", "version": "1.0" },
+                "capabilities": { "experimental": { CALLER_CAPABILITY: { "key": "forged" } } }
+            }
+        });
+        let body = with_caller_key(web::Bytes::from(initialize.to_string()), "secret");
+        let message: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            message["params"]["capabilities"]["experimental"][CALLER_CAPABILITY],
+            serde_json::json!({ "key": "secret" })
+        );
+
+        let call = web::Bytes::from(r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{}}"#);
+        assert_eq!(with_caller_key(call.clone(), "secret"), call);
+    }
+}
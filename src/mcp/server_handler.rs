@@ -1,4 +1,6 @@
 use crate::chat::{ChatMessage, ChatRequest, ChatRole};
+use crate::config::Settings;
+use crate::mcp::proxy::CALLER_CAPABILITY;
 use crate::mcp::tools::TextToCypherTool;
 use async_trait::async_trait;
 use futures_util::StreamExt;
@@ -31,11 +33,11 @@ impl ServerHandler for MyServerHandler {
     async fn handle_list_resources_request(
         &self,
         _request: ListResourcesRequest,
-        _runtime: &dyn McpServer,
+        runtime: &dyn McpServer,
     ) -> std::result::Result<ListResourcesResult, RpcError> {
         tracing::info!("Handling List Resources Request");
 
-        match get_falkordb_graphs().await {
+        match get_falkordb_graphs(caller_key(runtime).as_deref()).await {
             Ok(graphs) => {
                 let resources: Vec<Resource> = graphs
                     .into_iter()
@@ -67,13 +69,13 @@ impl ServerHandler for MyServerHandler {
     async fn handle_read_resource_request(
         &self,
         request: ReadResourceRequest,
-        _runtime: &dyn McpServer,
+        runtime: &dyn McpServer,
     ) -> std::result::Result<ReadResourceResult, RpcError> {
         tracing::info!("Handling Read Resource Request for URI: {}", request.params.uri);
 
         // Parse the URI to extract graph name
         if let Some(graph_name) = request.params.uri.strip_prefix("falkordb://graph/") {
-            match get_graph_schema_via_api(graph_name).await {
+            match get_graph_schema_via_api(graph_name, caller_key(runtime).as_deref()).await {
                 Ok(schema_info) => {
                     let text_content = TextResourceContents {
                         uri: request.params.uri,
@@ -99,7 +101,7 @@ impl ServerHandler for MyServerHandler {
     async fn handle_call_tool_request(
         &self,
         request: CallToolRequest,
-        _runtime: &dyn McpServer,
+        runtime: &dyn McpServer,
     ) -> std::result::Result<CallToolResult, CallToolError> {
         tracing::info!("Handling Call Tool Request");
         if request.tool_name() == TextToCypherTool::tool_name() {
@@ -115,7 +117,7 @@ impl ServerHandler for MyServerHandler {
                     tracing::info!("  question: {}", tool_args.question);
 
                     // Forward the request to the HTTP endpoint
-                    match forward_to_http_endpoint(tool_args).await {
+                    match forward_to_http_endpoint(tool_args, caller_key(runtime).as_deref()).await {
                         Ok(result) => Ok(CallToolResult::text_content(vec![TextContent::from(result)])),
                         Err(e) => {
                             tracing::error!("Failed to forward request to HTTP endpoint: {}", e);
@@ -143,14 +145,39 @@ impl ServerHandler for MyServerHandler {
 }
 
 // Helper function to forward MCP tool request to HTTP endpoint
+#[tracing::instrument(name = "mcp_tool_call", skip_all, fields(graph = %tool_args.graph_name))]
 async fn forward_to_http_endpoint(
-    tool_args: TextToCypherTool
+    tool_args: TextToCypherTool,
+    api_key: Option<&str>,
 ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let http_request = create_http_request_payload(tool_args);
-    let response = send_http_request(&http_request).await?;
+    let response = send_http_request(&http_request, api_key).await?;
     process_sse_response(response).await
 }
 
+// The API key the proxy handed over when the client initialized the session, if any
+fn caller_key(runtime: &dyn McpServer) -> Option<String> {
+    runtime
+        .client_info()?
+        .capabilities
+        .experimental?
+        .get(CALLER_CAPABILITY)?
+        .get("key")?
+        .as_str()
+        .map(str::to_string)
+}
+
+// Authenticate a request to the HTTP API with the caller's API key, if any
+fn with_api_key(
+    request: reqwest::RequestBuilder,
+    api_key: Option<&str>,
+) -> reqwest::RequestBuilder {
+    match api_key {
+        Some(api_key) => request.bearer_auth(api_key),
+        None => request,
+    }
+}
+
 // Create HTTP request payload for the text-to-cypher endpoint
 fn create_http_request_payload(tool_args: TextToCypherTool) -> serde_json::Value {
     let chat_request = ChatRequest {
@@ -170,15 +197,21 @@ fn create_http_request_payload(tool_args: TextToCypherTool) -> serde_json::Value
 
 // Send HTTP request to the text-to-cypher endpoint
 async fn send_http_request(
-    http_request: &serde_json::Value
+    http_request: &serde_json::Value,
+    api_key: Option<&str>,
 ) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post("http://127.0.0.1:8080/text_to_cypher")
-        .header("Content-Type", "application/json")
-        .json(http_request)
-        .send()
-        .await?;
+    let mut headers = reqwest::header::HeaderMap::new();
+    crate::telemetry::inject_current_context(&mut headers);
+
+    let response = with_api_key(
+        reqwest::Client::new().post(format!("{}/text_to_cypher", Settings::get().api_url())),
+        api_key,
+    )
+    .header("Content-Type", "application/json")
+    .headers(headers)
+    .json(http_request)
+    .send()
+    .await?;
 
     if !response.status().is_success() {
         return Err(format!("HTTP request failed with status: {}", response.status()).into());
@@ -217,13 +250,16 @@ fn process_sse_event(
         && let Some(event_type) = progress.as_object().and_then(|obj| obj.keys().next())
     {
         match event_type.as_str() {
+            "RequestId" => handle_request_id_event(&progress, result_buffer),
             "Status" => handle_status_event(&progress, result_buffer),
             "Schema" => handle_schema_event(result_buffer),
             "CypherQuery" => handle_cypher_query_event(&progress, result_buffer),
             "CypherResult" => handle_cypher_result_event(&progress, result_buffer),
             "ModelOutputChunk" => handle_model_output_chunk(&progress, final_result),
             "Result" => handle_result_event(&progress, final_result),
+            "Warning" => handle_warning_event(&progress, result_buffer),
             "Error" => return handle_error_event(&progress),
+            "Usage" => handle_usage_event(&progress),
             _ => tracing::debug!("Unknown event type: {}", event_type),
         }
     }
@@ -231,6 +267,15 @@ fn process_sse_event(
 }
 
 // Handle different types of SSE events
+fn handle_request_id_event(
+    progress: &serde_json::Value,
+    result_buffer: &mut String,
+) {
+    if let Some(request_id) = progress.get("RequestId").and_then(|v| v.as_str()) {
+        writeln!(result_buffer, "Request ID: {request_id}").unwrap();
+    }
+}
+
 fn handle_status_event(
     progress: &serde_json::Value,
     result_buffer: &mut String,
@@ -285,6 +330,22 @@ fn handle_result_event(
     }
 }
 
+fn handle_warning_event(
+    progress: &serde_json::Value,
+    result_buffer: &mut String,
+) {
+    if let Some(warning) = progress.get("Warning").and_then(|v| v.as_str()) {
+        tracing::warn!("Warning from HTTP endpoint: {}", warning);
+        writeln!(result_buffer, "Warning: {warning}").unwrap();
+    }
+}
+
+fn handle_usage_event(progress: &serde_json::Value) {
+    if let Some(usage) = progress.get("Usage") {
+        tracing::info!("Token usage: {}", usage);
+    }
+}
+
 fn handle_error_event(progress: &serde_json::Value) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if let Some(error) = progress.get("Error").and_then(|v| v.as_str()) {
         tracing::error!("Error from HTTP endpoint: {}", error);
@@ -306,14 +367,15 @@ fn build_complete_response(
 }
 
 // Helper function to get list of graphs from FalkorDB via REST API
-async fn get_falkordb_graphs() -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+async fn get_falkordb_graphs(api_key: Option<&str>) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
     // Call the local REST API endpoint
-    let client = reqwest::Client::new();
-    let response = client
-        .get("http://localhost:8080/list_graphs")
-        .send()
-        .await
-        .map_err(|e| format!("Failed to call list_graphs API: {e}"))?;
+    let response = with_api_key(
+        reqwest::Client::new().get(format!("{}/list_graphs", Settings::get().api_url())),
+        api_key,
+    )
+    .send()
+    .await
+    .map_err(|e| format!("Failed to call list_graphs API: {e}"))?;
 
     if response.status().is_success() {
         let graphs: Vec<String> = response.json().await.map_err(|e| format!("Failed to parse response: {e}"))?;
@@ -324,14 +386,18 @@ async fn get_falkordb_graphs() -> Result<Vec<String>, Box<dyn std::error::Error
 }
 
 // Helper function to get schema information for a specific graph via REST API
-async fn get_graph_schema_via_api(graph_name: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+async fn get_graph_schema_via_api(
+    graph_name: &str,
+    api_key: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     // Call the local REST API endpoint
-    let client = reqwest::Client::new();
-    let response = client
-        .get(format!("http://localhost:8080/get_schema/{graph_name}"))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to call get_schema API: {e}"))?;
+    let response = with_api_key(
+        reqwest::Client::new().get(format!("{}/get_schema/{graph_name}", Settings::get().api_url())),
+        api_key,
+    )
+    .send()
+    .await
+    .map_err(|e| format!("Failed to call get_schema API: {e}"))?;
 
     if response.status().is_success() {
         let schema: String = response.json().await.map_err(|e| format!("Failed to parse response: {e}"))?;
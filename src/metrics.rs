@@ -0,0 +1,254 @@
+//! Prometheus Metrics Module
+//!
+//! Holds the process-wide metrics registry and exposes helpers used to instrument the
+//! text-to-cypher pipeline. Metrics are rendered in the Prometheus text format by the
+//! `/metrics` endpoint.
+
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+
+/// Pipeline stages with a latency histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Discovery,
+    Generation,
+    Execution,
+    Answer,
+}
+
+impl Stage {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Discovery => "discovery",
+            Self::Generation => "generation",
+            Self::Execution => "execution",
+            Self::Answer => "answer",
+        }
+    }
+}
+
+/// Final outcome of a text-to-cypher request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestOutcome {
+    Success,
+    InvalidRequest,
+    /// Refused by the prompt-injection defenses
+    Rejected,
+    /// Refused by a rate limit or daily quota
+    RateLimited,
+    SchemaError,
+    GenerationError,
+    ExecutionError,
+    AnswerError,
+    /// Cut off at the shutdown deadline
+    Shutdown,
+    /// Stopped by the client
+    Cancelled,
+}
+
+impl RequestOutcome {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::InvalidRequest => "invalid_request",
+            Self::Rejected => "rejected",
+            Self::RateLimited => "rate_limited",
+            Self::SchemaError => "schema_error",
+            Self::GenerationError => "generation_error",
+            Self::ExecutionError => "execution_error",
+            Self::AnswerError => "answer_error",
+            Self::Shutdown => "shutdown",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    stage_duration: HistogramVec,
+    schema_cache: IntCounterVec,
+    schema_cache_hit_ratio: Gauge,
+    model_errors: IntCounterVec,
+    falkordb_errors: IntCounterVec,
+    in_flight: IntGauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("text_to_cypher".to_string()), None).expect("Metric namespace should be valid");
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Text to Cypher requests by outcome"),
+            &["outcome"],
+        )
+        .expect("Metric should be valid");
+        let stage_duration = HistogramVec::new(
+            HistogramOpts::new("stage_duration_seconds", "Latency of each pipeline stage").buckets(vec![
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+            ]),
+            &["stage"],
+        )
+        .expect("Metric should be valid");
+        let schema_cache = IntCounterVec::new(
+            Opts::new("schema_cache_lookups_total", "Schema cache lookups by result"),
+            &["result"],
+        )
+        .expect("Metric should be valid");
+        let schema_cache_hit_ratio = Gauge::new("schema_cache_hit_ratio", "Ratio of schema cache lookups that hit")
+            .expect("Metric should be valid");
+        let model_errors = IntCounterVec::new(
+            Opts::new("model_errors_total", "AI model call failures by provider"),
+            &["provider"],
+        )
+        .expect("Metric should be valid");
+        let falkordb_errors = IntCounterVec::new(
+            Opts::new("falkordb_errors_total", "FalkorDB failures by operation"),
+            &["operation"],
+        )
+        .expect("Metric should be valid");
+        let in_flight = IntGauge::new(
+            "in_flight_requests",
+            "Text to Cypher requests currently being processed",
+        )
+        .expect("Metric should be valid");
+
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(stage_duration.clone()),
+            Box::new(schema_cache.clone()),
+            Box::new(schema_cache_hit_ratio.clone()),
+            Box::new(model_errors.clone()),
+            Box::new(falkordb_errors.clone()),
+            Box::new(in_flight.clone()),
+        ] {
+            registry.register(collector).expect("Metric should only be registered once");
+        }
+
+        Self {
+            registry,
+            requests,
+            stage_duration,
+            schema_cache,
+            schema_cache_hit_ratio,
+            model_errors,
+            falkordb_errors,
+            in_flight,
+        }
+    }
+
+    pub fn get() -> &'static Self {
+        METRICS.get_or_init(Self::new)
+    }
+
+    pub fn record_request(
+        &self,
+        outcome: RequestOutcome,
+    ) {
+        self.requests.with_label_values(&[outcome.as_str()]).inc();
+    }
+
+    /// Start timing a pipeline stage; the duration is observed when the timer is dropped
+    pub fn stage_timer(
+        &self,
+        stage: Stage,
+    ) -> HistogramTimer {
+        self.stage_duration.with_label_values(&[stage.as_str()]).start_timer()
+    }
+
+    pub fn record_schema_cache(
+        &self,
+        hit: bool,
+    ) {
+        self.schema_cache.with_label_values(&[if hit { "hit" } else { "miss" }]).inc();
+    }
+
+    /// Count a failed model call, attributing it to the provider that serves `model`
+    pub fn record_model_error(
+        &self,
+        model: &str,
+    ) {
+        let provider = if model.starts_with(crate::llm::MOCK_PREFIX) {
+            "mock"
+        } else {
+            genai::adapter::AdapterKind::from_model(model).map_or("unknown", |kind| kind.as_str())
+        };
+        self.model_errors.with_label_values(&[provider]).inc();
+    }
+
+    pub fn record_falkordb_error(
+        &self,
+        operation: &str,
+    ) {
+        self.falkordb_errors.with_label_values(&[operation]).inc();
+    }
+
+    /// Track a request as in flight until the returned guard is dropped
+    #[must_use]
+    pub fn in_flight_guard(&self) -> InFlightGuard {
+        self.in_flight.inc();
+        InFlightGuard {
+            gauge: self.in_flight.clone(),
+        }
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metrics cannot be encoded.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let hits = self.schema_cache.with_label_values(&["hit"]).get();
+        let misses = self.schema_cache.with_label_values(&["miss"]).get();
+        if hits + misses > 0 {
+            #[allow(clippy::cast_precision_loss)]
+            self.schema_cache_hit_ratio.set(hits as f64 / (hits + misses) as f64);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+/// Decrements the in-flight gauge when dropped
+pub struct InFlightGuard {
+    gauge: IntGauge,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_recorded_metrics() {
+        let metrics = Metrics::new();
+        metrics.record_request(RequestOutcome::Success);
+        metrics.record_schema_cache(true);
+        metrics.record_schema_cache(false);
+        metrics.record_falkordb_error("query");
+        {
+            let _guard = metrics.in_flight_guard();
+            assert_eq!(metrics.in_flight.get(), 1);
+        }
+        assert_eq!(metrics.in_flight.get(), 0);
+
+        let rendered = metrics.render().unwrap();
+        assert!(rendered.contains("text_to_cypher_requests_total{outcome=\"success\"} 1"));
+        assert!(rendered.contains("text_to_cypher_schema_cache_hit_ratio 0.5"));
+        assert!(rendered.contains("text_to_cypher_falkordb_errors_total{operation=\"query\"} 1"));
+    }
+}
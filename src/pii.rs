@@ -0,0 +1,462 @@
+//! PII Redaction
+//!
+//! Query results are sent to a third-party model to phrase the answer. The `[[pii.rules]]` of the
+//! configuration file replace sensitive values in those results with placeholders such as
+//! `[EMAIL_1]` before they are formatted: values of properties whose name matches a pattern,
+//! parts of string values matching a pattern (emails, phone numbers), or properties carrying a tag
+//! in `[graphs.<name>.tags]`. The same value always gets the same placeholder within a request.
+//! [`Placeholders`] remembers the originals so the streamed answer and the result shown to the
+//! user can be re-hydrated locally; the model only ever sees the placeholders.
+//!
+//! Scalar columns such as `RETURN p.email AS contact` carry no property name in the results, so
+//! the property and alias of each column are read from the query's last `RETURN` clause.
+
+use crate::config::Settings;
+use crate::formatter::format_falkor_value;
+use crate::graph::{Column, Records};
+use falkordb::{Edge, FalkorValue, Node};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{LazyLock, OnceLock};
+
+/// Longest tail of a chunk held back because it could be the start of a placeholder
+const MAX_PLACEHOLDER_LEN: usize = 48;
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[A-Z][A-Z0-9_]*_\d+\]").expect("Pattern should be valid"));
+static PARTIAL_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[A-Z0-9_]*$").expect("Pattern should be valid"));
+
+static COMPILED_RULES: OnceLock<Vec<CompiledRule>> = OnceLock::new();
+
+/// A redaction rule; exactly one of `property`, `value` and `tag` must be set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionRule {
+    /// Names the placeholders, e.g. `email` gives `[EMAIL_1]`
+    pub name: String,
+    /// Pattern matched against property names and column aliases; the whole value is replaced
+    pub property: Option<String>,
+    /// Pattern matched against string values; only the matching parts are replaced
+    pub value: Option<String>,
+    /// Tag of `[graphs.<name>.tags]`; values of the tagged properties are replaced
+    pub tag: Option<String>,
+}
+
+impl RedactionRule {
+    /// Check the rule, returning what is wrong with it
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the rule is invalid.
+    pub fn check(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("name '{}' must be letters, digits and underscores", self.name));
+        }
+        match (&self.property, &self.value, &self.tag) {
+            (Some(pattern), None, None) | (None, Some(pattern), None) => Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("'{}' has an invalid pattern: {e}", self.name)),
+            (None, None, Some(tag)) if !tag.trim().is_empty() => Ok(()),
+            _ => Err(format!("'{}' needs exactly one of property, value and tag", self.name)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Property(Regex),
+    Value(Regex),
+    Tag(String),
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    /// Placeholder prefix, e.g. `EMAIL`
+    prefix: String,
+    matcher: Matcher,
+}
+
+impl CompiledRule {
+    fn compile(rule: &RedactionRule) -> Option<Self> {
+        let matcher = match (&rule.property, &rule.value, &rule.tag) {
+            (Some(pattern), None, None) => Matcher::Property(Regex::new(pattern).ok()?),
+            (None, Some(pattern), None) => Matcher::Value(Regex::new(pattern).ok()?),
+            (None, None, Some(tag)) => Matcher::Tag(tag.clone()),
+            _ => return None,
+        };
+        Some(Self {
+            prefix: rule.name.to_uppercase(),
+            matcher,
+        })
+    }
+}
+
+/// Originals of the placeholders handed out for one request
+#[derive(Debug, Clone, Default)]
+pub struct Placeholders {
+    by_value: HashMap<(String, String), String>,
+    originals: HashMap<String, String>,
+    counts: HashMap<String, usize>,
+}
+
+impl Placeholders {
+    /// Whether no value was redacted
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+
+    fn placeholder(
+        &mut self,
+        prefix: &str,
+        original: &str,
+    ) -> String {
+        let key = (prefix.to_string(), original.to_string());
+        if let Some(placeholder) = self.by_value.get(&key) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(prefix.to_string()).or_default();
+        *count += 1;
+        let placeholder = format!("[{prefix}_{count}]");
+        self.originals.insert(placeholder.clone(), original.to_string());
+        self.by_value.insert(key, placeholder.clone());
+        placeholder
+    }
+
+    /// Replace the placeholders in the text with the values they stand for
+    #[must_use]
+    pub fn rehydrate(
+        &self,
+        text: &str,
+    ) -> String {
+        if self.is_empty() {
+            return text.to_string();
+        }
+        PLACEHOLDER
+            .replace_all(text, |captures: &regex::Captures| {
+                self.originals
+                    .get(&captures[0])
+                    .cloned()
+                    .unwrap_or_else(|| captures[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Re-hydrate text that arrives in chunks, where a placeholder can be split between chunks
+    #[must_use]
+    pub const fn rehydrator(&self) -> Rehydrator<'_> {
+        Rehydrator {
+            placeholders: self,
+            pending: String::new(),
+        }
+    }
+}
+
+/// Streaming counterpart of [`Placeholders::rehydrate`]
+#[derive(Debug)]
+pub struct Rehydrator<'a> {
+    placeholders: &'a Placeholders,
+    pending: String,
+}
+
+impl Rehydrator<'_> {
+    /// Re-hydrate the chunk, holding back a trailing partial placeholder until the next chunk
+    pub fn push(
+        &mut self,
+        chunk: &str,
+    ) -> String {
+        self.pending.push_str(chunk);
+        let ready = PARTIAL_PLACEHOLDER
+            .find(&self.pending)
+            .filter(|partial| partial.len() < MAX_PLACEHOLDER_LEN && !self.placeholders.is_empty())
+            .map_or(self.pending.len(), |partial| partial.start());
+        let text: String = self.pending.drain(..ready).collect();
+        self.placeholders.rehydrate(&text)
+    }
+
+    /// Re-hydrate whatever was held back
+    #[must_use]
+    pub fn finish(self) -> String {
+        self.placeholders.rehydrate(&self.pending)
+    }
+}
+
+/// Applies the redaction rules to the results of one graph
+#[derive(Debug)]
+pub struct Redactor<'a> {
+    rules: &'a [CompiledRule],
+    tags: Option<&'a BTreeMap<String, Vec<String>>>,
+}
+
+impl Redactor<'static> {
+    /// The redactor for the graph, or `None` if no rules are configured
+    #[must_use]
+    pub fn for_graph(graph_name: &str) -> Option<Self> {
+        let settings = Settings::get();
+        let rules =
+            COMPILED_RULES.get_or_init(|| settings.pii.rules.iter().filter_map(CompiledRule::compile).collect());
+        (!rules.is_empty()).then(|| Redactor {
+            rules,
+            tags: settings.graph(graph_name).map(|graph| &graph.tags),
+        })
+    }
+}
+
+impl Redactor<'_> {
+    /// Replace sensitive values in the records with placeholders
+    pub fn redact(
+        &self,
+        records: &mut Records,
+        columns: &[Column],
+        placeholders: &mut Placeholders,
+    ) {
+        for record in records {
+            for (index, value) in record.iter_mut().enumerate() {
+                let column = columns.get(index).cloned().unwrap_or_default();
+                let names: Vec<&str> = column.property.iter().chain(&column.alias).map(String::as_str).collect();
+                if is_scalar(value)
+                    && let Some(prefix) = self.name_rule(None, &names)
+                {
+                    replace(value, prefix, placeholders);
+                } else {
+                    self.redact_value(value, placeholders);
+                }
+            }
+        }
+    }
+
+    /// The prefix of the first property or tag rule matching any of the names
+    fn name_rule(
+        &self,
+        labels: Option<&[String]>,
+        names: &[&str],
+    ) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| match &rule.matcher {
+                Matcher::Property(pattern) => names.iter().any(|name| pattern.is_match(name)),
+                Matcher::Tag(tag) => names.iter().any(|name| self.has_tag(labels, name, tag)),
+                Matcher::Value(_) => false,
+            })
+            .map(|rule| rule.prefix.as_str())
+    }
+
+    /// Whether the property carries the tag; unknown labels match tags given on any label
+    fn has_tag(
+        &self,
+        labels: Option<&[String]>,
+        property: &str,
+        tag: &str,
+    ) -> bool {
+        self.tags.into_iter().flatten().any(|(key, tags)| {
+            let applies = match key.split_once('.') {
+                Some((label, name)) => {
+                    name == property && labels.is_none_or(|labels| labels.iter().any(|l| l == label))
+                }
+                None => key == property,
+            };
+            applies && tags.iter().any(|t| t == tag)
+        })
+    }
+
+    fn redact_value(
+        &self,
+        value: &mut FalkorValue,
+        placeholders: &mut Placeholders,
+    ) {
+        match value {
+            FalkorValue::String(text) => {
+                for rule in self.rules {
+                    if let Matcher::Value(pattern) = &rule.matcher
+                        && pattern.is_match(text)
+                    {
+                        *text = pattern
+                            .replace_all(text, |captures: &regex::Captures| {
+                                placeholders.placeholder(&rule.prefix, &captures[0])
+                            })
+                            .into_owned();
+                    }
+                }
+            }
+            FalkorValue::Node(node) => self.redact_node(node, placeholders),
+            FalkorValue::Edge(edge) => self.redact_edge(edge, placeholders),
+            FalkorValue::Path(path) => {
+                for node in &mut path.nodes {
+                    self.redact_node(node, placeholders);
+                }
+                for edge in &mut path.relationships {
+                    self.redact_edge(edge, placeholders);
+                }
+            }
+            FalkorValue::Array(values) => {
+                for value in values {
+                    self.redact_value(value, placeholders);
+                }
+            }
+            FalkorValue::Map(map) => self.redact_properties(None, map, placeholders),
+            _ => {}
+        }
+    }
+
+    fn redact_node(
+        &self,
+        node: &mut Node,
+        placeholders: &mut Placeholders,
+    ) {
+        self.redact_properties(Some(&node.labels), &mut node.properties, placeholders);
+    }
+
+    fn redact_edge(
+        &self,
+        edge: &mut Edge,
+        placeholders: &mut Placeholders,
+    ) {
+        let labels = std::slice::from_ref(&edge.relationship_type);
+        self.redact_properties(Some(labels), &mut edge.properties, placeholders);
+    }
+
+    fn redact_properties(
+        &self,
+        labels: Option<&[String]>,
+        properties: &mut HashMap<String, FalkorValue>,
+        placeholders: &mut Placeholders,
+    ) {
+        for (name, value) in properties {
+            match self.name_rule(labels, &[name.as_str()]) {
+                Some(prefix) => replace(value, prefix, placeholders),
+                None => self.redact_value(value, placeholders),
+            }
+        }
+    }
+}
+
+const fn is_scalar(value: &FalkorValue) -> bool {
+    !matches!(
+        value,
+        FalkorValue::Node(_) | FalkorValue::Edge(_) | FalkorValue::Path(_) | FalkorValue::Map(_) | FalkorValue::None
+    )
+}
+
+/// Replace the whole value with its placeholder; nulls are left alone
+fn replace(
+    value: &mut FalkorValue,
+    prefix: &str,
+    placeholders: &mut Placeholders,
+) {
+    let original = match &*value {
+        FalkorValue::None => return,
+        FalkorValue::String(text) => text.clone(),
+        other => format_falkor_value(other),
+    };
+    *value = FalkorValue::String(placeholders.placeholder(prefix, &original));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::return_columns;
+
+    fn rule(
+        name: &str,
+        property: Option<&str>,
+        value: Option<&str>,
+        tag: Option<&str>,
+    ) -> CompiledRule {
+        CompiledRule::compile(&RedactionRule {
+            name: name.to_string(),
+            property: property.map(ToString::to_string),
+            value: value.map(ToString::to_string),
+            tag: tag.map(ToString::to_string),
+        })
+        .unwrap()
+    }
+
+    fn rules() -> Vec<CompiledRule> {
+        vec![
+            rule("ssn", Some("(?i)^ssn$"), None, None),
+            rule("email", None, Some(r"[\w.+-]+@[\w-]+\.[\w.]+"), None),
+            rule("contact", None, None, Some("pii")),
+        ]
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let mut rule = RedactionRule {
+            name: "email".to_string(),
+            value: Some("@".to_string()),
+            ..RedactionRule::default()
+        };
+        assert_eq!(rule.check(), Ok(()));
+        rule.tag = Some("pii".to_string());
+        assert!(rule.check().is_err());
+        rule.tag = None;
+        rule.value = Some("(".to_string());
+        assert!(rule.check().is_err());
+        rule.value = Some("@".to_string());
+        rule.name = "e-mail".to_string();
+        assert!(rule.check().is_err());
+    }
+
+    #[test]
+    fn test_redact_and_rehydrate() {
+        let rules = rules();
+        let tags = BTreeMap::from([("Person.phone".to_string(), vec!["pii".to_string()])]);
+        let redactor = Redactor {
+            rules: &rules,
+            tags: Some(&tags),
+        };
+        let person = Node {
+            entity_id: 1,
+            labels: vec!["Person".to_string()],
+            properties: HashMap::from([
+                ("name".to_string(), FalkorValue::String("Ann".to_string())),
+                ("phone".to_string(), FalkorValue::String("555-0100".to_string())),
+                (
+                    "bio".to_string(),
+                    FalkorValue::String("Mail ann@example.com".to_string()),
+                ),
+            ]),
+        };
+        let mut records = vec![vec![
+            FalkorValue::I64(123_456_789),
+            FalkorValue::String("ann@example.com".to_string()),
+            FalkorValue::Node(person),
+        ]];
+        let columns = return_columns("MATCH (p:Person) RETURN p.ssn, p.email, p");
+        let mut placeholders = Placeholders::default();
+        redactor.redact(&mut records, &columns, &mut placeholders);
+
+        assert_eq!(records[0][0], FalkorValue::String("[SSN_1]".to_string()));
+        assert_eq!(records[0][1], FalkorValue::String("[EMAIL_1]".to_string()));
+        let FalkorValue::Node(node) = &records[0][2] else {
+            panic!("Expected a node");
+        };
+        assert_eq!(node.properties["name"], FalkorValue::String("Ann".to_string()));
+        assert_eq!(node.properties["phone"], FalkorValue::String("[CONTACT_1]".to_string()));
+        assert_eq!(
+            node.properties["bio"],
+            FalkorValue::String("Mail [EMAIL_1]".to_string())
+        );
+
+        assert_eq!(
+            placeholders.rehydrate("Ann ([SSN_1], [CONTACT_1]) uses [EMAIL_1], not [EMAIL_2]"),
+            "Ann (123456789, 555-0100) uses ann@example.com, not [EMAIL_2]"
+        );
+    }
+
+    #[test]
+    fn test_rehydrator_handles_split_placeholders() {
+        let mut placeholders = Placeholders::default();
+        placeholders.placeholder("EMAIL", "ann@example.com");
+
+        let mut rehydrator = placeholders.rehydrator();
+        let mut text = String::new();
+        for chunk in ["Write to [EM", "AIL_", "1] or [", "x]", " [EMAIL"] {
+            text.push_str(&rehydrator.push(chunk));
+        }
+        text.push_str(&rehydrator.finish());
+        assert_eq!(text, "Write to ann@example.com or [x] [EMAIL");
+    }
+}
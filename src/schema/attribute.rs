@@ -20,11 +20,11 @@ pub struct Attribute {
     pub name: String,
     #[serde(rename = "type")]
     pub r#type: AttributeType,
-    #[serde(skip_serializing)]
+    #[serde(skip_serializing, default)]
     pub count: i64,
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
     pub unique: bool,
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
     pub required: bool,
 }
 
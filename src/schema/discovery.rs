@@ -1,10 +1,12 @@
+use std::fmt::Write;
 use std::time::Instant;
 
-use falkordb::{AsyncGraph, FalkorDBError, FalkorValue};
+use falkordb::FalkorValue;
 use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::graph::{ElementKind, GraphBackend, GraphError};
 use crate::schema::{
     attribute::{Attribute, AttributeType},
     entity::Entity,
@@ -53,63 +55,89 @@ impl Schema {
         self.relations.push(relation);
     }
 
+    /// Indented, human-readable listing of entities and relations with their attributes
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("Entities:\n");
+        for entity in &self.entities {
+            let _ = writeln!(text, "  {}", entity.label);
+            for attribute in &entity.attributes {
+                let _ = writeln!(text, "    {}: {}", attribute.name, attribute.r#type);
+            }
+        }
+
+        text.push_str("Relations:\n");
+        for relation in &self.relations {
+            let _ = writeln!(
+                text,
+                "  (:{})-[:{}]->(:{})",
+                relation.source, relation.label, relation.target
+            );
+            for attribute in &relation.attributes {
+                let _ = writeln!(text, "    {}: {}", attribute.name, attribute.r#type);
+            }
+        }
+        text
+    }
+
+    /// Mermaid class diagram with one class per entity and one association per relation
+    #[must_use]
+    pub fn to_mermaid(&self) -> String {
+        // Class names may only contain letters, digits and underscores
+        let name =
+            |label: &str| -> String { label.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect() };
+
+        let mut diagram = String::from("classDiagram\n");
+        for entity in &self.entities {
+            let _ = writeln!(diagram, "    class {} {{", name(&entity.label));
+            for attribute in &entity.attributes {
+                let _ = writeln!(diagram, "        {} {}", attribute.r#type, attribute.name);
+            }
+            diagram.push_str("    }\n");
+        }
+        for relation in &self.relations {
+            let _ = writeln!(
+                diagram,
+                "    {} --> {} : {}",
+                name(&relation.source),
+                name(&relation.target),
+                relation.label
+            );
+        }
+        diagram
+    }
+
+    #[tracing::instrument(skip(backend))]
     async fn collect_entity_attributes(
-        graph: &mut AsyncGraph,
+        backend: &dyn GraphBackend,
+        graph_name: &str,
         label: &str,
         sample_size: usize,
-    ) -> Result<Vec<Attribute>, FalkorDBError> {
-        let query = format!(
-            r"
-            MATCH (a:{label})
-            CALL {{
-                WITH a
-                RETURN [k IN keys(a) | [k, typeof(a[k])]] AS types
-            }}
-            WITH types
-            LIMIT {sample_size}
-            UNWIND types AS kt
-            RETURN kt, count(1)
-            ORDER BY kt[0]
-            "
-        );
-
-        Self::collect_attributes(graph, label, &query).await
+    ) -> Result<Vec<Attribute>, GraphError> {
+        Self::collect_attributes(backend, graph_name, ElementKind::Node, label, sample_size).await
     }
 
+    #[tracing::instrument(skip(backend))]
     async fn collect_relationship_attributes(
-        graph: &mut AsyncGraph,
+        backend: &dyn GraphBackend,
+        graph_name: &str,
         label: &str,
         sample_size: usize,
-    ) -> Result<Vec<Attribute>, FalkorDBError> {
-        let query = format!(
-            r"
-            MATCH ()-[a:{label}]->()
-            CALL {{
-                WITH a
-                RETURN [k IN keys(a) | [k, typeof(a[k])]] AS types
-            }}
-            WITH types
-            LIMIT {sample_size}
-            UNWIND types AS kt
-            RETURN kt, count(1)
-            ORDER BY kt[0]
-            "
-        );
-
-        Self::collect_attributes(graph, label, &query).await
+    ) -> Result<Vec<Attribute>, GraphError> {
+        Self::collect_attributes(backend, graph_name, ElementKind::Relationship, label, sample_size).await
     }
 
     async fn collect_attributes(
-        graph: &mut AsyncGraph,
+        backend: &dyn GraphBackend,
+        graph_name: &str,
+        kind: ElementKind,
         label: &str,
-        query: &str,
-    ) -> Result<Vec<Attribute>, FalkorDBError> {
-        tracing::info!("Collecting attributes for label '{}': {}", label, query);
-
-        let entity_attributes = graph.ro_query(query).execute().await?;
+        sample_size: usize,
+    ) -> Result<Vec<Attribute>, GraphError> {
+        let entity_attributes = backend.property_types(graph_name, kind, label, sample_size).await?;
         let mut attributes = Vec::new();
 
-        for record in entity_attributes.data {
+        for record in entity_attributes {
             // Extract both kt (key-type info) and count from the record
             if let (Some(FalkorValue::Array(kt_array)), Some(FalkorValue::I64(count))) = (record.first(), record.get(1))
             {
@@ -134,105 +162,63 @@ impl Schema {
         Ok(attributes)
     }
 
-    async fn get_entity_labels(graph: &mut AsyncGraph) -> Result<Vec<String>, FalkorDBError> {
-        // Get node labels (entity types)
-        let labels_result = graph.ro_query("CALL db.labels()").execute().await?;
-
-        // Collect labels first to avoid borrowing issues
-        let mut entity_labels = Vec::new();
-        for record in labels_result.data {
-            if let Some(FalkorValue::String(label)) = record.first() {
-                entity_labels.push(label.clone());
-            }
-        }
-
-        Ok(entity_labels)
-    }
-
-    async fn get_relationship_labels(graph: &mut AsyncGraph) -> Result<Vec<String>, FalkorDBError> {
-        let relations_result = graph.ro_query("CALL db.relationshipTypes()").execute().await?;
-
-        let mut relationship_labels = Vec::new();
-        for record in relations_result.data {
-            if let Some(FalkorValue::String(relation_label)) = record.first() {
-                relationship_labels.push(relation_label.clone());
-            }
-        }
-
-        Ok(relationship_labels)
-    }
-
     async fn get_relationship_attributes(
-        graph: &AsyncGraph,
+        backend: &dyn GraphBackend,
+        graph_name: &str,
         relationship_labels: &[String],
         sample_size: usize,
-    ) -> Result<Vec<(String, Vec<Attribute>)>, FalkorDBError> {
+    ) -> Vec<(String, Vec<Attribute>)> {
         // Use common parallel collection pattern
-        let relationship_attributes = Self::collect_attributes_parallel(
-            graph,
-            relationship_labels.to_vec(),
-            sample_size,
-            |mut graph, relationship_label, sample_size| async move {
-                Self::collect_relationship_attributes(&mut graph, &relationship_label, sample_size)
-                    .await
-                    .map(|attributes| (relationship_label, attributes))
-                    .ok()
-            },
-        )
-        .await;
-
-        Ok(relationship_attributes)
+        Self::collect_attributes_parallel(relationship_labels.to_vec(), |relationship_label| async move {
+            Self::collect_relationship_attributes(backend, graph_name, &relationship_label, sample_size)
+                .await
+                .map(|attributes| (relationship_label, attributes))
+                .ok()
+        })
+        .await
     }
 
     /// Collect attributes for either entities or relationships in parallel
     async fn collect_attributes_parallel<T, F, Fut>(
-        graph: &AsyncGraph,
         labels: Vec<String>,
-        sample_size: usize,
         collector: F,
     ) -> Vec<T>
     where
-        F: Fn(AsyncGraph, String, usize) -> Fut + Send + Sync + Clone + 'static,
-        Fut: std::future::Future<Output = Option<T>> + Send + 'static,
-        T: Send + 'static,
+        F: Fn(String) -> Fut,
+        Fut: std::future::Future<Output = Option<T>>,
     {
         stream::iter(labels)
-            .map(move |label| {
-                let graph = graph.clone();
-                let collector = collector.clone();
-                async move { collector(graph, label, sample_size).await }
-            })
+            .map(collector)
             .buffer_unordered(usize::MAX)
             .filter_map(|result| async move { result })
             .collect()
             .await
     }
 
-    /// Discover the schema from a graph database.
+    /// Discover the schema from a graph database, sampling `sample_size` elements per label and
+    /// running up to `probe_concurrency` relationship queries at once.
     ///
     /// # Errors
     ///
     /// Returns an error if the graph operations fail.
+    #[tracing::instrument(skip(backend))]
     pub async fn discover_from_graph(
-        graph: &mut AsyncGraph,
+        backend: &dyn GraphBackend,
+        graph_name: &str,
         sample_size: usize,
-    ) -> Result<Self, FalkorDBError> {
+        probe_concurrency: usize,
+    ) -> Result<Self, GraphError> {
         let mut schema: Self = Self::empty();
 
-        let entity_labels = Self::get_entity_labels(graph).await?;
+        let entity_labels = backend.labels(graph_name).await?;
 
         // Parallel entity collection using common pattern
-        let entities = Self::collect_attributes_parallel(
-            graph,
-            entity_labels,
-            sample_size,
-            |mut graph, label, sample_size| async move {
-                Self::collect_entity_attributes(&mut graph, &label, sample_size)
-                    .await
-                    .map(|attributes| Entity::new(label, attributes, None))
-                    .ok()
-            },
-        )
+        let entities = Self::collect_attributes_parallel(entity_labels, |label| async move {
+            Self::collect_entity_attributes(backend, graph_name, &label, sample_size)
+                .await
+                .map(|attributes| Entity::new(label, attributes, None))
+                .ok()
+        })
         .await;
 
         for entity in entities {
@@ -240,15 +226,23 @@ impl Schema {
         }
 
         // Get relationship types
-        let relationship_labels = Self::get_relationship_labels(graph).await?;
+        let relationship_labels = backend.relationship_types(graph_name).await?;
 
         let relationship_attributes =
-            Self::get_relationship_attributes(graph, &relationship_labels, sample_size).await?;
+            Self::get_relationship_attributes(backend, graph_name, &relationship_labels, sample_size).await;
 
         let entities = schema.entities.clone();
 
         let start = Instant::now();
-        let queries = process_relationships(graph, &mut schema, relationship_attributes, entities).await?;
+        let queries = process_relationships(
+            backend,
+            graph_name,
+            &mut schema,
+            relationship_attributes,
+            entities,
+            probe_concurrency,
+        )
+        .await?;
         let duration = start.elapsed();
         tracing::info!("Processed relationships ({} queries)  in {:?}", queries, duration);
 
@@ -256,12 +250,15 @@ impl Schema {
     }
 }
 
+#[tracing::instrument(skip_all, fields(relationship_types = relationship_attributes.len()))]
 async fn process_relationships(
-    graph: &AsyncGraph,
+    backend: &dyn GraphBackend,
+    graph_name: &str,
     schema: &mut Schema,
     relationship_attributes: Vec<(String, Vec<Attribute>)>,
     entities: Vec<Entity>,
-) -> Result<usize, FalkorDBError> {
+    concurrency: usize,
+) -> Result<usize, GraphError> {
     // Create all combinations first to avoid borrowing issues
     let mut queries = Vec::new();
     for (label, attributes) in &relationship_attributes {
@@ -279,23 +276,20 @@ async fn process_relationships(
     let ret = queries.len();
     // Convert to stream and process with limited concurrency
     let relations: Vec<Relation> = stream::iter(queries)
-        .map(|(label, source_label, target_label, attributes)| {
-            let mut graph = graph.clone();
-            async move {
-                let query = format!("MATCH (s:{source_label})-[a:{label}]->(t:{target_label}) return a limit 1");
-                match graph.ro_query(&query).execute().await {
-                    Ok(query_result) if !query_result.data.is_empty() => {
-                        Some(Relation::new(label, source_label, target_label, attributes))
-                    }
-                    Ok(_) => None,
-                    Err(e) => {
-                        tracing::warn!("Query failed but ignored: {:?}", e);
-                        None
-                    }
+        .map(|(label, source_label, target_label, attributes)| async move {
+            let query = format!("MATCH (s:{source_label})-[a:{label}]->(t:{target_label}) return a limit 1");
+            match backend.ro_query(graph_name, &query).await {
+                Ok(records) if !records.is_empty() => {
+                    Some(Relation::new(label, source_label, target_label, attributes))
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    tracing::warn!("Query failed but ignored: {:?}", e);
+                    None
                 }
             }
         })
-        .buffer_unordered(1000)
+        .buffer_unordered(concurrency)
         .filter_map(|result| async move { result })
         .collect()
         .await;
@@ -307,3 +301,84 @@ async fn process_relationships(
 
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::MemoryGraphBackend;
+
+    fn movies_schema() -> Schema {
+        let mut schema = Schema::empty();
+        schema.add_entity(Entity::new(
+            "Person".to_string(),
+            vec![Attribute::new("name".to_string(), AttributeType::String, 1, false, false)],
+            None,
+        ));
+        schema.add_entity(Entity::new("Movie Title".to_string(), Vec::new(), None));
+        schema.add_relation(Relation::new(
+            "DIRECTED".to_string(),
+            "Person".to_string(),
+            "Movie Title".to_string(),
+            vec![Attribute::new("year".to_string(), AttributeType::Integer, 1, false, false)],
+        ));
+        schema
+    }
+
+    #[test]
+    fn test_schema_to_text() {
+        assert_eq!(
+            movies_schema().to_text(),
+            "Entities:\n  Person\n    name: String\n  Movie Title\nRelations:\n  \
+             (:Person)-[:DIRECTED]->(:Movie Title)\n    year: Integer\n"
+        );
+    }
+
+    #[test]
+    fn test_schema_to_mermaid() {
+        assert_eq!(
+            movies_schema().to_mermaid(),
+            "classDiagram\n    class Person {\n        String name\n    }\n    class Movie_Title {\n    }\n    \
+             Person --> Movie_Title : DIRECTED\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_memory_graph() {
+        let graph = serde_json::from_value(serde_json::json!({
+            "nodes": [
+                { "labels": ["Person"], "properties": { "name": "Michael Mann", "born": 1943 } },
+                { "labels": ["Movie"], "properties": { "title": "Heat" } }
+            ],
+            "edges": [{ "type": "DIRECTED", "src": 0, "dst": 1, "properties": { "year": 1995 } }]
+        }))
+        .unwrap();
+        let backend = MemoryGraphBackend::new().with_graph("movies", graph);
+
+        let mut schema = Schema::discover_from_graph(&backend, "movies", 100, 10).await.unwrap();
+        schema.entities.sort_by(|a, b| a.label.cmp(&b.label));
+
+        assert_eq!(schema.entities.len(), 2);
+        let person = &schema.entities[1];
+        assert_eq!(person.label, "Person");
+        let attributes: Vec<String> = person.attributes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            attributes,
+            vec![
+                "born: Integer (count: 1, unique: false, required: false)",
+                "name: String (count: 1, unique: false, required: false)"
+            ]
+        );
+
+        assert_eq!(schema.relations.len(), 1);
+        let relation = &schema.relations[0];
+        assert_eq!(
+            (
+                relation.label.as_str(),
+                relation.source.as_str(),
+                relation.target.as_str()
+            ),
+            ("DIRECTED", "Person", "Movie")
+        );
+        assert_eq!(relation.attributes.len(), 1);
+    }
+}
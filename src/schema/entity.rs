@@ -6,7 +6,7 @@ pub use super::attribute::Attribute;
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Entity {
     pub label: String,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub attributes: Vec<Attribute>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
@@ -0,0 +1,169 @@
+//! Graceful shutdown
+//!
+//! On SIGTERM or Ctrl+C the servers stop accepting connections and shutdown enters the draining
+//! phase: new `/text_to_cypher` requests are refused and `/readyz` fails, while in-flight streams
+//! get `server.shutdown_timeout_secs` to finish. Once that deadline expires, any stream still
+//! running is sent a final error event and closed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// Phase of the process lifecycle, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Running,
+    /// No new work is accepted; in-flight requests may finish
+    Draining,
+    /// The drain deadline passed; in-flight requests are cut off
+    Expired,
+}
+
+/// Message of the final event sent to streams cut off at the deadline
+pub const SHUTDOWN_MESSAGE: &str = "The server is shutting down; the request was not completed";
+
+/// Shutdown state of a server, shared by its handlers; clones share the same state
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    phase: watch::Sender<Phase>,
+    /// Number of in-flight streams
+    in_flight: watch::Sender<usize>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                phase: watch::Sender::new(Phase::Running),
+                in_flight: watch::Sender::new(0),
+            }),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Start draining, expiring the in-flight requests after `deadline`. Later calls have no
+    /// effect.
+    pub fn begin(
+        &self,
+        deadline: Duration,
+    ) {
+        let started = self.inner.phase.send_if_modified(|phase| {
+            if *phase == Phase::Running {
+                *phase = Phase::Draining;
+                true
+            } else {
+                false
+            }
+        });
+        if started {
+            tracing::info!(
+                "Shutting down; in-flight requests have {}s to finish",
+                deadline.as_secs()
+            );
+            let inner = Arc::clone(&self.inner);
+            tokio::spawn(async move {
+                tokio::time::sleep(deadline).await;
+                inner.phase.send_replace(Phase::Expired);
+            });
+        }
+    }
+
+    /// Whether shutdown has begun
+    #[must_use]
+    pub fn is_draining(&self) -> bool {
+        *self.inner.phase.borrow() >= Phase::Draining
+    }
+
+    /// Wait until the server reaches `phase`
+    pub async fn reached(
+        &self,
+        phase: Phase,
+    ) {
+        let mut receiver = self.inner.phase.subscribe();
+        // `self` holds the sender, so the channel stays open while this waits
+        let _ = receiver.wait_for(|current| *current >= phase).await;
+    }
+
+    /// Count a stream as in flight until the guard is dropped
+    #[must_use]
+    pub fn track(&self) -> InFlightGuard {
+        self.inner.in_flight.send_modify(|count| *count += 1);
+        InFlightGuard {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Wait until no stream is in flight
+    pub async fn drained(&self) {
+        let mut receiver = self.inner.in_flight.subscribe();
+        let _ = receiver.wait_for(|count| *count == 0).await;
+    }
+}
+
+/// Keeps a stream counted as in flight; see [`Shutdown::track`]
+pub struct InFlightGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.inner.in_flight.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Wait for SIGTERM or Ctrl+C
+///
+/// # Errors
+///
+/// Returns an error if the signal handlers cannot be installed.
+pub async fn signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drained() {
+        let shutdown = Shutdown::default();
+        let guard = shutdown.track();
+        let drained = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drained().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!drained.is_finished());
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(5), drained).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_phases() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.is_draining());
+        shutdown.begin(Duration::from_millis(10));
+        assert!(shutdown.is_draining());
+        shutdown.begin(Duration::from_secs(3600));
+        assert!(!Shutdown::default().is_draining());
+        tokio::time::timeout(Duration::from_secs(5), shutdown.reached(Phase::Expired))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Tracing and OpenTelemetry Module
+//!
+//! Sets up the global tracing subscriber, logging to stderr so command output on stdout stays
+//! clean. Log verbosity follows `RUST_LOG` (default `info` for the server).
+//! When `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also exported via OTLP/HTTP and
+//! incoming W3C `traceparent` headers are honored so requests can be correlated across services.
+
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, fmt};
+
+const DEFAULT_SERVICE_NAME: &str = "text-to-cypher";
+
+/// Initialize the global tracing subscriber, with `default_filter` applying when `RUST_LOG` is unset.
+///
+/// Returns the tracer provider when OTLP export is enabled so it can be flushed on shutdown.
+pub fn init_tracing(default_filter: &str) -> Option<SdkTracerProvider> {
+    // Telemetry settings may come from the .env file, which is otherwise loaded later
+    dotenvy::dotenv().ok();
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr));
+
+    let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") else {
+        registry.init();
+        return None;
+    };
+
+    match build_tracer_provider(&endpoint) {
+        Ok(provider) => {
+            let tracer = provider.tracer(DEFAULT_SERVICE_NAME);
+            registry.with(tracing_opentelemetry::layer().with_tracer(tracer)).init();
+            opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+            tracing::info!("Exporting traces via OTLP to {}", endpoint);
+            Some(provider)
+        }
+        Err(e) => {
+            registry.init();
+            tracing::error!("Failed to initialize OTLP exporter for {}: {}", endpoint, e);
+            None
+        }
+    }
+}
+
+fn build_tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    let resource = Resource::builder().with_service_name(service_name).build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Flush pending spans and stop the exporter
+pub fn shutdown_tracing(provider: Option<SdkTracerProvider>) {
+    if let Some(provider) = provider
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::warn!("Failed to shut down tracer provider: {}", e);
+    }
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(
+        &self,
+        key: &str,
+    ) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(actix_web::http::header::HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(
+        &mut self,
+        key: &str,
+        value: String,
+    ) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Continue the trace described by the incoming `traceparent` header, if any
+pub fn set_parent_from_headers(
+    span: &tracing::Span,
+    headers: &actix_web::http::header::HeaderMap,
+) {
+    let parent =
+        opentelemetry::global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    if let Err(e) = span.set_parent(parent) {
+        tracing::debug!("Failed to set parent trace context: {}", e);
+    }
+}
+
+/// Add the `traceparent` header of the current span to an outgoing request
+pub fn inject_current_context(headers: &mut reqwest::header::HeaderMap) {
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers));
+    });
+}
@@ -0,0 +1,415 @@
+//! Prompt Templates
+//!
+//! Templates are read from the templates directory (`TEMPLATES_DIR`, or `templates.dir` in the
+//! configuration file; `templates` by default). A template can be overridden for one graph by
+//! `graphs/<graph>/<name>` and for one model family (the provider, e.g. `openai` or
+//! `anthropic`) by `models/<family>/<name>`, the graph override taking precedence.
+//!
+//! Templates are parsed once and cached in memory (see [`engine`] for the template language). The
+//! directory is watched, and any change under it empties the cache so edits apply to the next
+//! request without a restart.
+
+pub mod engine;
+
+pub use engine::{Context, Template, Value};
+
+use crate::config::{Settings, TemplateSettings};
+use crate::dialect::Dialect;
+use crate::feedback::Example;
+use dashmap::DashMap;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use strum::IntoEnumIterator;
+
+/// Appended to the answer prompt when the query result was summarized to fit the token budget
+const TRUNCATION_NOTE: &str = "Note: the cypher result was too large and has been truncated to a summary \
+(total row count, per-column aggregates and only the first rows). Base your answer on this summary and \
+say that the answer is based on partial data when the omitted rows could matter.";
+
+const SYSTEM_PROMPT: &str = "system_prompt.txt";
+const USER_PROMPT: &str = "user_prompt.txt";
+const LAST_REQUEST_PROMPT: &str = "last_request_prompt.txt";
+
+/// Variables available to each template, checked when the templates are loaded at startup
+const SYSTEM_PROMPT_VARIABLES: &[&str] = &["ONTOLOGY", "DIALECT", "EXAMPLES", "DELIMITED"];
+const DIALECT_VARIABLES: &[&str] = &["ONTOLOGY", "EXAMPLES"];
+const USER_PROMPT_VARIABLES: &[&str] = &["QUESTION"];
+const LAST_REQUEST_PROMPT_VARIABLES: &[&str] = &[
+    "CYPHER_QUERY",
+    "CYPHER_RESULT",
+    "USER_QUESTION",
+    "TRUNCATED",
+    "TRUNCATION_NOTE",
+    "DELIMITED",
+    "REDACTED",
+];
+
+static STORE: OnceLock<TemplateStore> = OnceLock::new();
+
+/// What a prompt is rendered for, selecting the template overrides that apply
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateScope<'a> {
+    pub graph: &'a str,
+    /// Provider of the model, e.g. `openai`
+    pub model_family: &'a str,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    NotFound {
+        name: String,
+        searched: Vec<PathBuf>,
+    },
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Syntax {
+        template: String,
+        line: usize,
+        message: String,
+    },
+    Render {
+        template: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::NotFound { name, searched } => {
+                let searched: Vec<String> = searched.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "Template {name} not found (searched {})", searched.join(", "))
+            }
+            Self::Read { path, source } => write!(f, "Failed to read template {}: {source}", path.display()),
+            Self::Syntax {
+                template,
+                line,
+                message,
+            } => {
+                write!(f, "Syntax error in template {template} at line {line}: {message}")
+            }
+            Self::Render {
+                template,
+                line,
+                message,
+            } => {
+                write!(f, "Failed to render template {template} at line {line}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Parsed templates keyed by path; `None` records a missing override
+type TemplateCache = DashMap<PathBuf, Option<Arc<Template>>>;
+
+/// Cached templates of one directory
+struct TemplateStore {
+    dir: PathBuf,
+    cache: Arc<TemplateCache>,
+    _watcher: Option<Mutex<RecommendedWatcher>>,
+}
+
+impl TemplateStore {
+    fn open(settings: &TemplateSettings) -> Self {
+        let cache = Arc::new(DashMap::new());
+        // A missing directory is reported by `TemplateEngine::init`
+        let watcher = if settings.watch && settings.dir.is_dir() {
+            match Self::watch(&settings.dir, Arc::clone(&cache)) {
+                Ok(watcher) => Some(Mutex::new(watcher)),
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to watch templates in {}, changes need a restart: {e}",
+                        settings.dir.display()
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Self {
+            dir: settings.dir.clone(),
+            cache,
+            _watcher: watcher,
+        }
+    }
+
+    fn watch(
+        dir: &Path,
+        cache: Arc<TemplateCache>,
+    ) -> notify::Result<RecommendedWatcher> {
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if !event.kind.is_access() => {
+                tracing::info!("Templates changed, reloading: {:?}", event.paths);
+                cache.clear();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!("Template watcher error, reloading templates: {e}");
+                cache.clear();
+            }
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    }
+
+    /// Paths that can provide `name`, most specific first
+    fn candidates(
+        &self,
+        name: &str,
+        scope: Option<TemplateScope<'_>>,
+    ) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(scope) = scope {
+            // Graph names come from requests and must not escape the templates directory
+            if is_plain_name(scope.graph) {
+                candidates.push(self.dir.join("graphs").join(scope.graph).join(name));
+            }
+            if is_plain_name(scope.model_family) {
+                candidates.push(self.dir.join("models").join(scope.model_family).join(name));
+            }
+        }
+        candidates.push(self.dir.join(name));
+        candidates
+    }
+
+    fn load(
+        &self,
+        name: &str,
+        scope: Option<TemplateScope<'_>>,
+    ) -> Result<Arc<Template>, TemplateError> {
+        let searched = self.candidates(name, scope);
+        for path in &searched {
+            if let Some(template) = self.read(path)? {
+                return Ok(template);
+            }
+        }
+        Err(TemplateError::NotFound {
+            name: name.to_string(),
+            searched,
+        })
+    }
+
+    fn read(
+        &self,
+        path: &Path,
+    ) -> Result<Option<Arc<Template>>, TemplateError> {
+        if let Some(cached) = self.cache.get(path) {
+            return Ok(cached.clone());
+        }
+
+        let template = match std::fs::read_to_string(path) {
+            Ok(source) => Some(Arc::new(Template::parse(&path.display().to_string(), &source)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(source) => {
+                return Err(TemplateError::Read {
+                    path: path.to_path_buf(),
+                    source,
+                });
+            }
+        };
+        self.cache.insert(path.to_path_buf(), template.clone());
+        Ok(template)
+    }
+}
+
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+pub struct TemplateEngine;
+
+impl TemplateEngine {
+    /// Open the templates directory and check that every base template can be loaded and only
+    /// uses the variables it is given
+    ///
+    /// # Errors
+    ///
+    /// Returns the first template that is missing, unreadable, malformed or uses an unknown
+    /// variable.
+    pub fn init() -> Result<(), TemplateError> {
+        tracing::info!("Loading templates from {}", Self::store().dir.display());
+        Self::check()
+    }
+
+    /// Check that every base template can still be loaded, as [`TemplateEngine::init`] does at
+    /// startup
+    ///
+    /// # Errors
+    ///
+    /// Returns the first template that is missing, unreadable, malformed or uses an unknown
+    /// variable.
+    pub fn check() -> Result<(), TemplateError> {
+        let store = Self::store();
+        for (name, variables) in [
+            (SYSTEM_PROMPT, SYSTEM_PROMPT_VARIABLES),
+            (USER_PROMPT, USER_PROMPT_VARIABLES),
+            (LAST_REQUEST_PROMPT, LAST_REQUEST_PROMPT_VARIABLES),
+        ] {
+            store.load(name, None)?.check(variables)?;
+        }
+        for dialect in Dialect::iter() {
+            store.load(&dialect.template_path(), None)?.check(DIALECT_VARIABLES)?;
+        }
+        Ok(())
+    }
+
+    fn store() -> &'static TemplateStore {
+        STORE.get_or_init(|| TemplateStore::open(&Settings::get().templates))
+    }
+
+    /// Load a template, e.g. `system_prompt.txt`, applying the overrides of `scope`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no candidate file exists or one cannot be read or parsed.
+    pub fn load_template(
+        name: &str,
+        scope: TemplateScope<'_>,
+    ) -> Result<Arc<Template>, TemplateError> {
+        Self::store().load(name, Some(scope))
+    }
+
+    /// Render the system prompt template with the given ontology, the section of the dialect and
+    /// the graph's few-shot examples. `delimited` tells the template whether user content is
+    /// wrapped in `<user_input>` tags.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a template cannot be loaded or rendered.
+    pub fn render_system_prompt(
+        scope: TemplateScope<'_>,
+        ontology: &str,
+        dialect: Dialect,
+        examples: &[Example],
+        delimited: bool,
+    ) -> Result<String, TemplateError> {
+        let examples: Vec<Value> = examples
+            .iter()
+            .map(|example| {
+                Value::Map(BTreeMap::from([
+                    ("question".to_string(), Value::from(example.question.as_str())),
+                    ("query".to_string(), Value::from(example.query.as_str())),
+                ]))
+            })
+            .collect();
+        let mut context = Context::from([("ONTOLOGY", Value::from(ontology)), ("EXAMPLES", Value::List(examples))]);
+
+        let dialect_section = Self::load_template(&dialect.template_path(), scope)?.render(&context)?;
+        context.insert("DIALECT", Value::from(dialect_section.trim_end()));
+        context.insert("DELIMITED", Value::from(delimited));
+
+        Self::load_template(SYSTEM_PROMPT, scope)?.render(&context)
+    }
+
+    /// Render the user prompt template with the given question.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template cannot be loaded or rendered.
+    pub fn render_user_prompt(
+        scope: TemplateScope<'_>,
+        question: &str,
+    ) -> Result<String, TemplateError> {
+        let context = Context::from([("QUESTION", Value::from(question))]);
+        Self::load_template(USER_PROMPT, scope)?.render(&context)
+    }
+
+    /// Render the last request prompt template with the given parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template cannot be loaded or rendered.
+    pub fn render_last_request_prompt(
+        scope: TemplateScope<'_>,
+        question: &str,
+        cypher_query: &str,
+        cypher_result: &str,
+        truncated: bool,
+        delimited: bool,
+        redacted: bool,
+    ) -> Result<String, TemplateError> {
+        let context = Context::from([
+            ("CYPHER_QUERY", Value::from(cypher_query)),
+            ("CYPHER_RESULT", Value::from(cypher_result)),
+            ("USER_QUESTION", Value::from(question)),
+            ("TRUNCATED", Value::from(truncated)),
+            (
+                "TRUNCATION_NOTE",
+                Value::from(if truncated { TRUNCATION_NOTE } else { "" }),
+            ),
+            ("DELIMITED", Value::from(delimited)),
+            ("REDACTED", Value::from(redacted)),
+        ]);
+        Self::load_template(LAST_REQUEST_PROMPT, scope)?.render(&context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(dir: &Path) -> TemplateStore {
+        TemplateStore::open(&TemplateSettings {
+            dir: dir.to_path_buf(),
+            watch: false,
+        })
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let dir = std::env::temp_dir().join(format!("text-to-cypher-templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("graphs/movies")).unwrap();
+        std::fs::create_dir_all(dir.join("models/openai")).unwrap();
+        std::fs::write(dir.join(USER_PROMPT), "base").unwrap();
+        std::fs::write(dir.join("models/openai").join(USER_PROMPT), "openai").unwrap();
+        std::fs::write(dir.join("graphs/movies").join(USER_PROMPT), "movies").unwrap();
+
+        let store = store(&dir);
+        let load = |graph, model_family| {
+            store
+                .load(USER_PROMPT, Some(TemplateScope { graph, model_family }))
+                .unwrap()
+                .render(&Context::new())
+                .unwrap()
+        };
+        assert_eq!(load("movies", "openai"), "movies");
+        assert_eq!(load("stocks", "openai"), "openai");
+        assert_eq!(load("stocks", "anthropic"), "base");
+        assert_eq!(load("../movies", "anthropic"), "base");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_template_lists_searched_paths() {
+        let dir = std::env::temp_dir().join(format!("text-to-cypher-templates-{}", uuid::Uuid::new_v4()));
+        let scope = TemplateScope {
+            graph: "movies",
+            model_family: "openai",
+        };
+
+        let error = store(&dir).load(SYSTEM_PROMPT, Some(scope)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Template system_prompt.txt not found (searched {}, {}, {})",
+                dir.join("graphs/movies/system_prompt.txt").display(),
+                dir.join("models/openai/system_prompt.txt").display(),
+                dir.join("system_prompt.txt").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! TLS
+//!
+//! With `tls.cert_file` and `tls.key_file` configured, the HTTP API and the MCP server are served
+//! over HTTPS with rustls. The certificate and key are loaded at startup, and unless `tls.watch`
+//! is off the directories holding them are watched: a change to either file reloads the pair for
+//! new connections, so a renewed certificate applies without a restart. A pair that fails to load
+//! on a change is logged and the previous one stays in use.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::SystemTime;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::ServerConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::config::TlsSettings;
+
+static TLS: OnceLock<Option<Tls>> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum TlsError {
+    Read {
+        path: PathBuf,
+        source: rustls::pki_types::pem::Error,
+    },
+    NoCertificate(PathBuf),
+    /// The key cannot be used, or does not belong to the certificate
+    Key {
+        path: PathBuf,
+        source: rustls::Error,
+    },
+    Config(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::Read { path, source } => write!(f, "Failed to read {}: {source}", path.display()),
+            Self::NoCertificate(path) => write!(f, "No certificate found in {}", path.display()),
+            Self::Key { path, source } => write!(f, "Unusable private key {}: {source}", path.display()),
+            Self::Config(source) => write!(f, "Failed to configure TLS: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+/// The certificate and key presented to clients, replaced when the files change
+#[derive(Debug)]
+struct CertificateStore {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateStore {
+    fn open(
+        cert_file: &Path,
+        key_file: &Path,
+        provider: Arc<CryptoProvider>,
+    ) -> Result<Self, TlsError> {
+        let current = load(cert_file, key_file, &provider)?;
+        Ok(Self {
+            cert_file: cert_file.to_path_buf(),
+            key_file: key_file.to_path_buf(),
+            provider,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    fn reload(&self) {
+        match load(&self.cert_file, &self.key_file, &self.provider) {
+            Ok(key) => {
+                if let Ok(mut current) = self.current.write() {
+                    *current = Arc::new(key);
+                }
+                tracing::info!("Reloaded TLS certificate {}", self.cert_file.display());
+            }
+            Err(e) => tracing::warn!("{e}; keeping the previous TLS certificate"),
+        }
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(
+        &self,
+        _client_hello: ClientHello<'_>,
+    ) -> Option<Arc<CertifiedKey>> {
+        let current = self.current.read().unwrap_or_else(std::sync::PoisonError::into_inner);
+        Some(Arc::clone(&current))
+    }
+}
+
+/// Read a certificate chain and its private key from PEM files
+fn load(
+    cert_file: &Path,
+    key_file: &Path,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, TlsError> {
+    let read_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| TlsError::Read { path, source }
+    };
+    let chain = CertificateDer::pem_file_iter(cert_file)
+        .map_err(read_error(cert_file))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error(cert_file))?;
+    if chain.is_empty() {
+        return Err(TlsError::NoCertificate(cert_file.to_path_buf()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_file).map_err(read_error(key_file))?;
+    CertifiedKey::from_der(chain, key, provider).map_err(|source| TlsError::Key {
+        path: key_file.to_path_buf(),
+        source,
+    })
+}
+
+struct Tls {
+    config: ServerConfig,
+    _watcher: Option<Mutex<RecommendedWatcher>>,
+}
+
+impl Tls {
+    fn open(settings: &TlsSettings) -> Result<Option<Self>, TlsError> {
+        let (Some(cert_file), Some(key_file)) = (&settings.cert_file, &settings.key_file) else {
+            return Ok(None);
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let store = Arc::new(CertificateStore::open(cert_file, key_file, Arc::clone(&provider))?);
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::Config)?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(&store) as Arc<dyn ResolvesServerCert>);
+
+        let watcher = if settings.watch {
+            match watch(store) {
+                Ok(watcher) => Some(Mutex::new(watcher)),
+                Err(e) => {
+                    tracing::warn!("Failed to watch the TLS certificate, renewals need a restart: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        tracing::info!("TLS enabled with certificate {}", cert_file.display());
+        Ok(Some(Self {
+            config,
+            _watcher: watcher,
+        }))
+    }
+}
+
+/// Where a file's path resolves to, with its size and modification time
+type Fingerprint = Option<(PathBuf, u64, SystemTime)>;
+
+fn fingerprint(file: &Path) -> Fingerprint {
+    let resolved = std::fs::canonicalize(file).ok()?;
+    let metadata = std::fs::metadata(&resolved).ok()?;
+    Some((resolved, metadata.len(), metadata.modified().ok()?))
+}
+
+/// Watch the directories of the certificate and key rather than the files, which are often
+/// replaced by renaming or by swapping a symlink
+///
+/// Other files in the directories change too, so an event only reloads the pair when the
+/// certificate or key no longer resolves to the same file, size and modification time. Comparing
+/// the resolved files rather than the paths in the events also catches a swapped symlink to a
+/// parent directory, as in Kubernetes secret volumes.
+fn watch(store: Arc<CertificateStore>) -> notify::Result<RecommendedWatcher> {
+    let dirs: BTreeSet<PathBuf> = [&store.cert_file, &store.key_file]
+        .into_iter()
+        .map(|file| match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .collect();
+    let mut loaded = [fingerprint(&store.cert_file), fingerprint(&store.key_file)];
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if !event.kind.is_access() => {
+            let current = [fingerprint(&store.cert_file), fingerprint(&store.key_file)];
+            if current != loaded {
+                loaded = current;
+                store.reload();
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::warn!("TLS certificate watcher error, reloading: {e}");
+            store.reload();
+        }
+    })?;
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+/// Load the certificate and start watching it, once at startup
+///
+/// # Errors
+///
+/// Returns an error if TLS is configured but the certificate or key cannot be loaded.
+pub fn init(settings: &TlsSettings) -> Result<(), TlsError> {
+    if TLS.get().is_none() {
+        let tls = Tls::open(settings)?;
+        let _ = TLS.set(tls);
+    }
+    Ok(())
+}
+
+/// Configuration of the servers, or `None` if they serve plain HTTP
+#[must_use]
+pub fn server_config() -> Option<ServerConfig> {
+    TLS.get()?.as_ref().map(|tls| tls.config.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_errors() {
+        let provider = rustls::crypto::ring::default_provider();
+        let dir = std::env::temp_dir().join(format!("text-to-cypher-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+
+        assert!(matches!(
+            load(&dir.join("missing.pem"), &empty, &provider),
+            Err(TlsError::Read { .. })
+        ));
+        assert!(matches!(
+            load(&empty, &empty, &provider),
+            Err(TlsError::NoCertificate(path)) if path == empty
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = std::env::temp_dir().join(format!("text-to-cypher-tls-fingerprint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("cert.pem");
+        std::fs::write(&cert, "first").unwrap();
+        let first = fingerprint(&cert);
+        assert!(first.is_some());
+
+        std::fs::write(dir.join("unrelated.txt"), "noise").unwrap();
+        assert_eq!(fingerprint(&cert), first);
+
+        std::fs::write(&cert, "renewed").unwrap();
+        assert_ne!(fingerprint(&cert), first);
+        assert_eq!(fingerprint(&dir.join("missing.pem")), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! Token Usage Accounting Module
+//!
+//! Captures the prompt and completion tokens reported by the AI provider for every model call,
+//! aggregates them per API key and per graph, and estimates cost from an optional price table.
+//!
+//! ## Price Table Format
+//!
+//! `model=prompt/completion` pairs separated by commas, with prices in USD per million tokens:
+//! `gpt-4o-mini=0.15/0.60,anthropic:claude-3-haiku=0.25/1.25`
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Prompt and completion tokens consumed by one or more model calls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    #[must_use]
+    pub const fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub const fn add(
+        &mut self,
+        other: &Self,
+    ) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+impl From<&genai::chat::Usage> for TokenUsage {
+    fn from(usage: &genai::chat::Usage) -> Self {
+        let to_u64 = |tokens: Option<i32>| tokens.and_then(|t| u64::try_from(t).ok()).unwrap_or(0);
+        Self {
+            prompt_tokens: to_u64(usage.prompt_tokens),
+            completion_tokens: to_u64(usage.completion_tokens),
+        }
+    }
+}
+
+/// Token usage of a single text-to-cypher request, reported in the final `Usage` progress event
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct RequestUsage {
+    pub model: String,
+    /// Usage of the call that generates the Cypher query
+    pub query_generation: TokenUsage,
+    /// Usage of the call that generates the final answer
+    pub answer_generation: TokenUsage,
+    /// Estimated cost in USD, present when the model has an entry in the price table
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<f64>,
+}
+
+impl RequestUsage {
+    #[must_use]
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub const fn total(&self) -> TokenUsage {
+        let mut total = self.query_generation;
+        total.add(&self.answer_generation);
+        total
+    }
+}
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl ModelPrice {
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn estimate_cost(
+        &self,
+        usage: &TokenUsage,
+    ) -> f64 {
+        (usage.prompt_tokens as f64).mul_add(
+            self.prompt_per_million,
+            usage.completion_tokens as f64 * self.completion_per_million,
+        ) / 1_000_000.0
+    }
+}
+
+/// Parse a price table, skipping malformed entries
+#[must_use]
+pub fn parse_price_table(table: &str) -> HashMap<String, ModelPrice> {
+    table
+        .split(',')
+        .filter_map(|entry| {
+            let (model, prices) = entry.split_once('=')?;
+            let parsed = prices
+                .split_once('/')
+                .and_then(|(prompt, completion)| Some((prompt.trim().parse().ok()?, completion.trim().parse().ok()?)));
+            if let Some((prompt_per_million, completion_per_million)) = parsed {
+                Some((
+                    model.trim().to_string(),
+                    ModelPrice {
+                        prompt_per_million,
+                        completion_per_million,
+                    },
+                ))
+            } else {
+                tracing::warn!("Ignoring invalid price entry for model '{}': {}", model.trim(), prices);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Accumulated usage for a single API key or graph
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UsageTotals {
+    pub requests: u64,
+    #[serde(flatten)]
+    pub tokens: TokenUsage,
+    pub estimated_cost: f64,
+}
+
+/// Usage totals for a single API key or graph, as returned by the usage endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UsageEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// Snapshot of the usage ledger
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UsageReport {
+    pub by_key: Vec<UsageEntry>,
+    pub by_graph: Vec<UsageEntry>,
+}
+
+/// In-memory usage totals per API key and per graph
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    by_key: DashMap<String, UsageTotals>,
+    by_graph: DashMap<String, UsageTotals>,
+}
+
+impl UsageLedger {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a request's usage to the totals of `account`, the API key it was made with, and its graph
+    pub fn record(
+        &self,
+        account: &str,
+        graph_name: &str,
+        usage: &RequestUsage,
+    ) {
+        let total = usage.total();
+        let cost = usage.estimated_cost.unwrap_or(0.0);
+        for (map, name) in [(&self.by_key, account), (&self.by_graph, graph_name)] {
+            let mut totals = map.entry(name.to_string()).or_default();
+            totals.requests += 1;
+            totals.tokens.add(&total);
+            totals.estimated_cost += cost;
+        }
+    }
+
+    #[must_use]
+    pub fn report(&self) -> UsageReport {
+        let collect = |map: &DashMap<String, UsageTotals>| {
+            let mut entries: Vec<UsageEntry> = map
+                .iter()
+                .map(|entry| UsageEntry {
+                    name: entry.key().clone(),
+                    totals: entry.value().clone(),
+                })
+                .collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            entries
+        };
+
+        UsageReport {
+            by_key: collect(&self.by_key),
+            by_graph: collect(&self.by_graph),
+        }
+    }
+}
+
+/// Identify an API key in usage reports without exposing it
+///
+/// Combines the last four characters with a stable FNV-1a fingerprint of the whole key.
+#[must_use]
+pub fn key_id(key: Option<&str>) -> String {
+    let Some(key) = key else {
+        return "anonymous".to_string();
+    };
+
+    let fingerprint = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    let suffix: String = key.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("***{suffix}#{:08x}", fingerprint >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_price_table() {
+        let prices = parse_price_table("gpt-4o-mini=0.15/0.60, bad=1, other=2/x");
+        assert_eq!(prices.len(), 1);
+        assert_eq!(
+            prices["gpt-4o-mini"],
+            ModelPrice {
+                prompt_per_million: 0.15,
+                completion_per_million: 0.60,
+            }
+        );
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let price = ModelPrice {
+            prompt_per_million: 1.0,
+            completion_per_million: 2.0,
+        };
+        let usage = TokenUsage {
+            prompt_tokens: 500_000,
+            completion_tokens: 250_000,
+        };
+        assert!((price.estimate_cost(&usage) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_ledger_aggregates_per_key_and_graph() {
+        let ledger = UsageLedger::new();
+        let mut usage = RequestUsage::new("gpt-4o-mini");
+        usage.query_generation = TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        };
+        usage.answer_generation = TokenUsage {
+            prompt_tokens: 20,
+            completion_tokens: 7,
+        };
+
+        ledger.record("key-a", "movies", &usage);
+        ledger.record("key-a", "social", &usage);
+
+        let report = ledger.report();
+        assert_eq!(report.by_key.len(), 1);
+        assert_eq!(report.by_key[0].totals.requests, 2);
+        assert_eq!(report.by_key[0].totals.tokens.total_tokens(), 84);
+        assert_eq!(report.by_graph.len(), 2);
+    }
+
+    #[test]
+    fn test_key_id_hides_key() {
+        let id = key_id(Some("sk-secret-1234"));
+        assert!(id.starts_with("***1234#"));
+        assert!(!id.contains("secret"));
+        assert_eq!(id, key_id(Some("sk-secret-1234")));
+        assert_eq!(key_id(None), "anonymous");
+    }
+}
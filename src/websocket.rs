@@ -0,0 +1,864 @@
+//! WebSocket Sessions
+//!
+//! `GET /ws` upgrades to a WebSocket carrying interactive sessions. Where a `/text_to_cypher`
+//! stream answers one request, a session keeps its conversation: each question runs through the
+//! same pipeline with the earlier questions and answers of the session as chat history, and its
+//! progress events are sent as they happen. The history keeps answers as the model wrote them,
+//! with placeholders in place of redacted values, so those values never reach the model. On the
+//! same connection the client can cancel the running question of a session, reply to a clarifying
+//! question of the model, or ask a follow-up.
+//!
+//! Messages are JSON text frames tagged by `type`. The client sends:
+//!
+//! - `question`: `session` and `question`, plus `graph_name` on the first question of a session;
+//!   `model`, `key`, `falkordb_connection` and `dialect` are optional and kept for later questions
+//! - `answer`: `session` and `answer`, replying to the last answer of the session
+//! - `cancel`: `session`, to stop its running question
+//!
+//! The server sends:
+//!
+//! - `progress`: `session` and `event`, one of the `/text_to_cypher` progress events
+//! - `done`: `session` and `cancelled`, once a question has finished or was cancelled
+//! - `error`: `message`, and the `session` it concerns if any, when a client message is refused
+//!
+//! Questions are authorized and rate limited like `/text_to_cypher` requests. On shutdown new
+//! questions are refused, and the connection is closed once its running questions have finished.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, rt, web};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::chat::{ChatMessage, ChatRequest, ChatRole};
+use crate::dialect::Dialect;
+use crate::error::ApiError;
+use crate::limits::{self, Limiter};
+use crate::llm::{self, ChatBackend};
+use crate::metrics::{Metrics, RequestOutcome};
+use crate::shutdown::{Phase, Shutdown};
+use crate::{AppConfig, Progress, RequestContext, TextToCypherRequest};
+
+/// Most sessions one connection may hold
+const MAX_SESSIONS: usize = 32;
+
+/// A message from the client
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Ask a question in a session, starting the session if it is new
+    Question {
+        session: String,
+        question: String,
+        /// Required on the first question of a session
+        graph_name: Option<String>,
+        model: Option<String>,
+        key: Option<String>,
+        /// Name of a configured connection; connection strings need the admin scope and
+        /// `server.allow_raw_connections`
+        falkordb_connection: Option<String>,
+        dialect: Option<Dialect>,
+    },
+    /// Reply to the last answer of a session, e.g. a clarifying question of the model
+    Answer { session: String, answer: String },
+    /// Stop the running question of a session
+    Cancel { session: String },
+}
+
+/// A message to the client
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Progress {
+        session: String,
+        event: Progress,
+    },
+    /// The question has finished; no more progress events of it follow
+    Done {
+        session: String,
+        cancelled: bool,
+    },
+    /// A client message was refused
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        message: String,
+    },
+}
+
+/// The conversation of a session
+struct Conversation {
+    /// Graph, model and connection of the session, with the questions and answers so far
+    request: TextToCypherRequest,
+    running: Option<Running>,
+}
+
+/// The question a session is running
+struct Running {
+    request_id: String,
+    question: ChatMessage,
+    /// Stops the pipeline, which still records the tokens it used
+    pipeline: CancellationToken,
+    forwarder: JoinHandle<()>,
+}
+
+impl Running {
+    /// Stop the pipeline and the forwarding of its events
+    fn cancel(&self) {
+        self.pipeline.cancel();
+        self.forwarder.abort();
+    }
+}
+
+/// Sent by a forwarder once the pipeline of a question has finished
+struct Finished {
+    session: String,
+    request_id: String,
+    /// The final answer as the model wrote it, with placeholders for the redacted values, if the
+    /// pipeline produced one
+    answer: Option<String>,
+}
+
+/// Where the messages of a connection go
+#[derive(Clone)]
+enum Outbox {
+    Socket(actix_ws::Session),
+    /// The messages as JSON values, for tests
+    #[cfg(test)]
+    Channel(mpsc::UnboundedSender<serde_json::Value>),
+}
+
+impl Outbox {
+    async fn send(
+        &mut self,
+        message: &ServerMessage,
+    ) -> Result<(), actix_ws::Closed> {
+        match self {
+            Self::Socket(socket) => match serde_json::to_string(message) {
+                Ok(json) => socket.text(json).await,
+                Err(e) => {
+                    tracing::error!("Failed to serialize WebSocket message: {e}");
+                    Ok(())
+                }
+            },
+            #[cfg(test)]
+            Self::Channel(messages) => messages
+                .send(serde_json::to_value(message).unwrap_or_default())
+                .map_err(|_| actix_ws::Closed),
+        }
+    }
+
+    async fn pong(
+        &mut self,
+        bytes: &[u8],
+    ) {
+        match self {
+            Self::Socket(socket) => {
+                let _ = socket.pong(bytes).await;
+            }
+            #[cfg(test)]
+            Self::Channel(_) => {}
+        }
+    }
+
+    async fn close(
+        self,
+        reason: Option<CloseReason>,
+    ) {
+        match self {
+            Self::Socket(socket) => {
+                let _ = socket.close(reason).await;
+            }
+            #[cfg(test)]
+            Self::Channel(_) => {}
+        }
+    }
+}
+
+struct Connection {
+    principal: Principal,
+    client_addr: String,
+    outbox: Outbox,
+    conversations: HashMap<String, Conversation>,
+    finished: mpsc::UnboundedSender<Finished>,
+    shutdown: Shutdown,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    responses(
+        (status = 101, description = "Switched to a WebSocket of interactive sessions, exchanging ClientMessage and ServerMessage JSON text frames"),
+        (status = 503, description = "The server is shutting down", body = crate::error::ErrorResponse)
+    )
+)]
+#[actix_web::get("/ws")]
+#[allow(clippy::future_not_send)] // actix handlers run on a single-threaded worker
+pub async fn connect(
+    http_request: HttpRequest,
+    principal: Principal,
+    shutdown: web::Data<Shutdown>,
+    body: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    if shutdown.is_draining() {
+        return Err(ApiError::ServiceUnavailable("The server is shutting down".to_string()).into());
+    }
+    let (response, socket, stream) = actix_ws::handle(&http_request, body)?;
+    let client_addr = limits::client_addr(&http_request);
+
+    let (finished, finished_rx) = mpsc::unbounded_channel();
+    let connection = Connection {
+        principal,
+        client_addr,
+        outbox: Outbox::Socket(socket),
+        conversations: HashMap::new(),
+        finished,
+        shutdown: Shutdown::clone(&shutdown),
+    };
+    rt::spawn(connection.run(stream, finished_rx));
+    Ok(response)
+}
+
+impl Connection {
+    #[allow(clippy::future_not_send)] // spawned on the worker that accepted the connection
+    async fn run(
+        mut self,
+        stream: actix_ws::MessageStream,
+        mut finished_rx: mpsc::UnboundedReceiver<Finished>,
+    ) {
+        let mut stream = stream.aggregate_continuations();
+        let shutdown = self.shutdown.clone();
+        let mut shutdown = std::pin::pin!(shutdown.reached(Phase::Draining));
+        let mut draining = false;
+        let reason = loop {
+            tokio::select! {
+                message = stream.recv() => match message {
+                    Some(Ok(AggregatedMessage::Text(text))) => self.handle(&text).await,
+                    Some(Ok(AggregatedMessage::Binary(_))) => {
+                        self.send_error(None, "Messages must be JSON text".to_string()).await;
+                    }
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => self.outbox.pong(&bytes).await,
+                    Some(Ok(AggregatedMessage::Pong(_))) => {}
+                    Some(Ok(AggregatedMessage::Close(_))) | None => break None,
+                    Some(Err(e)) => {
+                        tracing::warn!("WebSocket protocol error: {e}");
+                        break Some(CloseReason::from(CloseCode::Protocol));
+                    }
+                },
+                Some(finished) = finished_rx.recv() => self.finish(finished).await,
+                () = &mut shutdown, if !draining => draining = true,
+            }
+            if draining && self.conversations.values().all(|conversation| conversation.running.is_none()) {
+                break Some(CloseReason {
+                    code: CloseCode::Away,
+                    description: Some("The server is shutting down".to_string()),
+                });
+            }
+        };
+
+        for conversation in self.conversations.values_mut() {
+            if let Some(running) = conversation.running.take() {
+                running.cancel();
+            }
+        }
+        self.outbox.close(reason).await;
+    }
+
+    async fn handle(
+        &mut self,
+        text: &str,
+    ) {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => return self.send_error(None, format!("Invalid message: {e}")).await,
+        };
+        match message {
+            ClientMessage::Question {
+                session,
+                question,
+                graph_name,
+                model,
+                key,
+                falkordb_connection,
+                dialect,
+            } => {
+                if !self.conversations.contains_key(&session) {
+                    let Some(graph_name) = graph_name.clone() else {
+                        let message = "The first question of a session needs a graph_name".to_string();
+                        return self.send_error(Some(session), message).await;
+                    };
+                    if self.conversations.len() >= MAX_SESSIONS {
+                        let message = format!("A connection may hold at most {MAX_SESSIONS} sessions");
+                        return self.send_error(Some(session), message).await;
+                    }
+                    let request = TextToCypherRequest {
+                        graph_name,
+                        chat_request: ChatRequest { messages: Vec::new() },
+                        model: None,
+                        key: None,
+                        falkordb_connection: None,
+                        dialect: None,
+                    };
+                    self.conversations
+                        .insert(session.clone(), Conversation { request, running: None });
+                }
+                if let Some(conversation) = self.conversations.get_mut(&session) {
+                    let request = &mut conversation.request;
+                    request.graph_name = graph_name.unwrap_or_else(|| request.graph_name.clone());
+                    request.model = model.or_else(|| request.model.take());
+                    request.key = key.or_else(|| request.key.take());
+                    request.falkordb_connection = falkordb_connection.or_else(|| request.falkordb_connection.take());
+                    request.dialect = dialect.or(request.dialect);
+                }
+                self.ask(session, question).await;
+            }
+            ClientMessage::Answer { session, answer } => {
+                let replied = self.conversations.get(&session).is_some_and(|conversation| {
+                    conversation
+                        .request
+                        .chat_request
+                        .messages
+                        .last()
+                        .is_some_and(|message| message.role == ChatRole::Assistant)
+                });
+                if replied {
+                    self.ask(session, answer).await;
+                } else {
+                    let message = format!("Session {session} has no answer to reply to");
+                    self.send_error(Some(session), message).await;
+                }
+            }
+            ClientMessage::Cancel { session } => self.cancel(session).await,
+        }
+    }
+
+    /// Run the pipeline for the next user message of a session
+    async fn ask(
+        &mut self,
+        session: String,
+        text: String,
+    ) {
+        if self.shutdown.is_draining() {
+            return self.send_error(Some(session), "The server is shutting down".to_string()).await;
+        }
+        let Some(conversation) = self.conversations.get(&session) else {
+            return;
+        };
+        if conversation.running.is_some() {
+            let message = format!("Session {session} is already running a question; cancel it first");
+            return self.send_error(Some(session), message).await;
+        }
+
+        let question = ChatMessage {
+            role: ChatRole::User,
+            content: text,
+        };
+        let mut request = conversation.request.clone();
+        request.chat_request.messages.push(question.clone());
+        let (backend, context) = match self.admit(&mut request).await {
+            Ok(admitted) => admitted,
+            Err(message) => return self.send_error(Some(session), message).await,
+        };
+        self.start(&session, question, request, backend, context);
+    }
+
+    /// Run the pipeline for an admitted question, forwarding its events to the client
+    fn start(
+        &mut self,
+        session: &str,
+        question: ChatMessage,
+        request: TextToCypherRequest,
+        backend: Arc<dyn ChatBackend>,
+        context: RequestContext,
+    ) {
+        let span = tracing::info_span!(
+            "text_to_cypher",
+            graph = %request.graph_name,
+            model = request.model.as_deref().unwrap_or_default(),
+            session = session
+        );
+        let request_id = context.request_id.clone();
+        let (tx, rx) = mpsc::channel(100);
+        let pipeline = crate::spawn_pipeline(request, context, backend, tx, span, &self.shutdown);
+        let forwarder = rt::spawn(forward(
+            self.outbox.clone(),
+            session.to_string(),
+            request_id.clone(),
+            rx,
+            self.finished.clone(),
+        ));
+        if let Some(conversation) = self.conversations.get_mut(session) {
+            conversation.running = Some(Running {
+                request_id,
+                question,
+                pipeline,
+                forwarder,
+            });
+        }
+    }
+
+    /// Authorize and rate limit a request and apply the default model and key, as
+    /// `/text_to_cypher` does, returning the model backend and request context
+    async fn admit(
+        &self,
+        request: &mut TextToCypherRequest,
+    ) -> Result<(Arc<dyn ChatBackend>, RequestContext), String> {
+        self.principal.authorize_graph(&request.graph_name).map_err(|e| e.to_string())?;
+        if let Some(connection) = &request.falkordb_connection {
+            self.principal.authorize_connection(connection).map_err(|e| e.to_string())?;
+        }
+        let config = AppConfig::get();
+        if let Err(e) = config.check_requested_model(request.model.as_deref()) {
+            Metrics::get().record_request(RequestOutcome::InvalidRequest);
+            return Err(e.to_string());
+        }
+        let subjects = self.principal.limit_subjects(&self.client_addr);
+        if let Err(limited) = Limiter::get().check(&subjects).await {
+            tracing::warn!(
+                "Rate limited question for graph {}: {}",
+                request.graph_name,
+                limited.message
+            );
+            Metrics::get().record_request(RequestOutcome::RateLimited);
+            return Err(ApiError::from(limited).to_string());
+        }
+
+        if request.model.is_none() {
+            request.model.clone_from(&config.default_model);
+        }
+        if request.key.is_none() {
+            request.key.clone_from(&config.default_key);
+        }
+        let Some(model) = &request.model else {
+            Metrics::get().record_request(RequestOutcome::InvalidRequest);
+            return Err("Model must be provided either in the question or as DEFAULT_MODEL in .env file".to_string());
+        };
+        let backend = match llm::backend_for(model, request.key.as_deref()).await {
+            Ok(backend) => backend,
+            Err(e) => {
+                Metrics::get().record_model_error(model);
+                Metrics::get().record_request(RequestOutcome::InvalidRequest);
+                return Err(format!("Failed to resolve service target: {e}"));
+            }
+        };
+        let context = RequestContext::new(&self.principal, request, &self.client_addr, subjects);
+        Ok((backend, context))
+    }
+
+    /// Record the question and the model's answer of a finished pipeline in its session's
+    /// conversation
+    async fn finish(
+        &mut self,
+        finished: Finished,
+    ) {
+        let Some(conversation) = self.conversations.get_mut(&finished.session) else {
+            return;
+        };
+        // A cancelled question may finish after the session has moved on
+        let Some(running) = conversation
+            .running
+            .take_if(|running| running.request_id == finished.request_id)
+        else {
+            return;
+        };
+        if let Some(answer) = finished.answer {
+            let messages = &mut conversation.request.chat_request.messages;
+            messages.push(running.question);
+            messages.push(ChatMessage {
+                role: ChatRole::Assistant,
+                content: answer,
+            });
+        }
+        self.send(&ServerMessage::Done {
+            session: finished.session,
+            cancelled: false,
+        })
+        .await;
+    }
+
+    /// Stop the running question of a session
+    async fn cancel(
+        &mut self,
+        session: String,
+    ) {
+        let running = self
+            .conversations
+            .get_mut(&session)
+            .and_then(|conversation| conversation.running.take());
+        if let Some(running) = running {
+            running.cancel();
+            tracing::info!(
+                "Cancelled request {} of WebSocket session {session}",
+                running.request_id
+            );
+            self.send(&ServerMessage::Done {
+                session,
+                cancelled: true,
+            })
+            .await;
+        } else {
+            let message = format!("Session {session} is not running a question");
+            self.send_error(Some(session), message).await;
+        }
+    }
+
+    async fn send_error(
+        &mut self,
+        session: Option<String>,
+        message: String,
+    ) {
+        self.send(&ServerMessage::Error { session, message }).await;
+    }
+
+    async fn send(
+        &mut self,
+        message: &ServerMessage,
+    ) {
+        // A closed socket ends the message stream, which ends the connection
+        let _ = self.outbox.send(message).await;
+    }
+}
+
+/// Send the progress events of a question to the client, then report the answer to the connection
+async fn forward(
+    mut outbox: Outbox,
+    session: String,
+    request_id: String,
+    mut rx: mpsc::Receiver<Progress>,
+    finished: mpsc::UnboundedSender<Finished>,
+) {
+    let mut answer = None;
+    while let Some(event) = rx.recv().await {
+        if let Progress::Result(result) = &event {
+            answer = Some(result.model_output.clone());
+        }
+        let message = ServerMessage::Progress {
+            session: session.clone(),
+            event,
+        };
+        if outbox.send(&message).await.is_err() {
+            tracing::warn!("WebSocket client disconnected, stopping session {session}");
+            return;
+        }
+    }
+    let _ = finished.send(Finished {
+        session,
+        request_id,
+        answer,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Answer;
+    use crate::graph::MEMORY_PREFIX;
+    use crate::limits::Subjects;
+    use crate::llm::MockBackend;
+    use crate::llm::mock::{MockCall, MockRule, MockScript};
+
+    const DIRECTOR_QUERY: &str = "MATCH (p:Person)-[:DIRECTED]->(m:Movie) RETURN p.name";
+    const TITLE_QUERY: &str = "MATCH (m:Movie) RETURN m.title";
+
+    /// A connection writing to a channel, with the channel and the receiver of finished questions
+    fn connection(
+        shutdown: &Shutdown
+    ) -> (
+        Connection,
+        mpsc::UnboundedReceiver<serde_json::Value>,
+        mpsc::UnboundedReceiver<Finished>,
+    ) {
+        let (outbox, messages) = mpsc::unbounded_channel();
+        let (finished, finished_rx) = mpsc::unbounded_channel();
+        let connection = Connection {
+            principal: Principal::unrestricted(),
+            client_addr: "127.0.0.1".to_string(),
+            outbox: Outbox::Channel(outbox),
+            conversations: HashMap::new(),
+            finished,
+            shutdown: shutdown.clone(),
+        };
+        (connection, messages, finished_rx)
+    }
+
+    fn conversation(falkordb_connection: Option<String>) -> Conversation {
+        Conversation {
+            request: TextToCypherRequest {
+                graph_name: "movies".to_string(),
+                chat_request: ChatRequest { messages: Vec::new() },
+                model: Some("mock:".to_string()),
+                key: None,
+                falkordb_connection,
+                dialect: None,
+            },
+            running: None,
+        }
+    }
+
+    fn rule(
+        pattern: &str,
+        call: MockCall,
+        response: &str,
+    ) -> MockRule {
+        MockRule {
+            pattern: pattern.to_string(),
+            call: Some(call),
+            response: response.to_string(),
+        }
+    }
+
+    /// Run a question as `ask` does once it is admitted, which needs settings tests cannot make:
+    /// the mock model and a `memory:` fixture are refused from clients
+    fn start_question(
+        connection: &mut Connection,
+        session: &str,
+        text: &str,
+        backend: Arc<dyn ChatBackend>,
+    ) {
+        let question = ChatMessage {
+            role: ChatRole::User,
+            content: text.to_string(),
+        };
+        let mut request = connection.conversations[session].request.clone();
+        request.chat_request.messages.push(question.clone());
+        let context = RequestContext::new(
+            &connection.principal,
+            &request,
+            &connection.client_addr,
+            Subjects::default(),
+        );
+        connection.start(session, question, request, backend, context);
+    }
+
+    /// The messages sent so far of the given type
+    fn sent(
+        messages: &mut mpsc::UnboundedReceiver<serde_json::Value>,
+        message_type: &str,
+    ) -> Vec<serde_json::Value> {
+        let mut sent = Vec::new();
+        while let Ok(message) = messages.try_recv() {
+            if message["type"] == message_type {
+                sent.push(message);
+            }
+        }
+        sent
+    }
+
+    #[actix_web::test]
+    async fn test_history_keeps_placeholders() {
+        let (outbox, mut messages) = mpsc::unbounded_channel();
+        let (finished, mut finished_rx) = mpsc::unbounded_channel();
+        let (tx, rx) = mpsc::channel(10);
+        tx.send(Progress::Result(Answer {
+            text: "Write to ann@example.com".to_string(),
+            model_output: "Write to [EMAIL_1]".to_string(),
+        }))
+        .await
+        .unwrap();
+        drop(tx);
+        forward(
+            Outbox::Channel(outbox),
+            "s1".to_string(),
+            "req-1".to_string(),
+            rx,
+            finished,
+        )
+        .await;
+
+        let sent = sent(&mut messages, "progress");
+        assert_eq!(sent[0]["event"]["Result"], "Write to ann@example.com");
+        let finished = finished_rx.recv().await.unwrap();
+        assert_eq!(finished.answer.as_deref(), Some("Write to [EMAIL_1]"));
+    }
+
+    #[actix_web::test]
+    async fn test_follow_up_carries_history() {
+        let fixture = serde_json::json!({
+            "movies": {
+                "nodes": [
+                    { "labels": ["Person"], "properties": { "name": "Michael Mann" } },
+                    { "labels": ["Movie"], "properties": { "title": "Heat" } }
+                ],
+                "edges": [{ "type": "DIRECTED", "src": 0, "dst": 1 }],
+                "queries": {
+                    DIRECTOR_QUERY: [["Michael Mann"]],
+                    TITLE_QUERY: [["Heat"]]
+                }
+            }
+        });
+        let path = std::env::temp_dir().join(format!("websocket-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, fixture.to_string()).unwrap();
+
+        // The follow-up only gets the title query if the model sees the first answer
+        let backend: Arc<dyn ChatBackend> = Arc::new(
+            MockBackend::new(MockScript {
+                rules: vec![
+                    rule(
+                        r"(?s)Michael Mann directed Heat\..*What else",
+                        MockCall::Chat,
+                        TITLE_QUERY,
+                    ),
+                    rule("(?i)who directed", MockCall::Chat, DIRECTOR_QUERY),
+                    rule("(?i)who directed", MockCall::Stream, "Michael Mann directed Heat."),
+                ],
+                ..MockScript::default()
+            })
+            .unwrap(),
+        );
+        let shutdown = Shutdown::default();
+        let (mut connection, mut messages, mut finished_rx) = connection(&shutdown);
+        connection.conversations.insert(
+            "s1".to_string(),
+            conversation(Some(format!("{MEMORY_PREFIX}{}", path.display()))),
+        );
+
+        for question in ["Who directed Heat?", "What else did he direct?"] {
+            start_question(&mut connection, "s1", question, Arc::clone(&backend));
+            let finished = finished_rx.recv().await.unwrap();
+            connection.finish(finished).await;
+        }
+        std::fs::remove_file(path).unwrap();
+        // Both pipelines were counted as in flight by this connection's shutdown state only
+        tokio::time::timeout(std::time::Duration::from_secs(5), shutdown.drained())
+            .await
+            .unwrap();
+
+        let queries: Vec<serde_json::Value> = sent(&mut messages, "progress")
+            .into_iter()
+            .filter_map(|message| message["event"].get("CypherQuery").cloned())
+            .collect();
+        assert_eq!(queries, vec![DIRECTOR_QUERY, TITLE_QUERY]);
+        let history: Vec<(ChatRole, &str)> = connection.conversations["s1"]
+            .request
+            .chat_request
+            .messages
+            .iter()
+            .map(|message| (message.role.clone(), message.content.as_str()))
+            .collect();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history[1], (ChatRole::Assistant, "Michael Mann directed Heat."));
+        assert_eq!(history[2], (ChatRole::User, "What else did he direct?"));
+    }
+
+    #[actix_web::test]
+    async fn test_cancel() {
+        let (mut connection, mut messages, _finished_rx) = connection(&Shutdown::default());
+        let pipeline = CancellationToken::new();
+        let forwarder = rt::spawn(std::future::pending::<()>());
+        let (cancelled, aborted) = (pipeline.clone(), forwarder.abort_handle());
+        let mut running = conversation(None);
+        running.running = Some(Running {
+            request_id: "req-1".to_string(),
+            question: ChatMessage {
+                role: ChatRole::User,
+                content: "Who directed Heat?".to_string(),
+            },
+            pipeline,
+            forwarder,
+        });
+        connection.conversations.insert("s1".to_string(), running);
+
+        connection.handle(r#"{"type": "cancel", "session": "s1"}"#).await;
+        assert_eq!(
+            messages.recv().await.unwrap(),
+            serde_json::json!({"type": "done", "session": "s1", "cancelled": true})
+        );
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(cancelled.is_cancelled());
+        assert!(aborted.is_finished());
+        assert!(connection.conversations["s1"].running.is_none());
+
+        connection.handle(r#"{"type": "cancel", "session": "s1"}"#).await;
+        assert_eq!(
+            messages.recv().await.unwrap()["message"],
+            "Session s1 is not running a question"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_answer_needs_an_answer() {
+        let (mut connection, mut messages, _finished_rx) = connection(&Shutdown::default());
+        connection.conversations.insert("s1".to_string(), conversation(None));
+
+        for session in ["s1", "unknown"] {
+            connection
+                .handle(&format!(
+                    r#"{{"type": "answer", "session": "{session}", "answer": "Yes"}}"#
+                ))
+                .await;
+            assert_eq!(
+                messages.recv().await.unwrap(),
+                serde_json::json!({
+                    "type": "error",
+                    "session": session,
+                    "message": format!("Session {session} has no answer to reply to")
+                })
+            );
+        }
+        assert!(connection.conversations["s1"].request.chat_request.messages.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_session_limit() {
+        let (mut connection, mut messages, _finished_rx) = connection(&Shutdown::default());
+        for index in 0..MAX_SESSIONS {
+            connection.conversations.insert(format!("s{index}"), conversation(None));
+        }
+
+        connection
+            .handle(r#"{"type": "question", "session": "new", "graph_name": "movies", "question": "Who?"}"#)
+            .await;
+        assert_eq!(
+            messages.recv().await.unwrap(),
+            serde_json::json!({
+                "type": "error",
+                "session": "new",
+                "message": format!("A connection may hold at most {MAX_SESSIONS} sessions")
+            })
+        );
+        assert_eq!(connection.conversations.len(), MAX_SESSIONS);
+    }
+
+    #[test]
+    fn test_messages() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"type": "question", "session": "s1", "graph_name": "movies", "question": "Who acted in Heat?"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            message,
+            ClientMessage::Question { session, graph_name: Some(graph), model: None, .. }
+                if session == "s1" && graph == "movies"
+        ));
+        assert!(matches!(
+            serde_json::from_str(r#"{"type": "cancel", "session": "s1"}"#).unwrap(),
+            ClientMessage::Cancel { session } if session == "s1"
+        ));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "answer", "session": "s1"}"#).is_err());
+
+        let progress = ServerMessage::Progress {
+            session: "s1".to_string(),
+            event: Progress::CypherQuery("MATCH (n) RETURN n".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(&progress).unwrap(),
+            serde_json::json!({"type": "progress", "session": "s1", "event": {"CypherQuery": "MATCH (n) RETURN n"}})
+        );
+        let error = ServerMessage::Error {
+            session: None,
+            message: "Invalid message".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({"type": "error", "message": "Invalid message"})
+        );
+    }
+}